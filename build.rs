@@ -0,0 +1,35 @@
+//! Captures the git commit and build time for `GET /api/version`.
+
+use std::{
+    env,
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+fn main() {
+    // Builds from a source archive (e.g. in Docker) can pass the hash in
+    let commit = env::var("GIT_COMMIT").ok().filter(|commit| !commit.is_empty()).or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short=12", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|commit| commit.trim().to_string())
+    });
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit.as_deref().unwrap_or("unknown"));
+    
+    let built_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", built_at);
+    
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    // A missing path would rerun this on every build, so only watch what exists
+    for path in [".git/HEAD", ".git/refs", ".git/packed-refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS authorized_people (
+    face_id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    external_image_id TEXT NOT NULL,
+    added_at TEXT NOT NULL,
+    enrolled_via TEXT NOT NULL DEFAULT 'unknown',
+    on_grant_webhook TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_authorized_people_name ON authorized_people (name);
+
+CREATE TABLE IF NOT EXISTS access_logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    action TEXT NOT NULL,
+    person_name TEXT,
+    confidence REAL,
+    access_granted BOOLEAN NOT NULL,
+    attributes TEXT,
+    deny_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_access_logs_timestamp ON access_logs (timestamp);
@@ -0,0 +1 @@
+ALTER TABLE authorized_people ADD COLUMN pin_hash TEXT;
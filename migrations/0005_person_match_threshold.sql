@@ -0,0 +1 @@
+ALTER TABLE authorized_people ADD COLUMN match_threshold REAL;
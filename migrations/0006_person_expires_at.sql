@@ -0,0 +1 @@
+ALTER TABLE authorized_people ADD COLUMN expires_at TEXT;
@@ -0,0 +1,2 @@
+ALTER TABLE authorized_people ADD COLUMN access_count INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE authorized_people ADD COLUMN last_seen TEXT;
@@ -0,0 +1 @@
+ALTER TABLE authorized_people ADD COLUMN notify_email TEXT;
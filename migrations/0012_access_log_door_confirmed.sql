@@ -0,0 +1 @@
+ALTER TABLE access_logs ADD COLUMN door_confirmed BOOLEAN;
@@ -0,0 +1 @@
+ALTER TABLE authorized_people ADD COLUMN role TEXT NOT NULL DEFAULT 'user';
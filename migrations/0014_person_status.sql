@@ -0,0 +1 @@
+ALTER TABLE authorized_people ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
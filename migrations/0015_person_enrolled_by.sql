@@ -0,0 +1 @@
+ALTER TABLE authorized_people ADD COLUMN enrolled_by TEXT;
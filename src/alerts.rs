@@ -0,0 +1,169 @@
+use anyhow::Result;
+use bytes::Bytes;
+use lettre::{
+    message::{header::ContentType, Attachment, Mailbox, MultiPart, SinglePart},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{snapshots, AccessLog};
+
+/// How the SMTP connection is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SmtpTls {
+    /// Plain connection upgraded with STARTTLS (usually port 587).
+    Starttls,
+    /// TLS from the first byte (usually port 465).
+    Tls,
+    /// No encryption; only for a relay on the local network.
+    None,
+}
+
+/// `SMTP_*` / `ALERT_*` settings, present when `EMAIL_ALERTS_ENABLED` is set.
+#[derive(Debug, Clone)]
+pub(crate) struct EmailSettings {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) tls: SmtpTls,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    pub(crate) from: Mailbox,
+    pub(crate) to: Mailbox,
+    pub(crate) cooldown: Duration,
+}
+
+/// Emails `ALERT_EMAIL` about denied attempts, at most once per cooldown,
+/// and people's own notification addresses when they are let in.
+#[derive(Debug)]
+pub(crate) struct EmailAlerter {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Mailbox,
+    cooldown: Duration,
+    last_sent: Mutex<Option<Instant>>,
+}
+
+impl EmailAlerter {
+    pub(crate) fn new(settings: EmailSettings) -> Result<Self> {
+        let mut builder = match settings.tls {
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)?,
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host)?,
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&settings.host),
+        }
+        .port(settings.port);
+        if let (Some(username), Some(password)) = (settings.username, settings.password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+        
+        info!(
+            "📧 Email alerts enabled via {}:{} to {} (at most one per {:?})",
+            settings.host, settings.port, settings.to, settings.cooldown
+        );
+        
+        Ok(Self {
+            transport: builder.build(),
+            from: settings.from,
+            to: settings.to,
+            cooldown: settings.cooldown,
+            last_sent: Mutex::new(None),
+        })
+    }
+    
+    /// Sends the alert in the background so the denial never waits on the
+    /// mail server. `image_data` is attached only when the denial saved a
+    /// snapshot, under the snapshot's id.
+    pub(crate) fn denied(&self, log_entry: &AccessLog, image_data: Option<&Bytes>) {
+        {
+            let mut last_sent = self.last_sent.lock().unwrap();
+            let now = Instant::now();
+            if last_sent.is_some_and(|sent| now.duration_since(sent) < self.cooldown) {
+                return;
+            }
+            *last_sent = Some(now);
+        }
+        
+        let attachment = log_entry.snapshot.clone().zip(image_data.cloned());
+        let message = match self.message(log_entry, attachment) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("⚠️ Failed to build alert email: {}", e);
+                return;
+            }
+        };
+        
+        let transport = self.transport.clone();
+        tokio::spawn(async move {
+            match transport.send(message).await {
+                Ok(_) => info!("📧 Denied-access alert emailed"),
+                Err(e) => warn!("⚠️ Failed to send alert email: {}", e),
+            }
+        });
+    }
+    
+    /// Tells `to` that the person in `log_entry` was let in. Unlike denial
+    /// alerts these aren't rate limited: each grant is worth a mail.
+    pub(crate) fn arrived(&self, to: Mailbox, log_entry: &AccessLog) {
+        let name = log_entry.person_name.clone().unwrap_or_default();
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.clone())
+            .subject(format!("🏠 Smart door: {} arrived", name))
+            .singlepart(SinglePart::plain(format!(
+                "{} was let in at {}.\n",
+                name,
+                log_entry.timestamp.to_rfc3339()
+            )));
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("⚠️ Failed to build arrival email for {}: {}", name, e);
+                return;
+            }
+        };
+        
+        let transport = self.transport.clone();
+        tokio::spawn(async move {
+            match transport.send(message).await {
+                Ok(_) => info!("📧 Arrival of {} emailed to {}", name, to),
+                Err(e) => warn!("⚠️ Failed to email {} about {}: {}", to, name, e),
+            }
+        });
+    }
+    
+    fn message(&self, log_entry: &AccessLog, attachment: Option<(String, Bytes)>) -> Result<Message> {
+        let reason = log_entry
+            .deny_reason
+            .map_or_else(|| "denied".to_string(), |reason| reason.to_string());
+        let mut body = format!(
+            "Access was denied at the door.\n\nTime: {}\nReason: {}\n",
+            log_entry.timestamp.to_rfc3339(),
+            reason
+        );
+        if let Some(name) = &log_entry.person_name {
+            body.push_str(&format!("Matched: {}\n", name));
+        }
+        
+        let builder = Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(format!("🚨 Smart door: access denied ({})", reason));
+        let message = match attachment {
+            Some((id, image_data)) => {
+                let content_type = ContentType::parse(snapshots::content_type(&id))?;
+                builder.multipart(
+                    MultiPart::mixed()
+                        .singlepart(SinglePart::plain(body))
+                        .singlepart(Attachment::new(id).body(image_data.to_vec(), content_type)),
+                )?
+            }
+            None => builder.singlepart(SinglePart::plain(body))?,
+        };
+        
+        Ok(message)
+    }
+}
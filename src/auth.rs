@@ -0,0 +1,166 @@
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use tracing::warn;
+
+use crate::{error::ApiError, tls, AppState};
+
+pub(crate) const API_KEY_HEADER: &str = "x-api-key";
+
+/// Token from `/api/admin/verify`, accepted wherever an API key is except
+/// on the routes behind [`require_api_key_only`].
+pub(crate) const ADMIN_SESSION_HEADER: &str = "x-admin-session";
+
+/// Shared secret a camera presents when pushing frames to `/api/ingest`.
+pub(crate) const DEVICE_TOKEN_HEADER: &str = "x-device-token";
+
+/// Identity of the API key (or admin session) a request was authenticated
+/// with, inserted as a request extension by [`require_api_key`].
+#[derive(Debug, Clone)]
+pub(crate) struct Caller {
+    pub(crate) name: String,
+    /// The key named [`ADMIN_KEY_NAME`] or an admin's face session; the
+    /// only callers that may approve enrollments. Everyone is in open mode.
+    pub(crate) admin: bool,
+}
+
+impl Caller {
+    fn anonymous() -> Self {
+        Caller { name: "anonymous".to_string(), admin: true }
+    }
+}
+
+/// Identity of `API_KEY`; an `API_KEYS` entry may use it too.
+pub(crate) const ADMIN_KEY_NAME: &str = "admin";
+
+/// Rejects requests without a valid `X-API-Key` or `X-Admin-Session`
+/// header. When no API keys are configured every request is let through
+/// as `anonymous`.
+pub(crate) async fn require_api_key(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    match authenticate(&state, request.headers()) {
+        Some(caller) => {
+            request.extensions_mut().insert(caller);
+            next.run(request).await
+        }
+        None => unauthorized(),
+    }
+}
+
+/// Like [`require_api_key`], but an admin session doesn't count. For routes
+/// that hand over the whole system, such as roles, backup and restore,
+/// which a face at the door shouldn't be enough to reach.
+pub(crate) async fn require_api_key_only(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    match authenticate_key(&state, request.headers()) {
+        Some(caller) => {
+            request.extensions_mut().insert(caller);
+            next.run(request).await
+        }
+        None => unauthorized(),
+    }
+}
+
+/// Same as [`require_api_key`], but only enforced when `PROTECT_READS` is set.
+pub(crate) async fn require_api_key_for_reads(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.protect_reads || authenticate(&state, request.headers()).is_some() {
+        next.run(request).await
+    } else {
+        unauthorized()
+    }
+}
+
+/// Rejects requests without the `X-Device-Token` matching `DEVICE_TOKEN`.
+/// Unlike API keys there's no open mode: without a token nothing gets in.
+pub(crate) async fn require_device_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let authorized = match (&state.device_token, request.headers().get(DEVICE_TOKEN_HEADER)) {
+        (Some(expected), Some(provided)) => constant_time_eq(provided.as_bytes(), expected.as_bytes()),
+        _ => false,
+    };
+    
+    if authorized {
+        next.run(request).await
+    } else {
+        unauthorized()
+    }
+}
+
+/// With `TLS_CLIENT_CA_PATH` set, device routes also need the connection
+/// to have presented a client certificate signed by that CA.
+pub(crate) async fn require_client_cert(request: Request, next: Next) -> Response {
+    let verified = request
+        .extensions()
+        .get::<tls::ClientCert>()
+        .is_some_and(|cert| cert.verified);
+    
+    if verified {
+        next.run(request).await
+    } else {
+        warn!("🔏 Device request to {} without a client certificate", request.uri().path());
+        ApiError::Forbidden("A client certificate signed by TLS_CLIENT_CA_PATH is required".to_string()).into_response()
+    }
+}
+
+fn authenticate(state: &AppState, headers: &HeaderMap) -> Option<Caller> {
+    if state.api_keys.is_empty() {
+        return Some(Caller::anonymous());
+    }
+    
+    if let Some(token) = headers.get(ADMIN_SESSION_HEADER) {
+        let name = state.admin_session(token.to_str().ok()?)?;
+        return Some(Caller { name: format!("{} (face)", name), admin: true });
+    }
+    
+    authenticate_key(state, headers)
+}
+
+fn authenticate_key(state: &AppState, headers: &HeaderMap) -> Option<Caller> {
+    if state.api_keys.is_empty() {
+        return Some(Caller::anonymous());
+    }
+    
+    caller_for_key(state, headers.get(API_KEY_HEADER)?.to_str().ok()?)
+}
+
+/// Checks a credential sent in a `/ws` client's first message, since
+/// browsers can't set headers on a WebSocket: an API key or an admin
+/// session token.
+pub(crate) fn authenticate_token(state: &AppState, token: &str) -> Option<Caller> {
+    if state.api_keys.is_empty() {
+        return Some(Caller::anonymous());
+    }
+    
+    if let Some(name) = state.admin_session(token) {
+        return Some(Caller { name: format!("{} (face)", name), admin: true });
+    }
+    
+    caller_for_key(state, token)
+}
+
+fn caller_for_key(state: &AppState, provided: &str) -> Option<Caller> {
+    state
+        .api_keys
+        .iter()
+        .find(|(_, key)| constant_time_eq(provided.as_bytes(), key.as_bytes()))
+        .map(|(name, _)| Caller { name: name.clone(), admin: name == ADMIN_KEY_NAME })
+}
+
+/// Compares two byte strings without short-circuiting on the first
+/// mismatching byte, so response timing doesn't reveal the key prefix.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn unauthorized() -> Response {
+    ApiError::Unauthorized.into_response()
+}
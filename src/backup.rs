@@ -0,0 +1,238 @@
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use tracing::{info, warn};
+
+use crate::{error::ApiError, AccessWindow, AppState, AuthorizedPerson, EnrollmentSource, PersonRole, PersonStatus};
+
+/// Bumped whenever the document changes shape. A server refuses backups
+/// from a newer version rather than half-reading them.
+pub(crate) const SCHEMA_VERSION: u32 = 1;
+
+/// The local registry as `GET /api/backup` exports it, one entry per
+/// person. Rekognition doesn't export face vectors, so only face ids are
+/// kept: they restore as-is into the same collection, but a new collection
+/// needs photos to re-enroll from.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Backup {
+    schema_version: u32,
+    created_at: DateTime<Utc>,
+    people: Vec<BackupPerson>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupPerson {
+    name: String,
+    #[serde(default)]
+    faces: Vec<BackupFace>,
+    #[serde(default)]
+    on_grant_webhook: Option<String>,
+    #[serde(default)]
+    notify_email: Option<String>,
+    #[serde(default)]
+    schedule: Option<Vec<AccessWindow>>,
+    /// Argon2 hash, so the PIN survives a restore without being readable.
+    #[serde(default)]
+    pin_hash: Option<String>,
+    #[serde(default)]
+    match_threshold: Option<f32>,
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    access_count: u64,
+    #[serde(default)]
+    last_seen: Option<DateTime<Utc>>,
+    #[serde(default)]
+    role: PersonRole,
+    /// Base64-encoded photos to re-enroll from when none of `faces` is in
+    /// the collection any more. Never part of an export; add them by hand.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    photos: Vec<String>,
+}
+
+/// A face indexed in the collection the backup was taken from.
+#[derive(Debug, Serialize, Deserialize)]
+struct BackupFace {
+    face_id: String,
+    external_image_id: String,
+    added_at: DateTime<Utc>,
+    #[serde(default)]
+    enrolled_via: EnrollmentSource,
+    #[serde(default)]
+    status: PersonStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    enrolled_by: Option<String>,
+}
+
+impl BackupPerson {
+    fn record(&self, face: &BackupFace) -> AuthorizedPerson {
+        let mut record = AuthorizedPerson::new(&self.name, face.face_id.clone(), face.enrolled_via);
+        record.external_image_id = face.external_image_id.clone();
+        record.added_at = face.added_at;
+        // Only faces still in the collection get here, and only the admin
+        // key can restore, so the recorded status is trusted
+        record.status = face.status;
+        record.enrolled_by = face.enrolled_by.clone();
+        self.apply_settings(&mut record);
+        record
+    }
+    
+    fn apply_settings(&self, record: &mut AuthorizedPerson) {
+        record.on_grant_webhook = self.on_grant_webhook.clone();
+        record.notify_email = self.notify_email.clone();
+        record.schedule = self.schedule.clone();
+        record.pin_hash = self.pin_hash.clone();
+        record.match_threshold = self.match_threshold;
+        record.expires_at = self.expires_at;
+        record.access_count = self.access_count;
+        record.last_seen = self.last_seen;
+        record.role = self.role;
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct RestoreSummary {
+    /// Their faces were still in the collection, so only the records were
+    /// restored.
+    restored: Vec<String>,
+    /// Re-enrolled from the photos in the backup.
+    reenrolled: Vec<String>,
+    /// No face left in the collection and no usable photos; enroll these
+    /// again by hand.
+    needs_enrollment: Vec<String>,
+}
+
+pub(crate) fn export(state: &AppState) -> Backup {
+    let mut by_name: BTreeMap<String, Vec<AuthorizedPerson>> = BTreeMap::new();
+    for person in state.authorized_people.read().unwrap().values() {
+        by_name.entry(person.name.clone()).or_default().push(person.clone());
+    }
+    
+    let people = by_name
+        .into_iter()
+        .map(|(name, mut records)| {
+            records.sort_by_key(|record| record.added_at);
+            // Per-person settings are the same on every face record
+            let settings = records[0].clone();
+            BackupPerson {
+                name,
+                faces: records
+                    .into_iter()
+                    .map(|record| BackupFace {
+                        face_id: record.face_id,
+                        external_image_id: record.external_image_id,
+                        added_at: record.added_at,
+                        enrolled_via: record.enrolled_via,
+                        status: record.status,
+                        enrolled_by: record.enrolled_by,
+                    })
+                    .collect(),
+                on_grant_webhook: settings.on_grant_webhook,
+                notify_email: settings.notify_email,
+                schedule: settings.schedule,
+                pin_hash: settings.pin_hash,
+                match_threshold: settings.match_threshold,
+                expires_at: settings.expires_at,
+                access_count: settings.access_count,
+                last_seen: settings.last_seen,
+                role: settings.role,
+                photos: Vec::new(),
+            }
+        })
+        .collect();
+    
+    Backup {
+        schema_version: SCHEMA_VERSION,
+        created_at: Utc::now(),
+        people,
+    }
+}
+
+/// Restores each person in `backup`: from their face ids when any is
+/// still in the collection, otherwise by re-enrolling from their photos.
+/// Records already here for the same faces are overwritten.
+/// Photos re-enrolled here count as enrolled by `caller`.
+pub(crate) async fn restore(state: &AppState, backup: Backup, caller: &str) -> Result<RestoreSummary> {
+    if backup.schema_version > SCHEMA_VERSION {
+        return Err(ApiError::BadRequest(format!(
+            "backup schema version {} is newer than this server supports ({})",
+            backup.schema_version, SCHEMA_VERSION
+        ))
+        .into());
+    }
+    
+    let collection: HashSet<String> = state
+        .face_recognizer
+        .list_faces()
+        .await?
+        .into_iter()
+        .map(|face| face.face_id)
+        .collect();
+    
+    let mut summary = RestoreSummary::default();
+    for person in backup.people {
+        let records: Vec<AuthorizedPerson> = person
+            .faces
+            .iter()
+            .filter(|face| collection.contains(&face.face_id))
+            .map(|face| person.record(face))
+            .collect();
+        
+        if !records.is_empty() {
+            for record in &records {
+                state.store.upsert_person(record).await?;
+            }
+            {
+                let mut people = state.authorized_people.write().unwrap();
+                for record in records {
+                    people.insert(record.face_id.clone(), record);
+                }
+            }
+            summary.restored.push(person.name);
+            continue;
+        }
+        
+        if person.photos.is_empty() {
+            summary.needs_enrollment.push(person.name);
+            continue;
+        }
+        
+        let photos = person
+            .photos
+            .iter()
+            .enumerate()
+            .map(|(index, photo)| {
+                STANDARD.decode(photo).map(Bytes::from).map_err(|_| {
+                    ApiError::BadRequest(format!("photo {} of {} is not valid base64", index + 1, person.name))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match state
+            .add_person(person.name.clone(), photos, EnrollmentSource::Restore, caller, person.expires_at)
+            .await
+        {
+            Ok(_) => {
+                state
+                    .update_person(&person.name, |record| person.apply_settings(record))
+                    .await?;
+                summary.reenrolled.push(person.name);
+            }
+            Err(e) => {
+                warn!("⚠️ Could not re-enroll {} from the backup: {}", person.name, e);
+                summary.needs_enrollment.push(person.name);
+            }
+        }
+    }
+    state.update_enrollment_gauge();
+    
+    info!(
+        "♻️ Restored backup: {} restored, {} re-enrolled, {} need enrollment",
+        summary.restored.len(),
+        summary.reenrolled.len(),
+        summary.needs_enrollment.len()
+    );
+    Ok(summary)
+}
@@ -0,0 +1,83 @@
+use bytes::Bytes;
+use std::{
+    collections::BTreeMap,
+    io::{Cursor, Read},
+};
+use zip::ZipArchive;
+
+use crate::error::ApiError;
+
+/// Most files accepted from one archive.
+pub(crate) const MAX_ENTRIES: usize = 100;
+
+/// Largest uncompressed photo read from an archive, matching the request
+/// body limit so a small archive can't inflate into a huge one.
+const MAX_ENTRY_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Photos found in an enrollment archive, grouped by person name, plus the
+/// names whose files could not be read and why.
+#[derive(Debug, Default)]
+pub(crate) struct Archive {
+    pub(crate) photos: BTreeMap<String, Vec<Bytes>>,
+    pub(crate) rejected: BTreeMap<String, String>,
+}
+
+/// Reads a zip of `PersonName.jpg`-style files; the file stem is the
+/// person's name, so `Alice.jpg` and `photos/Alice.png` both enroll Alice.
+/// Directories and hidden or macOS metadata files are skipped. Image
+/// validation happens later, per person, like a normal enrollment.
+pub(crate) fn read_archive(data: &[u8]) -> Result<Archive, ApiError> {
+    let mut zip = ZipArchive::new(Cursor::new(data))
+        .map_err(|e| ApiError::BadRequest(format!("Invalid zip archive: {}", e)))?;
+    if zip.len() > MAX_ENTRIES {
+        return Err(ApiError::BadRequest(format!(
+            "Archive has {} entries - at most {} are accepted",
+            zip.len(),
+            MAX_ENTRIES
+        )));
+    }
+    
+    let mut archive = Archive::default();
+    for index in 0..zip.len() {
+        let mut file = zip
+            .by_index(index)
+            .map_err(|e| ApiError::BadRequest(format!("Invalid zip archive: {}", e)))?;
+        if file.is_dir() {
+            continue;
+        }
+        
+        let Some(path) = file.enclosed_name() else {
+            continue;
+        };
+        if path.components().any(|part| part.as_os_str().to_string_lossy().starts_with(['.', '_'])) {
+            continue;
+        }
+        let Some(name) = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().trim().to_string())
+            .filter(|name| !name.is_empty())
+        else {
+            continue;
+        };
+        
+        if file.size() > MAX_ENTRY_BYTES {
+            archive.rejected.insert(name, "file_too_large".to_string());
+            continue;
+        }
+        let mut photo = Vec::with_capacity(file.size() as usize);
+        // The declared size can lie; never read past the cap
+        match (&mut file).take(MAX_ENTRY_BYTES + 1).read_to_end(&mut photo) {
+            Ok(_) if photo.len() as u64 <= MAX_ENTRY_BYTES => {
+                archive.photos.entry(name).or_default().push(Bytes::from(photo));
+            }
+            Ok(_) => {
+                archive.rejected.insert(name, "file_too_large".to_string());
+            }
+            Err(_) => {
+                archive.rejected.insert(name, "unreadable".to_string());
+            }
+        }
+    }
+    
+    Ok(archive)
+}
@@ -0,0 +1,71 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::AppState;
+
+tokio::task_local! {
+    static CLIENT_IP: IpAddr;
+}
+
+/// The address of the client whose request is being handled; `None` in
+/// background tasks, like [`crate::request_id::current`].
+pub(crate) fn current() -> Option<IpAddr> {
+    CLIENT_IP.try_with(|ip| *ip).ok()
+}
+
+/// Runs `work` with `ip` as the current client address, for work moved
+/// onto another task.
+pub(crate) async fn with<F: std::future::Future>(ip: Option<IpAddr>, work: F) -> F::Output {
+    match ip {
+        Some(ip) => CLIENT_IP.scope(ip, work).await,
+        None => work.await,
+    }
+}
+
+/// The address a reverse proxy reported in header `name`. For a list like
+/// `X-Forwarded-For` that is the last entry, the one our proxy appended;
+/// anything before it came from the client and can't be trusted.
+pub(crate) fn header_ip(headers: &HeaderMap, name: &str) -> Option<IpAddr> {
+    headers
+        .get(name)?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse::<IpAddr>()
+        .ok()
+}
+
+/// The TCP peer, unless `trust_proxy` is set: then the last
+/// `X-Forwarded-For` entry, or `X-Real-IP`, as the reverse proxy reported
+/// them. Those headers are never read otherwise, since any client can send
+/// them when the server is exposed directly.
+pub(crate) fn resolve(headers: &HeaderMap, peer: Option<IpAddr>, trust_proxy: bool) -> Option<IpAddr> {
+    if !trust_proxy {
+        return peer;
+    }
+    
+    header_ip(headers, "x-forwarded-for")
+        .or_else(|| header_ip(headers, "x-real-ip"))
+        .or(peer)
+}
+
+/// Makes the client address available to [`current`] for the rest of the
+/// request.
+pub(crate) async fn scope(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    
+    match resolve(request.headers(), peer, state.trust_proxy) {
+        Some(ip) => CLIENT_IP.scope(ip, next.run(request)).await,
+        None => next.run(request).await,
+    }
+}
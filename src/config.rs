@@ -0,0 +1,689 @@
+use anyhow::Result;
+use aws_sdk_rekognition::types::QualityFilter;
+use axum::http::HeaderValue;
+use chrono_tz::Tz;
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Display,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    alerts::{EmailSettings, SmtpTls},
+    auth::ADMIN_KEY_NAME,
+    devices::CaptureMode,
+    mqtt::MqttSettings,
+    ratelimit::ClientKey,
+    tls::TlsSettings,
+    AuthMode, AwsOutagePolicy, CameraConfig, DenyReason, LivenessChallenge, MultiFacePolicy, WebhookEvents,
+    SERVER_PORT,
+};
+
+/// Everything the server reads from the environment, validated up front so
+/// one run reports every bad variable instead of the first.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    pub(crate) dry_run: bool,
+    pub(crate) aws: AwsSettings,
+    pub(crate) collection: CollectionSettings,
+    pub(crate) database_url: String,
+    /// Never empty, without duplicate ids; the first is the default camera.
+    pub(crate) cameras: Vec<CameraConfig>,
+    pub(crate) confidence_threshold: f32,
+    /// How far the best match must lead anyone else's; 0 turns it off.
+    pub(crate) match_margin: f32,
+    pub(crate) timezone: Tz,
+    pub(crate) door_control_enabled: bool,
+    pub(crate) capture_attributes: bool,
+    pub(crate) liveness_challenge: bool,
+    pub(crate) liveness_challenge_type: LivenessChallenge,
+    pub(crate) liveness_session_seconds: i64,
+    pub(crate) liveness_min_yaw_delta: f32,
+    pub(crate) liveness_enabled: bool,
+    pub(crate) liveness_min_sharpness: f32,
+    pub(crate) liveness_max_pose_degrees: f32,
+    pub(crate) snapshots_enabled: bool,
+    pub(crate) snapshot_dir: PathBuf,
+    pub(crate) snapshot_retention_days: u64,
+    /// Access log entries kept, in memory and in the database.
+    pub(crate) log_retention_max_entries: usize,
+    /// Entries older than this many days are dropped; 0 keeps any age.
+    pub(crate) log_retention_days: u64,
+    /// Behind a reverse proxy: take the client address from its headers.
+    pub(crate) trust_proxy: bool,
+    /// Live events held for `/ws` and `/api/events` clients that fall behind.
+    pub(crate) event_buffer: usize,
+    /// Serve `/api/camera/preview`, at no more than `preview_max_fps`.
+    pub(crate) preview_enabled: bool,
+    pub(crate) preview_max_fps: u32,
+    /// Check for a face locally before calling Rekognition.
+    pub(crate) local_prefilter: bool,
+    pub(crate) local_prefilter_model: PathBuf,
+    pub(crate) max_failed_attempts: usize,
+    pub(crate) fail_window_seconds: u64,
+    pub(crate) lockout_seconds: u64,
+    pub(crate) polling_enabled: bool,
+    pub(crate) poll_interval_ms: u64,
+    pub(crate) auth_mode: AuthMode,
+    pub(crate) multi_face_policy: MultiFacePolicy,
+    pub(crate) aws_outage_policy: AwsOutagePolicy,
+    pub(crate) webhook_url: Option<String>,
+    pub(crate) webhook_secret: Option<String>,
+    pub(crate) webhook_events: WebhookEvents,
+    pub(crate) grant_debounce_seconds: u64,
+    pub(crate) lock_on_shutdown: bool,
+    pub(crate) door_unlock_seconds: u64,
+    pub(crate) door_debounce_ms: u64,
+    /// Poll the door after an unlock until it reports itself open.
+    pub(crate) door_confirm: bool,
+    pub(crate) door_confirm_timeout_ms: u64,
+    /// How far a device's clock may be off from ours before its timestamps
+    /// are refused.
+    pub(crate) clock_skew_tolerance_seconds: i64,
+    pub(crate) http_timeout_seconds: u64,
+    pub(crate) http_max_retries: u32,
+    pub(crate) http_base_delay_ms: u64,
+    /// Request body limits: the default, access checks, and bulk enrollment.
+    pub(crate) max_upload_mb: usize,
+    pub(crate) max_check_upload_mb: usize,
+    pub(crate) max_bulk_upload_mb: usize,
+    pub(crate) protect_reads: bool,
+    /// Photos whose brightness or sharpness (0-100) is below this are not enrolled.
+    pub(crate) enroll_min_quality: Option<f32>,
+    /// New faces can't open the door until an admin approves them.
+    pub(crate) enroll_requires_approval: bool,
+    pub(crate) device_token: Option<String>,
+    pub(crate) email: Option<EmailSettings>,
+    /// Appended to every capture URL so the firmware can switch resolution.
+    pub(crate) esp32_capture_params: Option<String>,
+    /// Whether capture URLs serve single snapshots or an MJPEG stream.
+    pub(crate) esp32_capture_mode: CaptureMode,
+    /// Applied when indexing and searching faces.
+    pub(crate) quality_filter: QualityFilter,
+    /// Photo of `selftest_person` used by `/api/selftest`.
+    pub(crate) selftest_image: Option<PathBuf>,
+    pub(crate) selftest_person: String,
+    /// Browser origins allowed to call the API with credentials; `None`
+    /// allows any origin.
+    pub(crate) cors_allowed_origins: Option<Vec<HeaderValue>>,
+    /// Replacements for the built-in human-readable deny messages.
+    pub(crate) deny_messages: HashMap<DenyReason, String>,
+    /// `(identity, key)` pairs; empty leaves the API open.
+    pub(crate) api_keys: Vec<(String, String)>,
+    /// Requests per minute per client; 0 turns rate limiting off.
+    pub(crate) rate_limit_per_min: u32,
+    pub(crate) rate_limit_key: ClientKey,
+    pub(crate) mqtt: Option<MqttSettings>,
+    pub(crate) tls: Option<TlsSettings>,
+    pub(crate) port: u16,
+    /// Plain-HTTP port redirecting to HTTPS, when TLS is on.
+    pub(crate) http_redirect_port: Option<u16>,
+    /// Serves `/metrics` and `/health` on their own, off `port`.
+    pub(crate) metrics_port: Option<u16>,
+}
+
+/// Where Rekognition credentials come from; unused when `DRY_RUN` is set.
+/// A named profile wins over explicit keys.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AwsSettings {
+    pub(crate) profile: Option<String>,
+    pub(crate) access_key_id: Option<String>,
+    pub(crate) secret_access_key: Option<String>,
+    /// Only meaningful with explicit keys (temporary STS credentials).
+    pub(crate) session_token: Option<String>,
+    /// Falls back to the profile's region when unset.
+    pub(crate) region: Option<String>,
+    /// Overrides the Rekognition endpoint, e.g. LocalStack.
+    pub(crate) endpoint_url: Option<String>,
+}
+
+/// The Rekognition collection faces are indexed into.
+#[derive(Debug, Clone)]
+pub(crate) struct CollectionSettings {
+    pub(crate) id: String,
+    /// Applied only when the collection is created.
+    pub(crate) tags: HashMap<String, String>,
+    /// Off, a missing collection stops startup instead of being created
+    /// empty, which would deny everyone.
+    pub(crate) auto_create: bool,
+}
+
+impl Config {
+    pub(crate) fn from_env() -> Result<Self> {
+        let mut reader = EnvReader::default();
+        
+        let dry_run = reader.parse("DRY_RUN", false);
+        let aws = if dry_run {
+            AwsSettings::default()
+        } else {
+            reader.aws()
+        };
+        
+        let cameras = reader.cameras();
+        let timezone = match env::var("ACCESS_TIMEZONE").or_else(|_| env::var("TZ")) {
+            Ok(tz) => tz.parse::<Tz>().unwrap_or_else(|e| {
+                reader.problem(format!("ACCESS_TIMEZONE/TZ: '{}' is not a timezone ({})", tz, e));
+                Tz::UTC
+            }),
+            Err(_) => Tz::UTC,
+        };
+        let webhook_url = reader.optional("WEBHOOK_URL");
+        if let Some(url) = &webhook_url {
+            reader.check_url("WEBHOOK_URL", url);
+        }
+        
+        let email = if reader.parse("EMAIL_ALERTS_ENABLED", false) {
+            Some(reader.email())
+        } else {
+            None
+        };
+        
+        let enroll_min_quality = reader
+            .optional("ENROLL_MIN_QUALITY")
+            .map(|_| reader.in_range("ENROLL_MIN_QUALITY", 0.0, 0.0, 100.0));
+        
+        let mqtt = if reader.parse("MQTT_ENABLED", false) {
+            Some(MqttSettings {
+                broker_url: reader
+                    .optional("MQTT_BROKER_URL")
+                    .unwrap_or_else(|| "mqtt://localhost:1883".to_string()),
+                topic_prefix: reader
+                    .optional("MQTT_TOPIC_PREFIX")
+                    .unwrap_or_else(|| "smart-door".to_string()),
+            })
+        } else {
+            None
+        };
+        // Half a TLS setup must not quietly serve plain HTTP
+        let tls = match (
+            reader.optional("TLS_CERT_PATH"),
+            reader.optional("TLS_KEY_PATH"),
+            reader.optional("TLS_CLIENT_CA_PATH"),
+        ) {
+            (Some(cert_path), Some(key_path), client_ca_path) => Some(TlsSettings {
+                cert_path,
+                key_path,
+                client_ca_path,
+            }),
+            (None, None, None) => None,
+            (None, None, Some(_)) => {
+                reader.problem("TLS_CLIENT_CA_PATH: needs TLS_CERT_PATH and TLS_KEY_PATH".to_string());
+                None
+            }
+            _ => {
+                reader.problem("TLS_CERT_PATH, TLS_KEY_PATH: must be set together".to_string());
+                None
+            }
+        };
+        let http_redirect_port = reader
+            .optional("HTTP_REDIRECT_PORT")
+            .map(|_| reader.at_least("HTTP_REDIRECT_PORT", 80, 1));
+        let port = reader.at_least("PORT", SERVER_PORT, 1);
+        let metrics_port = reader
+            .optional("METRICS_PORT")
+            .map(|_| reader.at_least("METRICS_PORT", 9090, 1));
+        if metrics_port.is_some_and(|metrics_port| metrics_port == port || Some(metrics_port) == http_redirect_port) {
+            reader.problem("METRICS_PORT: must differ from PORT and HTTP_REDIRECT_PORT".to_string());
+        }
+        
+        let config = Config {
+            dry_run,
+            aws,
+            collection: CollectionSettings {
+                id: env::var("COLLECTION_ID").unwrap_or_else(|_| "smart-door-faces".to_string()),
+                tags: reader.collection_tags(),
+                auto_create: reader.parse("COLLECTION_AUTO_CREATE", true),
+            },
+            database_url: env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite://smart_door.db".to_string()),
+            cameras,
+            confidence_threshold: reader.in_range("CONFIDENCE_THRESHOLD", 75.0, 0.0, 100.0),
+            match_margin: reader.in_range("MATCH_MARGIN", 0.0, 0.0, 100.0),
+            timezone,
+            door_control_enabled: reader.parse("DOOR_CONTROL_ENABLED", true),
+            capture_attributes: reader.parse("CAPTURE_ATTRIBUTES", false),
+            liveness_challenge: reader.parse("LIVENESS_CHALLENGE", false),
+            liveness_challenge_type: reader.choice(
+                "LIVENESS_CHALLENGE_TYPE",
+                LivenessChallenge::Blink,
+                &[("blink", LivenessChallenge::Blink), ("turn", LivenessChallenge::Turn)],
+            ),
+            liveness_session_seconds: reader.at_least("LIVENESS_SESSION_SECONDS", 30, 1),
+            liveness_min_yaw_delta: reader.in_range("LIVENESS_MIN_YAW_DELTA", 20.0, 0.0, 180.0),
+            liveness_enabled: reader.parse("ENABLE_LIVENESS", false),
+            liveness_min_sharpness: reader.in_range("LIVENESS_MIN_SHARPNESS", 20.0, 0.0, 100.0),
+            liveness_max_pose_degrees: reader.in_range("LIVENESS_MAX_POSE_DEGREES", 45.0, 0.0, 180.0),
+            snapshots_enabled: reader.parse("SAVE_DENIED_SNAPSHOTS", false),
+            snapshot_dir: PathBuf::from(env::var("SNAPSHOT_DIR").unwrap_or_else(|_| "snapshots".to_string())),
+            snapshot_retention_days: reader.parse("SNAPSHOT_RETENTION_DAYS", 30),
+            log_retention_max_entries: reader.at_least("LOG_RETENTION_MAX_ENTRIES", 10_000, 1),
+            log_retention_days: reader.parse("LOG_RETENTION_DAYS", 0),
+            trust_proxy: reader.parse("TRUST_PROXY", false),
+            event_buffer: reader.at_least("EVENT_BUFFER", 100, 1),
+            preview_enabled: reader.parse("PREVIEW_ENABLED", false),
+            preview_max_fps: reader.in_range("PREVIEW_MAX_FPS", 5, 1, 30),
+            local_prefilter: reader.parse("LOCAL_PREFILTER", false),
+            local_prefilter_model: PathBuf::from(
+                env::var("LOCAL_PREFILTER_MODEL").unwrap_or_else(|_| "models/seeta_fd_frontal_v1.0.bin".to_string()),
+            ),
+            max_failed_attempts: reader.at_least("MAX_FAILED_ATTEMPTS", 5, 1),
+            fail_window_seconds: reader.at_least("FAIL_WINDOW_SECONDS", 60, 1),
+            lockout_seconds: reader.at_least("LOCKOUT_SECONDS", 300, 1),
+            polling_enabled: reader.parse("POLL_ENABLED", false),
+            poll_interval_ms: reader.at_least("POLL_INTERVAL_MS", 2000, 1),
+            auth_mode: reader.choice(
+                "AUTH_MODE",
+                AuthMode::Face,
+                &[("face", AuthMode::Face), ("face_pin", AuthMode::FacePin)],
+            ),
+            multi_face_policy: reader.choice(
+                "MULTI_FACE_POLICY",
+                MultiFacePolicy::First,
+                &[
+                    ("first", MultiFacePolicy::First),
+                    ("all", MultiFacePolicy::All),
+                    ("deny", MultiFacePolicy::Deny),
+                ],
+            ),
+            aws_outage_policy: reader.choice(
+                "AWS_OUTAGE_POLICY",
+                AwsOutagePolicy::Deny,
+                &[
+                    ("deny", AwsOutagePolicy::Deny),
+                    ("allow_pin", AwsOutagePolicy::AllowPin),
+                    ("manual_only", AwsOutagePolicy::ManualOnly),
+                ],
+            ),
+            webhook_url,
+            webhook_secret: reader.optional("WEBHOOK_SECRET"),
+            webhook_events: reader.choice(
+                "WEBHOOK_EVENTS",
+                WebhookEvents::All,
+                &[
+                    ("all", WebhookEvents::All),
+                    ("grants", WebhookEvents::Grants),
+                    ("denies", WebhookEvents::Denies),
+                ],
+            ),
+            grant_debounce_seconds: reader.parse("GRANT_DEBOUNCE_SECONDS", 10),
+            lock_on_shutdown: reader.parse("LOCK_ON_SHUTDOWN", true),
+            door_unlock_seconds: reader.at_least("DOOR_UNLOCK_SECONDS", 5, 1),
+            door_debounce_ms: reader.parse("DOOR_DEBOUNCE_MS", 500),
+            door_confirm: reader.parse("DOOR_CONFIRM", false),
+            door_confirm_timeout_ms: reader.at_least("DOOR_CONFIRM_TIMEOUT_MS", 5000, 1),
+            clock_skew_tolerance_seconds: reader.in_range("CLOCK_SKEW_TOLERANCE_SECONDS", 30, 0, 3600),
+            http_timeout_seconds: reader.at_least("HTTP_TIMEOUT_SECONDS", 10, 1),
+            http_max_retries: reader.parse("HTTP_MAX_RETRIES", 2),
+            http_base_delay_ms: reader.parse("HTTP_BASE_DELAY_MS", 200),
+            max_upload_mb: reader.at_least("MAX_UPLOAD_MB", 10, 1),
+            max_check_upload_mb: reader.at_least("MAX_CHECK_UPLOAD_MB", 8, 1),
+            max_bulk_upload_mb: reader.at_least("MAX_BULK_UPLOAD_MB", 100, 1),
+            protect_reads: reader.parse("PROTECT_READS", false),
+            device_token: reader.optional("DEVICE_TOKEN"),
+            enroll_min_quality,
+            enroll_requires_approval: reader.parse("ENROLL_REQUIRES_APPROVAL", false),
+            email,
+            esp32_capture_params: reader.optional("ESP32_CAPTURE_PARAMS"),
+            esp32_capture_mode: reader.choice(
+                "ESP32_CAPTURE_MODE",
+                CaptureMode::Snapshot,
+                &[("snapshot", CaptureMode::Snapshot), ("mjpeg", CaptureMode::Mjpeg)],
+            ),
+            quality_filter: reader.quality_filter(),
+            selftest_image: reader.optional("SELFTEST_IMAGE").map(PathBuf::from),
+            selftest_person: reader.optional("SELFTEST_PERSON").unwrap_or_else(|| "test".to_string()),
+            cors_allowed_origins: reader.cors_origins(),
+            deny_messages: reader.deny_messages(),
+            api_keys: reader.api_keys(),
+            rate_limit_per_min: reader.parse("RATE_LIMIT_PER_MIN", 30),
+            rate_limit_key: reader.choice(
+                "RATE_LIMIT_KEY",
+                ClientKey::Peer,
+                &[
+                    ("peer", ClientKey::Peer),
+                    ("x-forwarded-for", ClientKey::ForwardedFor),
+                    ("x-real-ip", ClientKey::RealIp),
+                ],
+            ),
+            mqtt,
+            tls,
+            port,
+            http_redirect_port,
+            metrics_port,
+        };
+        
+        reader.finish()?;
+        Ok(config)
+    }
+}
+
+/// Reads variables, falling back to defaults for unset ones and collecting
+/// a problem for every set-but-invalid one.
+#[derive(Debug, Default)]
+struct EnvReader {
+    problems: Vec<String>,
+}
+
+impl EnvReader {
+    fn problem(&mut self, problem: String) {
+        self.problems.push(problem);
+    }
+    
+    /// Unset and empty are the same thing.
+    fn optional(&self, name: &str) -> Option<String> {
+        env::var(name).ok().filter(|value| !value.trim().is_empty())
+    }
+    
+    fn required(&mut self, name: &str) -> String {
+        self.optional(name).unwrap_or_else(|| {
+            self.problem(format!("{}: required but not set", name));
+            String::new()
+        })
+    }
+    
+    fn parse<T>(&mut self, name: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let Some(value) = self.optional(name) else {
+            return default;
+        };
+        
+        match value.trim().parse::<T>() {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                self.problem(format!("{}: '{}' is invalid ({})", name, value, e));
+                default
+            }
+        }
+    }
+    
+    fn in_range<T>(&mut self, name: &str, default: T, min: T, max: T) -> T
+    where
+        T: FromStr + PartialOrd + Display + Copy,
+        T::Err: Display,
+    {
+        let value = self.parse(name, default);
+        if value < min || value > max {
+            self.problem(format!("{}: {} is out of range ({}-{})", name, value, min, max));
+            return default;
+        }
+        value
+    }
+    
+    fn at_least<T>(&mut self, name: &str, default: T, min: T) -> T
+    where
+        T: FromStr + PartialOrd + Display + Copy,
+        T::Err: Display,
+    {
+        let value = self.parse(name, default);
+        if value < min {
+            self.problem(format!("{}: {} is below the minimum of {}", name, value, min));
+            return default;
+        }
+        value
+    }
+    
+    fn choice<T: Copy>(&mut self, name: &str, default: T, choices: &[(&str, T)]) -> T {
+        let Some(value) = self.optional(name) else {
+            return default;
+        };
+        
+        let wanted = value.trim().to_lowercase();
+        match choices.iter().find(|(choice, _)| *choice == wanted) {
+            Some((_, parsed)) => *parsed,
+            None => {
+                let allowed: Vec<&str> = choices.iter().map(|(choice, _)| *choice).collect();
+                self.problem(format!("{}: '{}' is not one of {}", name, value, allowed.join(", ")));
+                default
+            }
+        }
+    }
+    
+    fn check_url(&mut self, name: &str, url: &str) {
+        match reqwest::Url::parse(url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => {}
+            Ok(parsed) => self.problem(format!("{}: '{}' must be http or https, not {}", name, url, parsed.scheme())),
+            Err(e) => self.problem(format!("{}: '{}' is not a valid URL ({})", name, url, e)),
+        }
+    }
+    
+    /// Not `choice`, since `QualityFilter` isn't `Copy` and a bad value
+    /// deserves an explanation of what the levels trade off.
+    fn quality_filter(&mut self) -> QualityFilter {
+        let Some(value) = self.optional("QUALITY_FILTER") else {
+            return QualityFilter::Auto;
+        };
+        
+        match value.trim().to_uppercase().as_str() {
+            "NONE" => QualityFilter::None,
+            "AUTO" => QualityFilter::Auto,
+            "LOW" => QualityFilter::Low,
+            "MEDIUM" => QualityFilter::Medium,
+            "HIGH" => QualityFilter::High,
+            _ => {
+                self.problem(format!(
+                    "QUALITY_FILTER: '{}' is not one of NONE, AUTO, LOW, MEDIUM, HIGH. Higher levels reject \
+                     blurry, dark or small faces, so enrollments are cleaner but more photos are refused; \
+                     lower levels help in bad lighting at the cost of weaker matches",
+                    value
+                ));
+                QualityFilter::Auto
+            }
+        }
+    }
+    
+    /// CORS_ALLOWED_ORIGINS is comma-separated, e.g.
+    /// `https://door.example.com,http://localhost:5173`.
+    fn cors_origins(&mut self) -> Option<Vec<HeaderValue>> {
+        let value = self.optional("CORS_ALLOWED_ORIGINS")?;
+        let mut origins = Vec::new();
+        for origin in value.split(',').map(str::trim).filter(|origin| !origin.is_empty()) {
+            if origin == "*" {
+                // Browsers refuse credentials with a wildcard origin
+                self.problem("CORS_ALLOWED_ORIGINS: '*' is not allowed; leave it unset to allow any origin".to_string());
+                continue;
+            }
+            match HeaderValue::from_str(origin) {
+                Ok(header) => origins.push(header),
+                Err(e) => self.problem(format!("CORS_ALLOWED_ORIGINS: '{}' is not a valid origin ({})", origin, e)),
+            }
+        }
+        Some(origins)
+    }
+    
+    /// DENY_MESSAGES is a JSON object from deny reason codes to the text
+    /// shown instead of the built-in message, e.g.
+    /// `{"outside_schedule": "The office is closed"}`.
+    fn deny_messages(&mut self) -> HashMap<DenyReason, String> {
+        let Some(json) = self.optional("DENY_MESSAGES") else {
+            return HashMap::new();
+        };
+        
+        match serde_json::from_str(&json) {
+            Ok(messages) => messages,
+            Err(e) => {
+                self.problem(format!("DENY_MESSAGES: not a map of deny reasons to messages ({})", e));
+                HashMap::new()
+            }
+        }
+    }
+    
+    /// `API_KEY` has the identity `admin`; `API_KEYS` adds more as
+    /// comma-separated `name:key` pairs, e.g. `alice:k1,frontdesk:k2`.
+    fn api_keys(&mut self) -> Vec<(String, String)> {
+        let mut keys = Vec::new();
+        if let Some(key) = self.optional("API_KEY") {
+            keys.push((ADMIN_KEY_NAME.to_string(), key));
+        }
+        
+        let Some(named) = self.optional("API_KEYS") else {
+            return keys;
+        };
+        for (index, entry) in named.split(',').map(str::trim).enumerate() {
+            match entry.split_once(':') {
+                _ if entry.is_empty() => {}
+                Some((name, key)) if !name.is_empty() && !key.is_empty() => {
+                    keys.push((name.to_string(), key.to_string()));
+                }
+                // By position, so the key itself stays out of the log
+                _ => self.problem(format!("API_KEYS: entry {} is not name:key", index + 1)),
+            }
+        }
+        keys
+    }
+    
+    /// COLLECTION_TAGS is comma-separated `key=value` pairs, e.g.
+    /// `env=prod,site=front-door`, within Rekognition's tag limits.
+    fn collection_tags(&mut self) -> HashMap<String, String> {
+        let Some(value) = self.optional("COLLECTION_TAGS") else {
+            return HashMap::new();
+        };
+        
+        let mut tags = HashMap::new();
+        for pair in value.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let Some((key, tag_value)) = pair.split_once('=') else {
+                self.problem(format!("COLLECTION_TAGS: '{}' is not key=value", pair));
+                continue;
+            };
+            let (key, tag_value) = (key.trim(), tag_value.trim());
+            if key.is_empty() || key.chars().count() > 128 || key.to_lowercase().starts_with("aws:") {
+                self.problem(format!(
+                    "COLLECTION_TAGS: '{}' is not a valid tag key (1-128 characters, no aws: prefix)",
+                    key
+                ));
+            } else if tag_value.chars().count() > 256 {
+                self.problem(format!("COLLECTION_TAGS: the value of '{}' is over 256 characters", key));
+            } else if tags.insert(key.to_string(), tag_value.to_string()).is_some() {
+                self.problem(format!("COLLECTION_TAGS: '{}' is given twice", key));
+            }
+        }
+        if tags.len() > 50 {
+            self.problem(format!("COLLECTION_TAGS: {} tags given, at most 50 are allowed", tags.len()));
+        }
+        tags
+    }
+    
+    fn aws(&mut self) -> AwsSettings {
+        let profile = self.optional("AWS_PROFILE");
+        let (access_key_id, secret_access_key) = if profile.is_some() {
+            (self.optional("AWS_ACCESS_KEY_ID"), self.optional("AWS_SECRET_ACCESS_KEY"))
+        } else {
+            (
+                Some(self.required("AWS_ACCESS_KEY_ID")),
+                Some(self.required("AWS_SECRET_ACCESS_KEY")),
+            )
+        };
+        let region = match profile {
+            Some(_) => self.optional("AWS_REGION"),
+            None => Some(self.required("AWS_REGION")),
+        };
+        let endpoint_url = self.optional("AWS_ENDPOINT_URL");
+        if let Some(url) = &endpoint_url {
+            self.check_url("AWS_ENDPOINT_URL", url);
+        }
+        
+        AwsSettings {
+            profile,
+            access_key_id,
+            secret_access_key,
+            session_token: self.optional("AWS_SESSION_TOKEN"),
+            region,
+            endpoint_url,
+        }
+    }
+    
+    /// SMTP_HOST and ALERT_EMAIL are required; the sender defaults to
+    /// SMTP_USERNAME, which must then be an address.
+    fn email(&mut self) -> EmailSettings {
+        let host = self.required("SMTP_HOST");
+        let tls = self.choice(
+            "SMTP_TLS",
+            SmtpTls::Starttls,
+            &[("starttls", SmtpTls::Starttls), ("tls", SmtpTls::Tls), ("none", SmtpTls::None)],
+        );
+        let default_port = match tls {
+            SmtpTls::Tls => 465,
+            SmtpTls::Starttls | SmtpTls::None => 587,
+        };
+        let username = self.optional("SMTP_USERNAME");
+        let password = self.optional("SMTP_PASSWORD");
+        if username.is_some() != password.is_some() {
+            self.problem("SMTP_USERNAME/SMTP_PASSWORD: set both or neither".to_string());
+        }
+        let to = self.required("ALERT_EMAIL");
+        let from = match self.optional("SMTP_FROM").or_else(|| username.clone()) {
+            Some(from) => from,
+            None => self.required("SMTP_FROM"),
+        };
+        
+        EmailSettings {
+            port: self.parse("SMTP_PORT", default_port),
+            tls,
+            username,
+            password,
+            from: self.mailbox("SMTP_FROM", &from),
+            to: self.mailbox("ALERT_EMAIL", &to),
+            cooldown: Duration::from_secs(self.parse("ALERT_COOLDOWN_SECONDS", 300)),
+            host,
+        }
+    }
+    
+    fn mailbox(&mut self, name: &str, address: &str) -> lettre::message::Mailbox {
+        address.parse().unwrap_or_else(|e| {
+            if !address.is_empty() {
+                self.problem(format!("{}: '{}' is not an email address ({})", name, address, e));
+            }
+            "alerts@localhost".parse().unwrap()
+        })
+    }
+    
+    /// CAMERAS is a JSON list of {id, capture_url, door_url}; without it
+    /// the single ESP32_CAM_CAPTURE_URL / PICO2_DOOR_URL pair is used.
+    fn cameras(&mut self) -> Vec<CameraConfig> {
+        let cameras = match self.optional("CAMERAS") {
+            Some(json) => match serde_json::from_str::<Vec<CameraConfig>>(&json) {
+                Ok(cameras) => cameras,
+                Err(e) => {
+                    self.problem(format!("CAMERAS: not a valid camera list ({})", e));
+                    return Vec::new();
+                }
+            },
+            None => vec![CameraConfig {
+                id: "default".to_string(),
+                capture_url: env::var("ESP32_CAM_CAPTURE_URL")
+                    .unwrap_or_else(|_| "http://192.168.1.140/capture".to_string()),
+                door_url: env::var("PICO2_DOOR_URL").unwrap_or_else(|_| "http://192.168.1.141/door".to_string()),
+            }],
+        };
+        
+        if cameras.is_empty() {
+            self.problem("CAMERAS: must list at least one camera".to_string());
+        }
+        let mut seen = HashSet::new();
+        for camera in &cameras {
+            if !seen.insert(camera.id.as_str()) {
+                self.problem(format!("CAMERAS: duplicate camera id '{}'", camera.id));
+            }
+            self.check_url(&format!("capture_url of camera '{}'", camera.id), &camera.capture_url);
+            self.check_url(&format!("door_url of camera '{}'", camera.id), &camera.door_url);
+        }
+        
+        cameras
+    }
+    
+    fn finish(self) -> Result<()> {
+        if self.problems.is_empty() {
+            return Ok(());
+        }
+        
+        Err(anyhow::anyhow!(
+            "Invalid configuration ({} problem(s)):\n  - {}",
+            self.problems.len(),
+            self.problems.join("\n  - ")
+        ))
+    }
+}
@@ -0,0 +1,94 @@
+use askama::Template;
+use std::sync::atomic::Ordering;
+
+use crate::{AccessLog, AppState};
+
+/// How many recent log entries the dashboard renders server-side; newer
+/// ones arrive over the `/ws` event stream.
+const DASHBOARD_LOG_ENTRIES: usize = 10;
+
+/// `templates/dashboard.html`; styles and scripts are served from `/static`.
+/// The page is public, so under `PROTECT_READS` it carries no data and the
+/// script fetches the log with the API key instead.
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+struct DashboardTemplate {
+    reads_protected: bool,
+    /// `None` under `PROTECT_READS`.
+    summary: Option<Summary>,
+    polling: &'static str,
+    /// Banner text while a lockdown is active.
+    lockdown: Option<String>,
+    /// Delay between preview frames; the preview is hidden without it.
+    preview_interval_ms: Option<u128>,
+    logs: Vec<LogRow>,
+}
+
+struct Summary {
+    people_count: usize,
+    /// Entries currently held in memory, out of `log_limit`.
+    log_count: usize,
+    log_limit: usize,
+    threshold: String,
+}
+
+struct LogRow {
+    status_class: &'static str,
+    time: String,
+    action: String,
+    confidence: String,
+    reason: String,
+    snapshot: Option<String>,
+}
+
+impl From<AccessLog> for LogRow {
+    fn from(log: AccessLog) -> Self {
+        Self {
+            status_class: if log.access_granted { "access-granted" } else { "access-denied" },
+            time: log.timestamp.format("%m-%d %H:%M:%S").to_string(),
+            confidence: log
+                .confidence
+                .map(|c| format!(" ({}%)", (c * 100.0) as i32))
+                .unwrap_or_default(),
+            reason: log
+                .deny_reason
+                .map(|r| format!(" [{}]", r.code()))
+                .unwrap_or_default(),
+            action: log.action,
+            snapshot: log.snapshot,
+        }
+    }
+}
+
+pub(crate) fn render(state: &AppState) -> askama::Result<String> {
+    if state.protect_reads {
+        return DashboardTemplate {
+            reads_protected: true,
+            summary: None,
+            polling: "…",
+            lockdown: None,
+            preview_interval_ms: state.preview_interval.map(|interval| interval.as_millis()),
+            logs: Vec::new(),
+        }
+        .render();
+    }
+    
+    let logs = state.get_recent_logs(DASHBOARD_LOG_ENTRIES);
+    
+    DashboardTemplate {
+        reads_protected: false,
+        summary: Some(Summary {
+            people_count: state.get_authorized_people().len(),
+            log_count: state.log_size(),
+            log_limit: state.log_retention.max_entries,
+            threshold: format!("{:.0}", state.global_threshold()),
+        }),
+        polling: if state.polling_enabled.load(Ordering::Relaxed) { "ON" } else { "OFF" },
+        lockdown: state.lockdown.read().unwrap().as_ref().map(|lockdown| {
+            format!("since {} by {}", lockdown.since.format("%Y-%m-%d %H:%M:%S UTC"), lockdown.by)
+        }),
+        preview_interval_ms: state.preview_interval.map(|interval| interval.as_millis()),
+        logs: logs.into_iter().map(LogRow::from).collect(),
+    }
+    .render()
+}
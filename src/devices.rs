@@ -0,0 +1,506 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{
+    stream::{self, BoxStream},
+    Future, StreamExt,
+};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::{backoff_delay, error::ApiError, request_id, CameraConfig};
+
+/// Frame served by every dry-run capture.
+const DRY_RUN_CAPTURE: &[u8] = include_bytes!("../assets/dry-run-capture.png");
+
+/// How much of an MJPEG stream is read looking for one complete frame
+/// before giving up.
+const MAX_MJPEG_BUFFER: usize = 4 * 1024 * 1024;
+
+/// A live preview is cut off after this long; the dashboard reconnects if
+/// it is still open. Also bounds the upstream MJPEG request.
+pub(crate) const PREVIEW_MAX_DURATION: Duration = Duration::from_secs(10 * 60);
+
+/// Camera frames for a live preview; ends after yielding an error.
+pub(crate) type FrameStream = BoxStream<'static, Result<Bytes>>;
+
+/// How frames are fetched from the ESP32-CAM, from `ESP32_CAPTURE_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CaptureMode {
+    /// One JPEG per request to the capture URL.
+    Snapshot,
+    /// The capture URL is a `multipart/x-mixed-replace` stream; the first
+    /// complete frame is used and the stream closed.
+    Mjpeg,
+}
+
+/// The ESP32-CAMs and Pico 2 doors. Telemetry, MQTT and access logging
+/// stay in `AppState`; implementations only talk to the hardware.
+#[async_trait]
+pub(crate) trait DoorController: Send + Sync + std::fmt::Debug {
+    async fn capture(&self, camera: &CameraConfig) -> Result<Bytes>;
+    
+    /// Frames for a live preview, at most one per `interval`. An MJPEG
+    /// stream is passed through, dropping frames over the rate; otherwise
+    /// a snapshot is taken every `interval`.
+    async fn preview(&self, camera: &CameraConfig, interval: Duration) -> Result<FrameStream>;
+    
+    /// `hold_open` tells the door how long to stay unlocked before
+    /// relocking by itself, so it fails safe if the server never sends
+    /// the lock. `None` holds it until the next command.
+    async fn set_door(&self, camera: &CameraConfig, unlock: bool, hold_open: Option<Duration>) -> Result<()>;
+    
+    /// Whether the door reports itself unlocked, for firmware that answers
+    /// `GET door_url` with `{"state": "locked"|"unlocked"}`. `None` when it
+    /// doesn't say, so only the last command sent is known.
+    async fn door_state(&self, camera: &CameraConfig) -> Result<Option<bool>>;
+    
+    /// Whether the door's sensor reports it physically open, for firmware
+    /// that adds `"open": true|false` to its `GET door_url` answer. `None`
+    /// when there is no sensor reading.
+    async fn door_sensor(&self, camera: &CameraConfig) -> Result<Option<SensorReading>>;
+    
+    /// Whether anything answers at `url`; used by the health check.
+    async fn is_reachable(&self, url: &str) -> bool;
+}
+
+/// A door sensor reading.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SensorReading {
+    pub(crate) open: bool,
+    /// When the sensor last changed by the device's clock, for firmware
+    /// that adds `"changed_at"` (Unix seconds).
+    pub(crate) changed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct HttpDoorController {
+    client: reqwest::Client,
+    max_retries: u32,
+    base_delay_ms: u64,
+    /// Query string appended to every capture URL, e.g. `res=SVGA`.
+    capture_params: Option<String>,
+    capture_mode: CaptureMode,
+}
+
+impl HttpDoorController {
+    pub(crate) fn new(
+        client: reqwest::Client,
+        max_retries: u32,
+        base_delay_ms: u64,
+        capture_params: Option<String>,
+        capture_mode: CaptureMode,
+    ) -> Self {
+        Self {
+            client,
+            max_retries,
+            base_delay_ms,
+            capture_params,
+            capture_mode,
+        }
+    }
+    
+    fn capture_url(&self, camera: &CameraConfig) -> String {
+        match self.capture_params.as_deref().map(|params| params.trim_start_matches(['?', '&'])) {
+            Some(params) if !params.is_empty() => {
+                let separator = if camera.capture_url.contains('?') { '&' } else { '?' };
+                format!("{}{}{}", camera.capture_url, separator, params)
+            }
+            _ => camera.capture_url.clone(),
+        }
+    }
+    
+    async fn capture_snapshot(&self, camera: &CameraConfig) -> Result<Bytes> {
+        let response = self
+            .send_with_retry(self.client.get(self.capture_url(camera)), "ESP32-CAM capture")
+            .await?;
+        
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("ESP32-CAM capture failed: {}", response.status()));
+        }
+        
+        // A camera mid-reset can answer 200 with an empty body or an HTML
+        // error page; catch that here rather than as a Rekognition error.
+        // Firmware that sends no content type at all gets the benefit of the doubt.
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        if !content_type.is_empty() && !content_type.starts_with("image/") {
+            return Err(ApiError::EmptyCapture(format!(
+                "camera '{}' returned {} instead of an image",
+                camera.id, content_type
+            ))
+            .into());
+        }
+        
+        let image_data = response.bytes().await?;
+        if image_data.is_empty() {
+            return Err(ApiError::EmptyCapture(format!("camera '{}' returned an empty frame", camera.id)).into());
+        }
+        
+        info!("✅ Captured {} bytes from ESP32-CAM", image_data.len());
+        Ok(image_data)
+    }
+    
+    /// Reads the stream at the capture URL until one whole JPEG has
+    /// arrived, then drops the connection.
+    async fn capture_stream_frame(&self, camera: &CameraConfig) -> Result<Bytes> {
+        let (mut response, boundary) = self.open_stream(self.client.get(self.capture_url(camera)), camera).await?;
+        
+        let mut buffer = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            if let Some((frame, _)) = next_mjpeg_frame(&buffer, &boundary) {
+                if !frame.starts_with(&[0xFF, 0xD8]) {
+                    return Err(ApiError::EmptyCapture(format!(
+                        "camera '{}' streamed a frame that is not a JPEG",
+                        camera.id
+                    ))
+                    .into());
+                }
+                info!("✅ Grabbed a {}-byte frame from the ESP32-CAM stream", frame.len());
+                // Dropping the response closes the stream
+                return Ok(frame);
+            }
+            if buffer.len() > MAX_MJPEG_BUFFER {
+                break;
+            }
+        }
+        
+        Err(ApiError::EmptyCapture(format!(
+            "no complete frame in the first {} bytes of the stream from camera '{}'",
+            buffer.len(),
+            camera.id
+        ))
+        .into())
+    }
+    
+    /// Requests the MJPEG stream, returning the response and its part
+    /// boundary.
+    async fn open_stream(
+        &self,
+        request: reqwest::RequestBuilder,
+        camera: &CameraConfig,
+    ) -> Result<(reqwest::Response, String)> {
+        let response = self.send_with_retry(request, "ESP32-CAM stream").await?;
+        
+        if !response.status().is_success() {
+            return Err(anyhow::anyhow!("ESP32-CAM stream failed: {}", response.status()));
+        }
+        
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        let Some(boundary) = multipart_boundary(&content_type) else {
+            return Err(ApiError::EmptyCapture(format!(
+                "camera '{}' returned {} instead of an MJPEG stream",
+                camera.id,
+                if content_type.is_empty() { "no content type" } else { &content_type }
+            ))
+            .into());
+        };
+        
+        Ok((response, boundary))
+    }
+    
+    /// Keeps the camera's stream open and forwards its frames, skipping
+    /// any that arrive sooner than `interval` after the last one sent.
+    async fn preview_stream(&self, camera: &CameraConfig, interval: Duration) -> Result<FrameStream> {
+        // The client's timeout covers the whole body, far too short for a stream
+        let request = self.client.get(self.capture_url(camera)).timeout(PREVIEW_MAX_DURATION);
+        let (response, boundary) = self.open_stream(request, camera).await?;
+        let camera_id = camera.id.clone();
+        
+        let frames = stream::unfold(Some((response, Vec::new(), Instant::now())), move |state| {
+            let boundary = boundary.clone();
+            let camera_id = camera_id.clone();
+            async move {
+                let (mut response, mut buffer, mut due) = state?;
+                loop {
+                    while let Some((frame, consumed)) = next_mjpeg_frame(&buffer, &boundary) {
+                        buffer.drain(..consumed);
+                        let now = Instant::now();
+                        if now >= due {
+                            due = now + interval;
+                            return Some((Ok(frame), Some((response, buffer, due))));
+                        }
+                    }
+                    if buffer.len() > MAX_MJPEG_BUFFER {
+                        let error = ApiError::EmptyCapture(format!(
+                            "no complete frame in {} bytes of the stream from camera '{}'",
+                            buffer.len(),
+                            camera_id
+                        ));
+                        return Some((Err(error.into()), None));
+                    }
+                    match response.chunk().await {
+                        Ok(Some(chunk)) => buffer.extend_from_slice(&chunk),
+                        Ok(None) => return None,
+                        Err(e) => return Some((Err(e.into()), None)),
+                    }
+                }
+            }
+        });
+        Ok(frames.boxed())
+    }
+    
+    /// Sends a device request, retrying connection errors, timeouts and 5xx
+    /// responses with exponential backoff. Other responses (including 4xx)
+    /// are returned to the caller as-is. Carries the current request id, so
+    /// device logs line up with ours.
+    async fn send_with_retry(&self, request: reqwest::RequestBuilder, what: &str) -> Result<reqwest::Response> {
+        let request = request_id::forward(request);
+        let attempts = self.max_retries + 1;
+        let mut last_error = String::new();
+        
+        for attempt in 1..=attempts {
+            let Some(request) = request.try_clone() else {
+                return Err(anyhow::anyhow!("{} request cannot be retried", what));
+            };
+            
+            match request.send().await {
+                Ok(response) if response.status().is_server_error() => {
+                    last_error = format!("HTTP {}", response.status());
+                }
+                Ok(response) => return Ok(response),
+                Err(e) if e.is_connect() || e.is_timeout() => {
+                    last_error = e.to_string();
+                }
+                Err(e) => return Err(anyhow::anyhow!("{} failed: {}", what, e)),
+            }
+            
+            if attempt < attempts {
+                let delay = backoff_delay(self.base_delay_ms, attempt);
+                warn!(
+                    "⚠️ {} attempt {}/{} failed ({}), retrying in {:?}",
+                    what, attempt, attempts, last_error, delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+        
+        Err(anyhow::anyhow!("{} failed after {} attempts: {}", what, attempts, last_error))
+    }
+}
+
+/// The boundary of a `multipart/x-mixed-replace; boundary=frame` content
+/// type, without quotes.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    if !content_type.to_ascii_lowercase().starts_with("multipart/") {
+        return None;
+    }
+    content_type
+        .split(';')
+        .filter_map(|param| param.trim().split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// The body of the first complete part in an MJPEG stream read so far,
+/// and how many bytes of `buffer` it used up; `None` while more data is
+/// needed. Parts carry a `Content-Length` on most firmware; without one,
+/// the part runs to the next boundary.
+fn next_mjpeg_frame(buffer: &[u8], boundary: &str) -> Option<(Bytes, usize)> {
+    let delimiter = format!("--{}", boundary);
+    let headers_start = find(buffer, delimiter.as_bytes())? + delimiter.len();
+    let body_start = headers_start + find(&buffer[headers_start..], b"\r\n\r\n")? + 4;
+    
+    let headers = String::from_utf8_lossy(&buffer[headers_start..body_start]);
+    let content_length = headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("content-length") {
+            value.trim().parse::<usize>().ok()
+        } else {
+            None
+        }
+    });
+    
+    let body = &buffer[body_start..];
+    let (frame, consumed) = match content_length {
+        Some(length) => (body.get(..length)?, length),
+        None => {
+            let end = find(body, delimiter.as_bytes())?;
+            (body[..end].strip_suffix(b"\r\n").unwrap_or(&body[..end]), end)
+        }
+    };
+    Some((Bytes::copy_from_slice(frame), body_start + consumed))
+}
+
+/// Calls `capture` once per `interval` until it fails.
+fn paced<F, Fut>(interval: Duration, capture: F) -> FrameStream
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Bytes>> + Send + 'static,
+{
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    
+    stream::unfold(Some((ticker, capture)), |state| async move {
+        let (mut ticker, capture) = state?;
+        ticker.tick().await;
+        match capture().await {
+            Ok(frame) => Some((Ok(frame), Some((ticker, capture)))),
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+    .boxed()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[async_trait]
+impl DoorController for HttpDoorController {
+    async fn capture(&self, camera: &CameraConfig) -> Result<Bytes> {
+        match self.capture_mode {
+            CaptureMode::Snapshot => self.capture_snapshot(camera).await,
+            CaptureMode::Mjpeg => self.capture_stream_frame(camera).await,
+        }
+    }
+    
+    async fn preview(&self, camera: &CameraConfig, interval: Duration) -> Result<FrameStream> {
+        match self.capture_mode {
+            CaptureMode::Snapshot => {
+                let controller = self.clone();
+                let camera = camera.clone();
+                Ok(paced(interval, move || {
+                    let controller = controller.clone();
+                    let camera = camera.clone();
+                    async move { controller.capture_snapshot(&camera).await }
+                }))
+            }
+            CaptureMode::Mjpeg => self.preview_stream(camera, interval).await,
+        }
+    }
+    
+    async fn set_door(&self, camera: &CameraConfig, unlock: bool, hold_open: Option<Duration>) -> Result<()> {
+        let action = if unlock { "unlock" } else { "lock" };
+        let mut payload = serde_json::json!({
+            "action": action,
+            "timestamp": Utc::now().timestamp()
+        });
+        if let Some(hold_open) = hold_open {
+            payload["unlock_duration_ms"] = serde_json::json!(hold_open.as_millis() as u64);
+        }
+        
+        let response = self
+            .send_with_retry(self.client.post(&camera.door_url).json(&payload), "Pico 2 door command")
+            .await?;
+        
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            warn!("⚠️ Pico 2 door {} failed: {}", action, response.status());
+            Err(anyhow::anyhow!("Pico 2 door {} failed: {}", action, response.status()))
+        }
+    }
+    
+    /// A single attempt: a status query is cheap to repeat, and firmware
+    /// without one answers with an error status or a body we can't read.
+    async fn door_state(&self, camera: &CameraConfig) -> Result<Option<bool>> {
+        #[derive(Deserialize)]
+        struct ReportedState {
+            state: String,
+        }
+        
+        let response = request_id::forward(self.client.get(&camera.door_url)).send().await?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        
+        Ok(match response.json::<ReportedState>().await {
+            Ok(reported) => match reported.state.as_str() {
+                "unlocked" => Some(true),
+                "locked" => Some(false),
+                _ => None,
+            },
+            Err(_) => None,
+        })
+    }
+    
+    /// A single attempt, like `door_state`; callers poll.
+    async fn door_sensor(&self, camera: &CameraConfig) -> Result<Option<SensorReading>> {
+        #[derive(Deserialize)]
+        struct ReportedSensor {
+            open: Option<bool>,
+            changed_at: Option<i64>,
+        }
+        
+        let response = request_id::forward(self.client.get(&camera.door_url)).send().await?;
+        if !response.status().is_success() {
+            return Ok(None);
+        }
+        
+        let Ok(reported) = response.json::<ReportedSensor>().await else {
+            return Ok(None);
+        };
+        Ok(reported.open.map(|open| SensorReading {
+            open,
+            changed_at: reported
+                .changed_at
+                .and_then(|seconds| DateTime::from_timestamp(seconds, 0)),
+        }))
+    }
+    
+    /// Any HTTP response counts as reachable; the device may not implement HEAD.
+    async fn is_reachable(&self, url: &str) -> bool {
+        self.client.head(url).send().await.is_ok()
+    }
+}
+
+/// Pretends every camera and door is present: captures return a bundled
+/// frame and door commands are only logged.
+#[derive(Debug, Default)]
+pub(crate) struct DryRunDoorController;
+
+#[async_trait]
+impl DoorController for DryRunDoorController {
+    async fn capture(&self, camera: &CameraConfig) -> Result<Bytes> {
+        info!("🧪 Dry run - returning the bundled frame for camera '{}'", camera.id);
+        Ok(Bytes::from_static(DRY_RUN_CAPTURE))
+    }
+    
+    async fn preview(&self, camera: &CameraConfig, interval: Duration) -> Result<FrameStream> {
+        info!("🧪 Dry run - previewing the bundled frame for camera '{}'", camera.id);
+        Ok(paced(interval, || async { Ok(Bytes::from_static(DRY_RUN_CAPTURE)) }))
+    }
+    
+    async fn set_door(&self, camera: &CameraConfig, unlock: bool, hold_open: Option<Duration>) -> Result<()> {
+        match hold_open {
+            Some(hold_open) if unlock => info!("🧪 Dry run - door '{}' would unlock for {:?}", camera.id, hold_open),
+            _ => info!(
+                "🧪 Dry run - door '{}' would {}",
+                camera.id,
+                if unlock { "unlock" } else { "lock" }
+            ),
+        }
+        Ok(())
+    }
+    
+    async fn door_state(&self, _camera: &CameraConfig) -> Result<Option<bool>> {
+        Ok(None)
+    }
+    
+    /// The pretend door always swings open.
+    async fn door_sensor(&self, _camera: &CameraConfig) -> Result<Option<SensorReading>> {
+        Ok(Some(SensorReading {
+            open: true,
+            changed_at: None,
+        }))
+    }
+    
+    async fn is_reachable(&self, _url: &str) -> bool {
+        true
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use std::future::Future;
+use tokio::sync::watch;
+
+use crate::{client_ip, error::ApiError, request_id};
+
+tokio::task_local! {
+    /// Closes when the handler that started the work is dropped, which is
+    /// what hyper does once the client has disconnected.
+    static CLIENT: watch::Receiver<()>;
+}
+
+/// Runs `work` on its own task, carrying the request id and client address
+/// along. A disconnect drops the handler, but not this task, so `work` is
+/// never cut off halfway through a door command; it finds out through
+/// [`client_gone`] and [`unless_client_gone`] instead.
+pub(crate) async fn detach<T, F>(work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: Future<Output = Result<T>> + Send + 'static,
+{
+    // Held by this future only, so dropping it is the disconnect signal
+    let (_connected, client) = watch::channel(());
+    let work = CLIENT.scope(client, work);
+    let work = client_ip::with(client_ip::current(), work);
+    let work = request_id::with(request_id::current(), work);
+    
+    tokio::spawn(work)
+        .await
+        .map_err(|e| ApiError::Internal(format!("Recognition task failed: {}", e)))?
+}
+
+/// Whether the client that started this work has gone away. Always `false`
+/// outside [`detach`], e.g. for polling.
+pub(crate) fn client_gone() -> bool {
+    CLIENT
+        .try_with(|client| client.has_changed().is_err())
+        .unwrap_or(false)
+}
+
+/// Runs `work` unless the client disconnects first, in which case `work`
+/// is dropped and `None` returned.
+pub(crate) async fn unless_client_gone<F: Future>(work: F) -> Option<F::Output> {
+    let Ok(mut client) = CLIENT.try_with(watch::Receiver::clone) else {
+        return Some(work.await);
+    };
+    
+    tokio::select! {
+        output = work => Some(output),
+        // Nothing is ever sent, so this only resolves once the sender is gone
+        _ = client.changed() => None,
+    }
+}
@@ -0,0 +1,297 @@
+use axum::{
+    extract::multipart::MultipartError,
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Json, Response},
+};
+use aws_sdk_rekognition::{error::ProvideErrorMetadata, Error as RekognitionError};
+use std::fmt;
+
+use crate::{request_id, ApiResponse};
+
+/// Failure surfaced by a handler. Each variant maps to a stable
+/// `error_code` and HTTP status so clients can branch without parsing
+/// the human-readable message.
+#[derive(Debug)]
+pub(crate) enum ApiError {
+    BadRequest(String),
+    /// A multipart field that is unexpected, too long or of the wrong type.
+    InvalidField(String),
+    Unauthorized,
+    /// `/api/admin/verify` found no confident match for an admin.
+    NotAdmin,
+    /// Authenticated, but not allowed to do this.
+    Forbidden(String),
+    PersonNotFound(String),
+    PersonExists(String),
+    UnknownCamera(String),
+    SnapshotNotFound(String),
+    /// A `Range` that starts past the end of the file.
+    RangeNotSatisfiable,
+    /// Seconds until the client may retry.
+    RateLimited(u64),
+    NoFaceDetected,
+    /// Every photo fell below `ENROLL_MIN_QUALITY`.
+    PoorPhotoQuality(String),
+    InvalidImage(String),
+    /// Not a format we can decode and transcode for Rekognition.
+    UnsupportedImageFormat(String),
+    LivenessRequired,
+    PinRequired,
+    LivenessSessionExpired,
+    /// A pushed frame's capture time is too old, or too far off our clock.
+    StaleFrame(String),
+    DoorControlDisabled,
+    /// A manual or test unlock during a lockdown.
+    LockdownActive,
+    /// `PREVIEW_ENABLED` is off.
+    PreviewDisabled,
+    Esp32Unreachable(String),
+    /// The camera answered, but not with an image.
+    EmptyCapture(String),
+    DoorUnreachable(String),
+    AwsThrottled,
+    Aws(String),
+    /// No route for this path.
+    NotFound(String),
+    MethodNotAllowed,
+    /// The body is over the route's `MAX_*UPLOAD_MB` limit.
+    PayloadTooLarge,
+    /// The client disconnected before recognition finished; nobody reads
+    /// the response, but the log and metrics see the code.
+    ClientClosedRequest,
+    Internal(String),
+}
+
+impl ApiError {
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "BAD_REQUEST",
+            ApiError::InvalidField(_) => "INVALID_FIELD",
+            ApiError::Unauthorized => "UNAUTHORIZED",
+            ApiError::NotAdmin => "NOT_ADMIN",
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::PersonNotFound(_) => "PERSON_NOT_FOUND",
+            ApiError::PersonExists(_) => "PERSON_EXISTS",
+            ApiError::UnknownCamera(_) => "UNKNOWN_CAMERA",
+            ApiError::SnapshotNotFound(_) => "SNAPSHOT_NOT_FOUND",
+            ApiError::RangeNotSatisfiable => "RANGE_NOT_SATISFIABLE",
+            ApiError::RateLimited(_) => "RATE_LIMITED",
+            ApiError::NoFaceDetected => "NO_FACE_DETECTED",
+            ApiError::PoorPhotoQuality(_) => "POOR_PHOTO_QUALITY",
+            ApiError::InvalidImage(_) => "INVALID_IMAGE",
+            ApiError::UnsupportedImageFormat(_) => "UNSUPPORTED_IMAGE_FORMAT",
+            ApiError::LivenessRequired => "LIVENESS_REQUIRED",
+            ApiError::PinRequired => "PIN_REQUIRED",
+            ApiError::LivenessSessionExpired => "LIVENESS_SESSION_EXPIRED",
+            ApiError::StaleFrame(_) => "STALE_FRAME",
+            ApiError::DoorControlDisabled => "DOOR_CONTROL_DISABLED",
+            ApiError::LockdownActive => "LOCKDOWN_ACTIVE",
+            ApiError::PreviewDisabled => "PREVIEW_DISABLED",
+            ApiError::Esp32Unreachable(_) => "ESP32_UNREACHABLE",
+            ApiError::EmptyCapture(_) => "EMPTY_CAPTURE",
+            ApiError::DoorUnreachable(_) => "DOOR_UNREACHABLE",
+            ApiError::AwsThrottled => "AWS_THROTTLED",
+            ApiError::Aws(_) => "AWS_ERROR",
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::MethodNotAllowed => "METHOD_NOT_ALLOWED",
+            ApiError::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            ApiError::ClientClosedRequest => "CLIENT_CLOSED_REQUEST",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+    
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) | ApiError::InvalidField(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::NotAdmin | ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::PersonNotFound(_)
+            | ApiError::UnknownCamera(_)
+            | ApiError::SnapshotNotFound(_)
+            | ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            ApiError::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            // nginx's non-standard 499, which proxies already understand
+            ApiError::ClientClosedRequest => StatusCode::from_u16(499).unwrap(),
+            ApiError::NoFaceDetected | ApiError::PoorPhotoQuality(_) | ApiError::StaleFrame(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ApiError::InvalidImage(_) | ApiError::UnsupportedImageFormat(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::LivenessRequired | ApiError::PinRequired => StatusCode::PRECONDITION_REQUIRED,
+            ApiError::LivenessSessionExpired => StatusCode::GONE,
+            ApiError::PersonExists(_)
+            | ApiError::DoorControlDisabled
+            | ApiError::LockdownActive
+            | ApiError::PreviewDisabled => StatusCode::CONFLICT,
+            ApiError::Esp32Unreachable(_)
+            | ApiError::EmptyCapture(_)
+            | ApiError::DoorUnreachable(_)
+            | ApiError::Aws(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::AwsThrottled => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::BadRequest(message) => write!(f, "{}", message),
+            ApiError::InvalidField(detail) => write!(f, "Invalid form field: {}", detail),
+            ApiError::Unauthorized => write!(f, "Missing or invalid API key"),
+            ApiError::NotAdmin => write!(f, "Face not recognized as an admin"),
+            ApiError::Forbidden(detail) => write!(f, "{}", detail),
+            ApiError::PersonNotFound(name) => write!(f, "Person '{}' not found", name),
+            ApiError::PersonExists(name) => write!(f, "Person '{}' already exists", name),
+            ApiError::UnknownCamera(id) => write!(f, "Unknown camera '{}'", id),
+            ApiError::SnapshotNotFound(id) => write!(f, "Snapshot '{}' not found", id),
+            ApiError::RangeNotSatisfiable => write!(f, "Requested range is past the end of the snapshot"),
+            ApiError::RateLimited(seconds) => write!(f, "Too many requests - retry in {}s", seconds),
+            ApiError::NoFaceDetected => write!(f, "No face detected in image"),
+            ApiError::PoorPhotoQuality(detail) => write!(f, "Photo quality too low to enroll: {}", detail),
+            ApiError::InvalidImage(detail) => write!(f, "Invalid image: {}", detail),
+            ApiError::UnsupportedImageFormat(detail) => write!(f, "Unsupported image format: {}", detail),
+            ApiError::LivenessRequired => {
+                write!(f, "Liveness challenge required - start one via /api/liveness/start")
+            }
+            ApiError::PinRequired => write!(f, "PIN required - use /api/check-access-2fa"),
+            ApiError::LivenessSessionExpired => write!(f, "Liveness session expired or unknown"),
+            ApiError::StaleFrame(detail) => write!(f, "Stale frame: {}", detail),
+            ApiError::DoorControlDisabled => write!(f, "door control disabled"),
+            ApiError::LockdownActive => write!(f, "lockdown active - clear it before unlocking a door"),
+            ApiError::PreviewDisabled => write!(f, "camera preview disabled - set PREVIEW_ENABLED=true"),
+            // Already worded by the device helpers, e.g. "ESP32-CAM capture failed: ..."
+            ApiError::Esp32Unreachable(detail) | ApiError::DoorUnreachable(detail) => write!(f, "{}", detail),
+            ApiError::EmptyCapture(detail) => write!(f, "ESP32-CAM capture unusable: {}", detail),
+            ApiError::AwsThrottled => write!(f, "AWS Rekognition is throttling requests - retry shortly"),
+            ApiError::Aws(detail) => write!(f, "AWS Rekognition error: {}", detail),
+            ApiError::NotFound(path) => write!(f, "No route for {}", path),
+            ApiError::MethodNotAllowed => write!(f, "Method not allowed on this route"),
+            ApiError::PayloadTooLarge => write!(f, "Request body is over the size limit for this route"),
+            ApiError::ClientClosedRequest => write!(f, "Client disconnected before recognition finished"),
+            ApiError::Internal(detail) => write!(f, "{}", detail),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Recovers the typed error when one was raised inside `AppState`, and
+/// classifies Rekognition failures; anything else is an internal error.
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<ApiError>() {
+            Ok(api_error) => return api_error,
+            Err(error) => error,
+        };
+        
+        match error.downcast_ref::<RekognitionError>() {
+            Some(error) if is_throttling(error) => ApiError::AwsThrottled,
+            Some(error) if rejects_faceless_image(error) => ApiError::NoFaceDetected,
+            Some(RekognitionError::InvalidParameterException(error)) => {
+                ApiError::BadRequest(error.message().unwrap_or("invalid parameter").to_string())
+            }
+            Some(other) => ApiError::Aws(other.to_string()),
+            None => ApiError::Internal(error.to_string()),
+        }
+    }
+}
+
+/// Rekognition's answer to a search image without a detectable face.
+pub(crate) fn is_no_face(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<RekognitionError>().is_some_and(rejects_faceless_image)
+}
+
+/// Rekognition reports a faceless image as an `InvalidParameterException`,
+/// the same code it uses for any other bad parameter, so only the message
+/// tells them apart.
+fn rejects_faceless_image(error: &RekognitionError) -> bool {
+    matches!(error, RekognitionError::InvalidParameterException(_))
+        && error.message().is_some_and(|message| message.to_lowercase().contains("no faces"))
+}
+
+/// Rekognition being down, overloaded or unreachable, as opposed to it
+/// rejecting the request: what `AWS_OUTAGE_POLICY` covers. Network and
+/// timeout failures carry no error code.
+pub(crate) fn is_outage(error: &anyhow::Error) -> bool {
+    match error.downcast_ref::<RekognitionError>() {
+        Some(error) => {
+            is_throttling(error)
+                || matches!(error, RekognitionError::InternalServerError(_))
+                || matches!(error.code(), None | Some("ServiceUnavailable" | "ServiceUnavailableException"))
+        }
+        None => false,
+    }
+}
+
+/// Errors AWS raises when the account's request rate is exceeded; worth
+/// backing off and retrying rather than reporting straight away.
+pub(crate) fn is_throttling(error: &RekognitionError) -> bool {
+    matches!(
+        error,
+        RekognitionError::ThrottlingException(_) | RekognitionError::ProvisionedThroughputExceededException(_)
+    )
+}
+
+impl From<MultipartError> for ApiError {
+    fn from(error: MultipartError) -> Self {
+        if error.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return ApiError::PayloadTooLarge;
+        }
+        ApiError::BadRequest(format!("Invalid multipart body: {}", error.body_text()))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (
+            self.status(),
+            Json(ApiResponse::<()> {
+                success: false,
+                data: None,
+                error: Some(self.to_string()),
+                error_code: Some(self.code().to_string()),
+                request_id: request_id::current(),
+            }),
+        )
+            .into_response()
+    }
+}
+
+/// Router fallback, so unknown paths get the usual `ApiResponse` envelope.
+pub(crate) async fn not_found(uri: Uri) -> ApiError {
+    ApiError::NotFound(uri.path().to_string())
+}
+
+/// axum answers a known path with the wrong method with an empty 405;
+/// this swaps in the JSON envelope and keeps the `Allow` header.
+pub(crate) async fn method_not_allowed(response: Response) -> Response {
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED || response.headers().contains_key(header::CONTENT_TYPE) {
+        return response;
+    }
+    
+    let allow = response.headers().get(header::ALLOW).cloned();
+    let mut json = ApiError::MethodNotAllowed.into_response();
+    if let Some(allow) = allow {
+        json.headers_mut().insert(header::ALLOW, allow);
+    }
+    json
+}
+
+/// tower-http rejects an oversized body with an empty 413 and axum's
+/// extractors with a plain-text one; both get the JSON envelope instead.
+pub(crate) async fn payload_too_large(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type.as_bytes().starts_with(b"application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    
+    ApiError::PayloadTooLarge.into_response()
+}
@@ -0,0 +1,122 @@
+use anyhow::Result;
+use bytes::Bytes;
+use futures::Stream;
+use serde::Deserialize;
+
+use crate::{store::Store, AccessLog};
+
+/// Rows fetched from the store per chunk of the response body.
+const BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    pub(crate) fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+        }
+    }
+    
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+enum Cursor {
+    Start,
+    After(i64),
+    Done,
+}
+
+/// Streams the whole access log, oldest first, a batch at a time so memory
+/// use stays flat however long the history is. JSON is emitted as a single
+/// array; CSV has a header row.
+pub(crate) fn stream(store: Store, format: ExportFormat) -> impl Stream<Item = Result<Bytes>> + Send + 'static {
+    futures::stream::unfold(Cursor::Start, move |cursor| {
+        let store = store.clone();
+        async move {
+            let after = match cursor {
+                Cursor::Start => 0,
+                Cursor::After(id) => id,
+                Cursor::Done => return None,
+            };
+            let first = matches!(cursor, Cursor::Start);
+            
+            let batch = match store.logs_after(after, BATCH_SIZE).await {
+                Ok(batch) => batch,
+                Err(e) => return Some((Err(e), Cursor::Done)),
+            };
+            let Some(&(last_id, _)) = batch.last() else {
+                let tail = match (format, first) {
+                    (ExportFormat::Csv, true) => format!("{}\n", CSV_HEADER),
+                    (ExportFormat::Csv, false) => String::new(),
+                    (ExportFormat::Json, true) => "[]".to_string(),
+                    (ExportFormat::Json, false) => "]".to_string(),
+                };
+                return Some((Ok(Bytes::from(tail)), Cursor::Done));
+            };
+            
+            let chunk = match format {
+                ExportFormat::Csv => encode_csv(&batch, first),
+                ExportFormat::Json => match encode_json(&batch, first) {
+                    Ok(chunk) => chunk,
+                    Err(e) => return Some((Err(e), Cursor::Done)),
+                },
+            };
+            Some((Ok(Bytes::from(chunk)), Cursor::After(last_id)))
+        }
+    })
+}
+
+const CSV_HEADER: &str = "timestamp,action,person_name,confidence,access_granted";
+
+fn encode_csv(batch: &[(i64, AccessLog)], first: bool) -> String {
+    let mut out = String::new();
+    if first {
+        out.push_str(CSV_HEADER);
+        out.push('\n');
+    }
+    
+    for (_, log) in batch {
+        let row = [
+            log.timestamp.to_rfc3339(),
+            csv_field(&log.action),
+            csv_field(log.person_name.as_deref().unwrap_or("")),
+            log.confidence.map(|c| c.to_string()).unwrap_or_default(),
+            log.access_granted.to_string(),
+        ];
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Quotes a field when it contains a delimiter, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn encode_json(batch: &[(i64, AccessLog)], first: bool) -> Result<String> {
+    let mut out = String::from(if first { "[" } else { "," });
+    for (index, (_, log)) in batch.iter().enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        out.push_str(&serde_json::to_string(log)?);
+    }
+    Ok(out)
+}
@@ -0,0 +1,587 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use aws_config::BehaviorVersion;
+use aws_sdk_rekognition::{
+    config::{Credentials, Region},
+    types::{Attribute, BoundingBox, FaceDetail, Image, QualityFilter},
+    Client as RekognitionClient, Error as RekognitionError,
+};
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::Mutex,
+};
+use tracing::{info, warn};
+
+use crate::{
+    backoff_delay,
+    config::{AwsSettings, CollectionSettings},
+    error, telemetry, FaceAttributes, FaceBox, FacePose,
+};
+
+/// What the engine reports about the collection itself.
+#[derive(Debug, Clone)]
+pub(crate) struct CollectionInfo {
+    pub(crate) face_count: u64,
+    pub(crate) face_model_version: Option<String>,
+    pub(crate) arn: Option<String>,
+}
+
+/// A face stored in the collection, with the name it was indexed under.
+#[derive(Debug, Clone)]
+pub(crate) struct StoredFace {
+    pub(crate) face_id: String,
+    pub(crate) external_id: String,
+}
+
+/// A collection match for a searched image. `similarity` is a
+/// percentage, as Rekognition reports it.
+#[derive(Debug, Clone)]
+pub(crate) struct FaceMatch {
+    pub(crate) face_id: String,
+    pub(crate) external_id: String,
+    pub(crate) similarity: f32,
+    /// Where the face that was searched with sits in the image, the same
+    /// for every match of one search. Spares a `DetectFaces` call when the
+    /// box is all that's needed.
+    pub(crate) searched_face: Option<FaceBox>,
+}
+
+/// A face found by detection, in engine-neutral terms. Box coordinates are
+/// fractions of the image size and angles are in degrees.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DetectedFace {
+    pub(crate) bounding_box: Option<FaceBox>,
+    pub(crate) pose: Option<FacePose>,
+    /// 0-100; higher is brighter.
+    pub(crate) brightness: Option<f32>,
+    /// 0-100; higher is sharper.
+    pub(crate) sharpness: Option<f32>,
+    pub(crate) eyes_open: Option<bool>,
+    pub(crate) attributes: FaceAttributes,
+}
+
+impl DetectedFace {
+    pub(crate) fn area(&self) -> f32 {
+        self.bounding_box
+            .as_ref()
+            .map_or(0.0, |bounds| bounds.width * bounds.height)
+    }
+}
+
+/// Face detection, indexing and search. The rest of the server only talks
+/// to this, so the engine behind it is chosen once at startup and a local
+/// engine can stand in for Rekognition.
+#[async_trait]
+pub(crate) trait FaceRecognizer: Send + Sync + std::fmt::Debug {
+    /// Creates the face collection if it doesn't exist yet, unless
+    /// `COLLECTION_AUTO_CREATE` is off.
+    async fn ensure_collection(&self) -> Result<()>;
+    
+    async fn list_faces(&self) -> Result<Vec<StoredFace>>;
+    
+    async fn describe_collection(&self) -> Result<CollectionInfo>;
+    
+    /// Indexes the most prominent face in the image under `external_id`,
+    /// returning its face id, or `None` when no face was found.
+    async fn index_face(&self, external_id: &str, image_data: &Bytes) -> Result<Option<String>>;
+    
+    /// Returns the closest face at or above `threshold` percent similarity.
+    async fn search_face(&self, image_data: &Bytes, threshold: f32) -> Result<Option<FaceMatch>> {
+        Ok(self.search_candidates(image_data, threshold, 1).await?.into_iter().next())
+    }
+    
+    /// Returns up to `max_faces` faces at or above `threshold` percent
+    /// similarity, closest first.
+    async fn search_candidates(&self, image_data: &Bytes, threshold: f32, max_faces: i32) -> Result<Vec<FaceMatch>>;
+    
+    /// Removes the given faces, returning how many were actually deleted.
+    async fn delete_faces(&self, face_ids: &[String]) -> Result<usize>;
+    
+    async fn detect_faces(&self, image_data: &Bytes) -> Result<Vec<DetectedFace>>;
+    
+    async fn is_healthy(&self) -> bool;
+}
+
+/// Guards against a `next_token` that never runs out; at 1000 faces a
+/// page this is far beyond any household.
+const LIST_FACES_MAX_PAGES: usize = 100;
+
+#[derive(Debug)]
+pub(crate) struct RekognitionRecognizer {
+    client: RekognitionClient,
+    collection_id: String,
+    collection_tags: HashMap<String, String>,
+    auto_create: bool,
+    /// Resolved from `AWS_REGION` or the profile; the collection lives here.
+    region: Option<String>,
+    quality_filter: QualityFilter,
+}
+
+impl RekognitionRecognizer {
+    pub(crate) async fn new(aws: &AwsSettings, collection: CollectionSettings, quality_filter: QualityFilter) -> Self {
+        info!("🦀 Initializing Rust AWS Rekognition Door Lock...");
+        
+        let mut loader = aws_config::defaults(BehaviorVersion::latest());
+        
+        let source = match (&aws.profile, &aws.access_key_id, &aws.secret_access_key) {
+            (Some(profile), _, _) => {
+                loader = loader.profile_name(profile);
+                format!("profile '{}'", profile)
+            }
+            (None, Some(key), Some(secret)) => {
+                loader = loader.credentials_provider(Credentials::new(
+                    key,
+                    secret,
+                    aws.session_token.clone(),
+                    None,
+                    "environment",
+                ));
+                let key_prefix: String = key.chars().take(8).collect();
+                let token = if aws.session_token.is_some() { " + session token" } else { "" };
+                format!("access key {}...{}", key_prefix, token)
+            }
+            _ => "default provider chain".to_string(),
+        };
+        info!("🔑 AWS credentials: {}", source);
+        
+        if let Some(region) = &aws.region {
+            loader = loader.region(Region::new(region.clone()));
+        }
+        if let Some(endpoint_url) = &aws.endpoint_url {
+            info!("🧭 Rekognition endpoint overridden: {}", endpoint_url);
+            loader = loader.endpoint_url(endpoint_url);
+        }
+        
+        let config = loader.load().await;
+        let region = config.region().map(|region| region.to_string());
+        info!("🌍 AWS Region: {}", region.as_deref().unwrap_or("(none)"));
+        
+        info!("🎚️ Face quality filter: {}", quality_filter.as_str());
+        
+        Self {
+            client: RekognitionClient::new(&config),
+            collection_id: collection.id,
+            collection_tags: collection.tags,
+            auto_create: collection.auto_create,
+            region,
+            quality_filter,
+        }
+    }
+}
+
+fn face_box(bounds: BoundingBox) -> FaceBox {
+    FaceBox {
+        left: bounds.left.unwrap_or(0.0),
+        top: bounds.top.unwrap_or(0.0),
+        width: bounds.width.unwrap_or(0.0),
+        height: bounds.height.unwrap_or(0.0),
+    }
+}
+
+impl From<FaceDetail> for DetectedFace {
+    fn from(detail: FaceDetail) -> Self {
+        let dominant_emotion = detail
+            .emotions
+            .unwrap_or_default()
+            .into_iter()
+            .max_by(|a, b| a.confidence.unwrap_or(0.0).total_cmp(&b.confidence.unwrap_or(0.0)))
+            .and_then(|emotion| emotion.r#type)
+            .map(|emotion| emotion.as_str().to_lowercase());
+        let (brightness, sharpness) = detail
+            .quality
+            .map_or((None, None), |quality| (quality.brightness, quality.sharpness));
+        
+        DetectedFace {
+            bounding_box: detail.bounding_box.map(face_box),
+            pose: detail.pose.map(|pose| FacePose {
+                yaw: pose.yaw.unwrap_or(0.0),
+                pitch: pose.pitch.unwrap_or(0.0),
+                roll: pose.roll.unwrap_or(0.0),
+            }),
+            brightness,
+            sharpness,
+            eyes_open: detail.eyes_open.map(|eyes| eyes.value),
+            attributes: FaceAttributes {
+                age_low: detail.age_range.as_ref().and_then(|range| range.low),
+                age_high: detail.age_range.as_ref().and_then(|range| range.high),
+                smiling: detail.smile.map(|smile| smile.value),
+                dominant_emotion,
+            },
+        }
+    }
+}
+
+fn image(image_data: &Bytes) -> Image {
+    Image::builder()
+        .bytes(image_data.to_vec().into())
+        .build()
+}
+
+#[async_trait]
+impl FaceRecognizer for RekognitionRecognizer {
+    async fn ensure_collection(&self) -> Result<()> {
+        // A collection belongs to one region; never guess which
+        let Some(region) = &self.region else {
+            anyhow::bail!(
+                "No AWS region configured for collection '{}' - set AWS_REGION",
+                self.collection_id
+            );
+        };
+        info!("🔍 Checking collection '{}' in {}...", self.collection_id, region);
+        
+        match self
+            .client
+            .describe_collection()
+            .collection_id(&self.collection_id)
+            .send()
+            .await
+        {
+            Ok(_) => {
+                info!("✅ Collection '{}' exists", self.collection_id);
+            }
+            // Anything but "not found" (bad credentials, no network) must
+            // not lead to a new, empty collection
+            Err(e) if !e.as_service_error().is_some_and(|e| e.is_resource_not_found_exception()) => {
+                return Err(RekognitionError::from(e).into());
+            }
+            Err(_) if !self.auto_create => {
+                anyhow::bail!(
+                    "Collection '{}' does not exist in {} and COLLECTION_AUTO_CREATE is off - check \
+                     COLLECTION_ID and AWS_REGION",
+                    self.collection_id,
+                    region
+                );
+            }
+            Err(_) => {
+                info!("🏗️ Creating collection '{}' in {}...", self.collection_id, region);
+                
+                self.client
+                    .create_collection()
+                    .collection_id(&self.collection_id)
+                    .set_tags((!self.collection_tags.is_empty()).then(|| self.collection_tags.clone()))
+                    .send()
+                    .await
+                    .map_err(RekognitionError::from)?;
+                
+                info!(
+                    "✅ Created collection '{}' with {} tag(s)",
+                    self.collection_id,
+                    self.collection_tags.len()
+                );
+            }
+        }
+        
+        Ok(())
+    }
+    
+    /// Follows `next_token`, since one page holds at most 1000 faces.
+    async fn list_faces(&self) -> Result<Vec<StoredFace>> {
+        let mut faces = Vec::new();
+        let mut next_token = None;
+        
+        for page in 1..=LIST_FACES_MAX_PAGES {
+            let response = self
+                .client
+                .list_faces()
+                .collection_id(&self.collection_id)
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(RekognitionError::from)?;
+            
+            faces.extend(response.faces.unwrap_or_default().into_iter().filter_map(|face| {
+                Some(StoredFace {
+                    face_id: face.face_id?,
+                    external_id: face.external_image_id?,
+                })
+            }));
+            
+            match response.next_token {
+                Some(token) if !token.is_empty() => next_token = Some(token),
+                _ => {
+                    info!("👥 Listed {} face(s) from '{}' in {} page(s)", faces.len(), self.collection_id, page);
+                    return Ok(faces);
+                }
+            }
+        }
+        
+        warn!(
+            "⚠️ Stopped listing '{}' after {} pages ({} faces) - the rest of the collection was not loaded",
+            self.collection_id,
+            LIST_FACES_MAX_PAGES,
+            faces.len()
+        );
+        Ok(faces)
+    }
+    
+    async fn describe_collection(&self) -> Result<CollectionInfo> {
+        let response = with_throttle_retry("describe_collection", || async {
+            self.client
+                .describe_collection()
+                .collection_id(&self.collection_id)
+                .send()
+                .await
+                .map_err(RekognitionError::from)
+        })
+        .await?;
+        
+        Ok(CollectionInfo {
+            face_count: response.face_count.unwrap_or(0).max(0) as u64,
+            face_model_version: response.face_model_version,
+            arn: response.collection_arn,
+        })
+    }
+    
+    async fn index_face(&self, external_id: &str, image_data: &Bytes) -> Result<Option<String>> {
+        let image = image(image_data);
+        
+        let response = with_throttle_retry("index_faces", || async {
+            self.client
+                .index_faces()
+                .collection_id(&self.collection_id)
+                .image(image.clone())
+                .external_image_id(external_id)
+                .max_faces(1)
+                .quality_filter(self.quality_filter.clone())
+                .send()
+                .await
+                .map_err(RekognitionError::from)
+        })
+        .await?;
+        
+        Ok(response
+            .face_records
+            .and_then(|records| records.into_iter().next())
+            .and_then(|record| record.face)
+            .and_then(|face| face.face_id))
+    }
+    
+    async fn search_candidates(&self, image_data: &Bytes, threshold: f32, max_faces: i32) -> Result<Vec<FaceMatch>> {
+        let image = image(image_data);
+        
+        let response = with_throttle_retry("search_faces_by_image", || async {
+            self.client
+                .search_faces_by_image()
+                .collection_id(&self.collection_id)
+                .image(image.clone())
+                .max_faces(max_faces)
+                .face_match_threshold(threshold)
+                .quality_filter(self.quality_filter.clone())
+                .send()
+                .await
+                .map_err(RekognitionError::from)
+        })
+        .await?;
+        
+        let searched_face = response.searched_face_bounding_box.map(face_box);
+        Ok(response
+            .face_matches
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|face_match| {
+                let face = face_match.face?;
+                Some(FaceMatch {
+                    face_id: face.face_id.unwrap_or_default(),
+                    external_id: face.external_image_id?,
+                    similarity: face_match.similarity?,
+                    searched_face: searched_face.clone(),
+                })
+            })
+            .collect())
+    }
+    
+    async fn delete_faces(&self, face_ids: &[String]) -> Result<usize> {
+        let response = self
+            .client
+            .delete_faces()
+            .collection_id(&self.collection_id)
+            .set_face_ids(Some(face_ids.to_vec()))
+            .send()
+            .await
+            .map_err(RekognitionError::from)?;
+        
+        Ok(response.deleted_faces.map(|faces| faces.len()).unwrap_or(0))
+    }
+    
+    async fn detect_faces(&self, image_data: &Bytes) -> Result<Vec<DetectedFace>> {
+        let image = image(image_data);
+        
+        let response = with_throttle_retry("detect_faces", || async {
+            self.client
+                .detect_faces()
+                .image(image.clone())
+                .attributes(Attribute::All)
+                .send()
+                .await
+                .map_err(RekognitionError::from)
+        })
+        .await?;
+        
+        Ok(response
+            .face_details
+            .unwrap_or_default()
+            .into_iter()
+            .map(DetectedFace::from)
+            .collect())
+    }
+    
+    async fn is_healthy(&self) -> bool {
+        self.client
+            .describe_collection()
+            .collection_id(&self.collection_id)
+            .send()
+            .await
+            .is_ok()
+    }
+}
+
+/// Similarity reported for every dry-run match.
+const DRY_RUN_SIMILARITY: f32 = 99.0;
+
+/// Where the one dry-run face sits, for detection and search alike.
+fn dry_run_face_box() -> FaceBox {
+    FaceBox {
+        left: 0.3,
+        top: 0.2,
+        width: 0.4,
+        height: 0.5,
+    }
+}
+
+/// Offline stand-in for Rekognition. Every image contains one sharp,
+/// frontal face, and every search matches the earliest-enrolled face in
+/// the collection, so the whole flow can be exercised without AWS.
+#[derive(Debug, Default)]
+pub(crate) struct DryRunRecognizer {
+    /// Keyed by insertion order so "first enrolled" stays deterministic.
+    faces: Mutex<BTreeMap<u64, StoredFace>>,
+    next_key: Mutex<u64>,
+}
+
+impl DryRunRecognizer {
+    /// Seeds the collection with faces restored from the database, since
+    /// nothing else remembers them between dry runs.
+    pub(crate) fn new(faces: impl IntoIterator<Item = StoredFace>) -> Self {
+        let recognizer = Self::default();
+        for face in faces {
+            recognizer.insert(face);
+        }
+        recognizer
+    }
+    
+    fn insert(&self, face: StoredFace) {
+        let mut next_key = self.next_key.lock().unwrap();
+        self.faces.lock().unwrap().insert(*next_key, face);
+        *next_key += 1;
+    }
+}
+
+#[async_trait]
+impl FaceRecognizer for DryRunRecognizer {
+    async fn ensure_collection(&self) -> Result<()> {
+        info!("🧪 Dry run - using an in-memory face collection");
+        Ok(())
+    }
+    
+    async fn list_faces(&self) -> Result<Vec<StoredFace>> {
+        Ok(self.faces.lock().unwrap().values().cloned().collect())
+    }
+    
+    async fn describe_collection(&self) -> Result<CollectionInfo> {
+        Ok(CollectionInfo {
+            face_count: self.faces.lock().unwrap().len() as u64,
+            face_model_version: Some("dry-run".to_string()),
+            arn: None,
+        })
+    }
+    
+    async fn index_face(&self, external_id: &str, _image_data: &Bytes) -> Result<Option<String>> {
+        let face_id = uuid::Uuid::new_v4().to_string();
+        info!("🧪 Dry run - indexed {} as face {}", external_id, face_id);
+        self.insert(StoredFace {
+            face_id: face_id.clone(),
+            external_id: external_id.to_string(),
+        });
+        Ok(Some(face_id))
+    }
+    
+    async fn search_candidates(&self, _image_data: &Bytes, threshold: f32, max_faces: i32) -> Result<Vec<FaceMatch>> {
+        if DRY_RUN_SIMILARITY < threshold {
+            return Ok(Vec::new());
+        }
+        
+        Ok(self
+            .faces
+            .lock()
+            .unwrap()
+            .values()
+            .take(max_faces.max(0) as usize)
+            .map(|face| FaceMatch {
+                face_id: face.face_id.clone(),
+                external_id: face.external_id.clone(),
+                similarity: DRY_RUN_SIMILARITY,
+                searched_face: Some(dry_run_face_box()),
+            })
+            .collect())
+    }
+    
+    async fn delete_faces(&self, face_ids: &[String]) -> Result<usize> {
+        let mut faces = self.faces.lock().unwrap();
+        let before = faces.len();
+        faces.retain(|_, face| !face_ids.contains(&face.face_id));
+        Ok(before - faces.len())
+    }
+    
+    async fn detect_faces(&self, _image_data: &Bytes) -> Result<Vec<DetectedFace>> {
+        Ok(vec![DetectedFace {
+            bounding_box: Some(dry_run_face_box()),
+            pose: Some(FacePose {
+                yaw: 0.0,
+                pitch: 0.0,
+                roll: 0.0,
+            }),
+            brightness: Some(70.0),
+            sharpness: Some(90.0),
+            eyes_open: Some(true),
+            attributes: FaceAttributes::default(),
+        }])
+    }
+    
+    async fn is_healthy(&self) -> bool {
+        true
+    }
+}
+
+/// Attempts per Rekognition call while AWS reports throttling, on top of
+/// the SDK's own retries.
+const REKOGNITION_MAX_ATTEMPTS: u32 = 4;
+const REKOGNITION_BACKOFF_MS: u64 = 250;
+
+/// Runs a Rekognition call, backing off and retrying while AWS throttles
+/// it. Any other error is returned straight away.
+async fn with_throttle_retry<T, F, Fut>(operation: &'static str, mut call: F) -> Result<T, RekognitionError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, RekognitionError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(e) if error::is_throttling(&e) => {
+                telemetry::record_rekognition_throttle(operation);
+                if attempt >= REKOGNITION_MAX_ATTEMPTS {
+                    warn!("🐢 Rekognition {} still throttled after {} attempts", operation, attempt);
+                    return Err(e);
+                }
+                
+                let delay = backoff_delay(REKOGNITION_BACKOFF_MS, attempt);
+                warn!("🐢 Rekognition throttled {} - retrying in {}ms", operation, delay.as_millis());
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
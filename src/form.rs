@@ -0,0 +1,75 @@
+use axum::extract::multipart::Field;
+use bytes::{Bytes, BytesMut};
+
+use crate::error::ApiError;
+
+/// Longest person or guest name accepted, in characters.
+pub(crate) const MAX_NAME_LENGTH: usize = 100;
+
+/// Longest value accepted in any other text field (PINs, camera ids,
+/// session ids), in bytes. Nothing legitimate comes close.
+pub(crate) const MAX_TEXT_LENGTH: usize = 256;
+
+/// Reads a text field, refusing it as soon as it grows past `max_len`
+/// bytes rather than buffering the whole thing first.
+pub(crate) async fn text(mut field: Field<'_>, max_len: usize) -> Result<String, ApiError> {
+    let name = field.name().unwrap_or("").to_string();
+    let mut buffer = BytesMut::new();
+    
+    while let Some(chunk) = field.chunk().await? {
+        if buffer.len() + chunk.len() > max_len {
+            return Err(ApiError::InvalidField(format!("'{}' is longer than {} bytes", name, max_len)));
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    
+    String::from_utf8(buffer.to_vec()).map_err(|_| ApiError::InvalidField(format!("'{}' is not valid UTF-8", name)))
+}
+
+/// Reads a `name` field, capped at [`MAX_NAME_LENGTH`] characters.
+pub(crate) async fn name(field: Field<'_>) -> Result<String, ApiError> {
+    // Up to four bytes a character; the exact count is checked below
+    let name = text(field, MAX_NAME_LENGTH * 4).await?;
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(ApiError::InvalidField(format!(
+            "'name' is longer than {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    validate_name(&name)?;
+    
+    Ok(name)
+}
+
+/// Names are stored as the Rekognition `ExternalImageId`, which only takes
+/// letters, digits and `_.-:`. Checked up front, since Rekognition's own
+/// rejection is indistinguishable from other bad parameters.
+pub(crate) fn validate_name(name: &str) -> Result<(), ApiError> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-' | ':');
+    if name.is_empty() || !name.chars().all(allowed) {
+        return Err(ApiError::InvalidField(format!(
+            "'name' may only contain letters, digits and _ . - : (got '{}')",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Reads an image field, which must declare an `image/*` content type.
+/// The bytes themselves are still checked by [`crate::images::normalize`].
+pub(crate) async fn image(field: Field<'_>) -> Result<Bytes, ApiError> {
+    let name = field.name().unwrap_or("").to_string();
+    match field.content_type() {
+        Some(content_type) if content_type.starts_with("image/") => Ok(field.bytes().await?),
+        Some(content_type) => Err(ApiError::InvalidField(format!(
+            "'{}' must be an image, not {}",
+            name, content_type
+        ))),
+        None => Err(ApiError::InvalidField(format!("'{}' has no content type", name))),
+    }
+}
+
+/// A field the route doesn't take; refused so typos don't go unnoticed.
+pub(crate) fn unexpected(field: &Field<'_>) -> ApiError {
+    ApiError::InvalidField(format!("unexpected field '{}'", field.name().unwrap_or("")))
+}
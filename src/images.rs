@@ -0,0 +1,131 @@
+use bytes::Bytes;
+use image::{
+    codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader,
+};
+use std::io::Cursor;
+
+use crate::{error::ApiError, FaceBox};
+
+/// Longest edge sent to Rekognition; larger photos only cost bandwidth.
+const MAX_DIMENSION: u32 = 4096;
+
+/// Rekognition rejects inline image bytes above 5 MB.
+const MAX_ENCODED_BYTES: usize = 5 * 1024 * 1024;
+
+/// JPEG qualities tried in order until the encoded image fits.
+const JPEG_QUALITIES: &[u8] = &[90, 80, 70, 60];
+
+/// Margin kept around a face when it is cropped out, as a fraction of the
+/// face's size on each side, so the crop still reads as a face.
+const CROP_MARGIN: f32 = 0.5;
+
+/// Formats we decode; anything else is `UNSUPPORTED_IMAGE_FORMAT` rather
+/// than an opaque `InvalidImageFormatException` from Rekognition.
+const ACCEPTED_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Jpeg,
+    ImageFormat::Png,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+    ImageFormat::Bmp,
+];
+
+/// The upload's format, from its magic bytes rather than the client's
+/// file name or content type.
+pub(crate) fn detect_format(data: &[u8]) -> Option<ImageFormat> {
+    image::guess_format(data).ok()
+}
+
+/// The MIME type matching the data's magic bytes, for serving it back.
+pub(crate) fn mime_type(data: &[u8]) -> &'static str {
+    detect_format(data).map_or("application/octet-stream", |format| format.to_mime_type())
+}
+
+/// Decodes an upload, applies its EXIF orientation, caps its size and
+/// re-encodes it as a JPEG Rekognition will accept. Every accepted format
+/// is transcoded, JPEG and PNG included, so orientation is always applied.
+/// CPU-bound; call it from a blocking task.
+pub(crate) fn normalize(data: &[u8]) -> Result<Bytes, ApiError> {
+    let format = match detect_format(data) {
+        Some(format) if ACCEPTED_FORMATS.contains(&format) => format,
+        Some(format) => {
+            return Err(ApiError::UnsupportedImageFormat(format!(
+                "{:?} is not supported - upload a JPEG, PNG or WebP",
+                format
+            )))
+        }
+        None => {
+            return Err(ApiError::UnsupportedImageFormat(
+                "not a recognized image - upload a JPEG, PNG or WebP".to_string(),
+            ))
+        }
+    };
+    
+    let mut decoder = ImageReader::with_format(Cursor::new(data), format)
+        .into_decoder()
+        .map_err(decode_error)?;
+    let orientation = decoder.orientation().map_err(decode_error)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(decode_error)?;
+    image.apply_orientation(orientation);
+    
+    if image.width() > MAX_DIMENSION || image.height() > MAX_DIMENSION {
+        image = image.resize(MAX_DIMENSION, MAX_DIMENSION, FilterType::Triangle);
+    }
+    
+    loop {
+        let rgb = image.to_rgb8();
+        for &quality in JPEG_QUALITIES {
+            let mut encoded = Vec::new();
+            JpegEncoder::new_with_quality(&mut encoded, quality)
+                .encode_image(&rgb)
+                .map_err(|e| ApiError::Internal(format!("Failed to encode image: {}", e)))?;
+            if encoded.len() <= MAX_ENCODED_BYTES {
+                return Ok(Bytes::from(encoded));
+            }
+        }
+        
+        // Still too large at the lowest quality: halve the resolution and retry
+        image = image.resize(image.width() / 2, image.height() / 2, FilterType::Triangle);
+    }
+}
+
+/// Cuts the face at `bounds` out of a normalized image, with a margin, so
+/// it can be searched on its own. CPU-bound; call it from a blocking task.
+pub(crate) fn crop(data: &[u8], bounds: &FaceBox) -> Result<Bytes, ApiError> {
+    let image = image::load_from_memory(data).map_err(decode_error)?;
+    let (width, height) = (image.width() as f32, image.height() as f32);
+    let margin_x = bounds.width * CROP_MARGIN;
+    let margin_y = bounds.height * CROP_MARGIN;
+    
+    let left = ((bounds.left - margin_x).max(0.0) * width) as u32;
+    let top = ((bounds.top - margin_y).max(0.0) * height) as u32;
+    let right = ((bounds.left + bounds.width + margin_x).min(1.0) * width) as u32;
+    let bottom = ((bounds.top + bounds.height + margin_y).min(1.0) * height) as u32;
+    if right <= left || bottom <= top {
+        return Err(ApiError::InvalidImage("face box lies outside the image".to_string()));
+    }
+    
+    let face = image.crop_imm(left, top, right - left, bottom - top).to_rgb8();
+    let mut encoded = Vec::new();
+    JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITIES[0])
+        .encode_image(&face)
+        .map_err(|e| ApiError::Internal(format!("Failed to encode image: {}", e)))?;
+    Ok(Bytes::from(encoded))
+}
+
+/// A format we recognize but were built without is unsupported; anything
+/// else means the bytes are corrupt.
+fn decode_error(error: ImageError) -> ApiError {
+    match error {
+        ImageError::Unsupported(e) => ApiError::UnsupportedImageFormat(e.to_string()),
+        e => ApiError::InvalidImage(e.to_string()),
+    }
+}
+
+/// Width and height read from the image header, without decoding pixels.
+pub(crate) fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
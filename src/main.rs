@@ -1,34 +1,230 @@
+// Guards on the shared std locks must never live across an .await
+#![deny(clippy::await_holding_lock)]
+
+mod alerts;
+mod auth;
+mod backup;
+mod bulk;
+mod client_ip;
+mod config;
+mod dashboard;
+mod disconnect;
+mod devices;
+mod error;
+mod export;
+mod face;
+mod form;
+mod images;
+mod mqtt;
+mod pins;
+mod prefilter;
+mod ratelimit;
+mod request_id;
+mod selftest;
+mod snapshots;
+mod store;
+mod telemetry;
+mod tls;
+mod version;
+
 use anyhow::Result;
 use axum::{
-    extract::{Multipart, State},
-    http::StatusCode,
-    response::{Html, Json},
-    routing::{get, post},
+    body::Body,
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Extension, FromRequest, Multipart, Path, Query, Request, State,
+    },
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Json, Response,
+    },
+    routing::{delete, get, post, put},
     Router,
 };
-use aws_config::BehaviorVersion;
-use aws_sdk_rekognition::{
-    types::{Image, QualityFilter},
-    Client as RekognitionClient,
-};
 use bytes::Bytes;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use futures::{future, Stream, StreamExt};
+use hmac::{Hmac, Mac};
+use metrics_exporter_prometheus::PrometheusHandle;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    convert::Infallible,
     env,
-    sync::{Arc, Mutex},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Notify};
+use tower::ServiceExt;
+use tower_http::{
+    cors::CorsLayer,
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::{ServeDir, ServeFile},
+    trace::TraceLayer,
 };
-use tower_http::cors::CorsLayer;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::{error::ApiError, store::Store};
+
+/// How many of the most recent persisted log entries are loaded back into
+/// memory on startup.
+const STARTUP_LOG_HISTORY: usize = 1000;
+
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct AccessLog {
     timestamp: DateTime<Utc>,
     action: String,
     person_name: Option<String>,
+    /// 0-1, see `similarity_to_confidence`.
     confidence: Option<f32>,
     access_granted: bool,
+    attributes: Option<FaceAttributes>,
+    deny_reason: Option<DenyReason>,
+    snapshot: Option<String>,
+    /// The HTTP request that produced this entry, if any.
+    #[serde(default)]
+    request_id: Option<String>,
+    /// Who sent that request; see `TRUST_PROXY`.
+    #[serde(default)]
+    client_ip: Option<String>,
+    /// Whether the door opened after this grant; see `DOOR_CONFIRM`.
+    #[serde(default)]
+    door_confirmed: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DenyReason {
+    NotRecognized,
+    NotInRegistry,
+    LivenessFailed,
+    SpoofSuspected,
+    OutsideSchedule,
+    LockedOut,
+    PinMismatch,
+    LowConfidence,
+    GuestExpired,
+    Lockdown,
+    MultipleFaces,
+    RecognitionUnavailable,
+    AmbiguousMatch,
+    PendingApproval,
+}
+
+impl DenyReason {
+    fn code(&self) -> &'static str {
+        match self {
+            DenyReason::NotRecognized => "not_recognized",
+            DenyReason::NotInRegistry => "not_in_registry",
+            DenyReason::LivenessFailed => "liveness_failed",
+            DenyReason::SpoofSuspected => "spoof_suspected",
+            DenyReason::OutsideSchedule => "outside_schedule",
+            DenyReason::LockedOut => "locked_out",
+            DenyReason::PinMismatch => "pin_mismatch",
+            DenyReason::LowConfidence => "low_confidence",
+            DenyReason::GuestExpired => "guest_expired",
+            DenyReason::Lockdown => "lockdown",
+            DenyReason::MultipleFaces => "multiple_faces",
+            DenyReason::RecognitionUnavailable => "recognition_unavailable",
+            DenyReason::AmbiguousMatch => "ambiguous_match",
+            DenyReason::PendingApproval => "pending_approval",
+        }
+    }
+    
+    fn message(&self) -> &'static str {
+        match self {
+            DenyReason::NotRecognized => "Face not recognized",
+            DenyReason::NotInRegistry => "Face not in local registry",
+            DenyReason::LivenessFailed => "liveness failed",
+            DenyReason::SpoofSuspected => "Liveness check failed - possible spoof",
+            DenyReason::OutsideSchedule => "Outside allowed schedule",
+            DenyReason::LockedOut => "Too many failed attempts - locked out",
+            DenyReason::PinMismatch => "Face matched but PIN incorrect or not set",
+            DenyReason::LowConfidence => "Match below the required confidence",
+            DenyReason::GuestExpired => "Guest access has expired",
+            DenyReason::Lockdown => "Lockdown active",
+            DenyReason::MultipleFaces => "More than one person at the door",
+            DenyReason::RecognitionUnavailable => "Face recognition is unavailable",
+            DenyReason::AmbiguousMatch => "Face matches more than one person too closely",
+            DenyReason::PendingApproval => "Enrollment awaiting approval",
+        }
+    }
+    
+    /// Whether this denial counts towards the brute-force lockout. A known
+    /// person arriving outside their schedule, before their enrollment is
+    /// approved, or a guest after their access ran out, is not a failed
+    /// attempt; nor is anyone turned away during a lockdown, for bringing
+    /// company or while AWS is down.
+    fn counts_as_failure(&self) -> bool {
+        !matches!(
+            self,
+            DenyReason::OutsideSchedule
+                | DenyReason::LockedOut
+                | DenyReason::GuestExpired
+                | DenyReason::PendingApproval
+                | DenyReason::Lockdown
+                | DenyReason::MultipleFaces
+                | DenyReason::RecognitionUnavailable
+        )
+    }
+}
+
+impl std::fmt::Display for DenyReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FaceAttributes {
+    age_low: Option<i32>,
+    age_high: Option<i32>,
+    smiling: Option<bool>,
+    dominant_emotion: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum EnrollmentSource {
+    Upload,
+    Bulk,
+    /// Re-enrolled from photos in a backup.
+    Restore,
+    #[default]
+    Unknown,
+}
+
+/// What a person may do besides opening the door. Admins can also sign in
+/// by face through `/api/admin/verify` and manage everyone else.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PersonRole {
+    Admin,
+    #[default]
+    User,
+}
+
+/// Whether a face can open the door. With `ENROLL_REQUIRES_APPROVAL`, new
+/// faces are indexed as `pending` until an admin approves them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum PersonStatus {
+    #[default]
+    Active,
+    Pending,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,17 +233,383 @@ struct AuthorizedPerson {
     face_id: String,
     external_image_id: String,
     added_at: DateTime<Utc>,
+    #[serde(default)]
+    enrolled_via: EnrollmentSource,
+    #[serde(default)]
+    on_grant_webhook: Option<String>,
+    /// Emailed on every grant, alongside `on_grant_webhook`.
+    #[serde(default)]
+    notify_email: Option<String>,
+    /// Allowed access windows; `None` means access at any time.
+    #[serde(default)]
+    schedule: Option<Vec<AccessWindow>>,
+    /// Argon2 hash of the keypad PIN; never sent to clients.
+    #[serde(default, skip_serializing)]
+    pin_hash: Option<String>,
+    /// Similarity (0-100) this person's matches must reach; overrides the
+    /// global threshold when set.
+    #[serde(default)]
+    match_threshold: Option<f32>,
+    /// Set for temporary guests, whose faces are purged once it passes.
+    #[serde(default)]
+    expires_at: Option<DateTime<Utc>>,
+    /// Granted face matches so far, counted per person rather than per face.
+    #[serde(default)]
+    access_count: u64,
+    #[serde(default)]
+    last_seen: Option<DateTime<Utc>>,
+    #[serde(default)]
+    role: PersonRole,
+    /// Per face, unlike the settings above: a new photo of an approved
+    /// person still waits for approval.
+    #[serde(default)]
+    status: PersonStatus,
+    /// The caller who enrolled this face. `None` for faces found in the
+    /// collection rather than enrolled here.
+    #[serde(default)]
+    enrolled_by: Option<String>,
+}
+
+impl AuthorizedPerson {
+    fn new(name: &str, face_id: String, enrolled_via: EnrollmentSource) -> Self {
+        Self {
+            name: name.to_string(),
+            face_id,
+            external_image_id: name.to_string(),
+            added_at: Utc::now(),
+            enrolled_via,
+            on_grant_webhook: None,
+            notify_email: None,
+            schedule: None,
+            pin_hash: None,
+            match_threshold: None,
+            expires_at: None,
+            access_count: 0,
+            last_seen: None,
+            role: PersonRole::User,
+            status: PersonStatus::Active,
+            enrolled_by: None,
+        }
+    }
+    
+    /// Copies the per-person settings of `existing`, another face of the
+    /// same person, so a new face can't sidestep them. Status and role stay
+    /// per face.
+    fn inherit_settings(&mut self, existing: &AuthorizedPerson) {
+        self.on_grant_webhook = existing.on_grant_webhook.clone();
+        self.notify_email = existing.notify_email.clone();
+        self.schedule = existing.schedule.clone();
+        self.pin_hash = existing.pin_hash.clone();
+        self.match_threshold = existing.match_threshold;
+        self.expires_at = self.expires_at.or(existing.expires_at);
+        self.access_count = existing.access_count;
+        self.last_seen = existing.last_seen;
+    }
+    
+    fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+    
+    /// `now` is local time in `TIMEZONE`; no schedule means any time.
+    fn within_schedule(&self, now: DateTime<Tz>) -> bool {
+        match &self.schedule {
+            Some(schedule) => schedule.iter().any(|window| window.contains(now.weekday(), now.time())),
+            None => true,
+        }
+    }
+}
+
+/// A recurring weekly window, e.g. Mon–Fri 09:00:00–17:00:00, in the
+/// configured access timezone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AccessWindow {
+    days: Vec<Weekday>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl AccessWindow {
+    fn contains(&self, weekday: Weekday, time: NaiveTime) -> bool {
+        self.days.contains(&weekday) && time >= self.start && time < self.end
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum LivenessChallenge {
+    Blink,
+    Turn,
+}
+
+impl LivenessChallenge {
+    fn prompt(&self) -> &'static str {
+        match self {
+            LivenessChallenge::Blink => "Look at the camera and blink between the two frames",
+            LivenessChallenge::Turn => "Look at the camera, then turn your head to one side",
+        }
+    }
+}
+
+/// Which access events the global `WEBHOOK_URL` is notified about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WebhookEvents {
+    All,
+    Grants,
+    Denies,
+}
+
+impl WebhookEvents {
+    fn matches(&self, log_entry: &AccessLog) -> bool {
+        let denied = log_entry.deny_reason.is_some();
+        match self {
+            WebhookEvents::All => log_entry.access_granted || denied,
+            WebhookEvents::Grants => log_entry.access_granted,
+            WebhookEvents::Denies => denied,
+        }
+    }
+}
+
+/// Factors required before the door unlocks, from `AUTH_MODE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuthMode {
+    Face,
+    FacePin,
+}
+
+/// What to do when the frame holds more than one face, from
+/// `MULTI_FACE_POLICY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MultiFacePolicy {
+    /// Recognize the largest face and ignore the rest.
+    First,
+    /// Every face must belong to a known person; the largest one still
+    /// decides who is granted.
+    All,
+    /// Deny outright, so nobody can follow someone else in.
+    Deny,
+}
+
+/// What to do when Rekognition is down, from `AWS_OUTAGE_POLICY`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AwsOutagePolicy {
+    /// Fail the check, as for any other error.
+    Deny,
+    /// Let anyone in whose PIN matches, without a face.
+    AllowPin,
+    /// Deny and log each attempt, leaving the door to a manual override.
+    ManualOnly,
+}
+
+/// Where a recognition attempt came from; failed attempts are tracked
+/// separately per source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AccessSource {
+    Esp32,
+    Upload,
+}
+
+impl AccessSource {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AccessSource::Esp32 => "esp32",
+            AccessSource::Upload => "upload",
+        }
+    }
+}
+
+/// Which failures count together. The ESP32 camera is one shared client,
+/// so its lockout covers the whole source; uploads are tracked per client
+/// address so one caller can't lock everyone else out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct LockoutKey {
+    source: AccessSource,
+    client: Option<IpAddr>,
+}
+
+impl LockoutKey {
+    fn current(source: AccessSource) -> Self {
+        let client = match source {
+            AccessSource::Esp32 => None,
+            AccessSource::Upload => client_ip::current(),
+        };
+        Self { source, client }
+    }
+}
+
+impl std::fmt::Display for LockoutKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.client {
+            Some(ip) => write!(f, "{} ({})", self.source.as_str(), ip),
+            None => f.write_str(self.source.as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct FailureTracker {
+    failures: VecDeque<Instant>,
+    locked_until: Option<Instant>,
+}
+
+/// Messages for the background task that persists access logs.
+#[derive(Debug)]
+enum LogCommand {
+    Write(AccessLog),
+    /// Acknowledged once every earlier write has been persisted.
+    Flush(oneshot::Sender<()>),
 }
 
 #[derive(Debug, Clone)]
+struct LivenessSession {
+    challenge: LivenessChallenge,
+    expires_at: DateTime<Utc>,
+}
+
+/// The last command that reached a door, for debouncing and for
+/// `GET /api/door`.
+#[derive(Debug, Default)]
+struct DoorState {
+    unlocked: Option<bool>,
+    commanded_at: Option<Instant>,
+    since: Option<DateTime<Utc>>,
+    auto_relock_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DoorPosition {
+    Locked,
+    Unlocked,
+    /// No command has reached the door since startup and it can't report.
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DoorStatus {
+    camera: String,
+    state: DoorPosition,
+    since: Option<DateTime<Utc>>,
+    auto_relock_at: Option<DateTime<Utc>>,
+    /// Whether the door itself confirmed `state`, rather than it being the
+    /// last command sent.
+    reported: bool,
+}
+
+/// How much of the access log is kept, from the `LOG_RETENTION_*` settings.
+#[derive(Debug, Clone, Copy)]
+struct LogRetention {
+    max_entries: usize,
+    max_age: Option<chrono::Duration>,
+}
+
+/// Request body limits in bytes, from the `MAX_*UPLOAD_MB` settings.
+#[derive(Debug, Clone, Copy)]
+struct UploadLimits {
+    /// Enrollment photos and every route without its own limit.
+    default: usize,
+    /// Access checks, which carry one photo (two for liveness).
+    check: usize,
+    /// Bulk enrollment archives.
+    bulk: usize,
+}
+
+/// An ESP32-CAM and the Pico 2 door it watches.
+#[derive(Debug, Clone, Deserialize)]
+struct CameraConfig {
+    id: String,
+    capture_url: String,
+    door_url: String,
+}
+
+#[derive(Clone)]
 struct AppState {
-    rekognition_client: RekognitionClient,
-    collection_id: String,
-    access_log: Arc<Mutex<Vec<AccessLog>>>,
-    authorized_people: Arc<Mutex<HashMap<String, AuthorizedPerson>>>,
-    esp32_cam_url: String,
-    pico2_door_url: String,
-    confidence_threshold: f32,
+    face_recognizer: Arc<dyn face::FaceRecognizer>,
+    access_log: Arc<RwLock<Vec<AccessLog>>>,
+    authorized_people: Arc<RwLock<HashMap<String, AuthorizedPerson>>>,
+    cameras: Arc<HashMap<String, CameraConfig>>,
+    /// Used when a request doesn't name a camera: the first configured one.
+    default_camera: String,
+    confidence_threshold: Arc<Mutex<f32>>,
+    timezone: Tz,
+    door_control_enabled: bool,
+    capture_attributes: bool,
+    liveness_challenge: bool,
+    liveness_challenge_type: LivenessChallenge,
+    liveness_session_seconds: i64,
+    liveness_min_yaw_delta: f32,
+    liveness_sessions: Arc<Mutex<HashMap<String, LivenessSession>>>,
+    /// By token.
+    admin_sessions: Arc<Mutex<HashMap<String, AdminSession>>>,
+    store: Store,
+    log_writer: mpsc::UnboundedSender<LogCommand>,
+    door_unlock_seconds: u64,
+    /// Per camera id; an async mutex so a command holds its door while sending.
+    door_states: Arc<HashMap<String, tokio::sync::Mutex<DoorState>>>,
+    door_debounce: Duration,
+    /// How long to wait for the door to report itself open after an
+    /// unlock; `None` when `DOOR_CONFIRM` is off.
+    door_confirm_timeout: Option<Duration>,
+    /// `CLOCK_SKEW_TOLERANCE_SECONDS`: slack allowed either way on
+    /// timestamps devices send.
+    clock_skew_tolerance: chrono::Duration,
+    /// Time of the latest unlock per camera id, so a stale relock can tell
+    /// it was superseded.
+    last_unlock: Arc<Mutex<HashMap<String, Instant>>>,
+    doors: Arc<dyn devices::DoorController>,
+    api_keys: Vec<(String, String)>,
+    /// Shared secret cameras send to `POST /api/ingest`; ingest is off without it.
+    device_token: Option<String>,
+    enroll_min_quality: Option<f32>,
+    /// `ENROLL_REQUIRES_APPROVAL`: new faces start out pending.
+    enroll_requires_approval: bool,
+    protect_reads: bool,
+    rate_limiter: Option<Arc<ratelimit::RateLimiter>>,
+    events: broadcast::Sender<AccessLog>,
+    liveness_enabled: bool,
+    liveness_min_sharpness: f32,
+    liveness_max_pose_degrees: f32,
+    snapshots_enabled: bool,
+    snapshot_dir: PathBuf,
+    snapshot_retention_days: u64,
+    max_failed_attempts: usize,
+    fail_window: Duration,
+    lockout_duration: Duration,
+    failed_attempts: Arc<Mutex<HashMap<LockoutKey, FailureTracker>>>,
+    metrics: PrometheusHandle,
+    lock_on_shutdown: bool,
+    polling_enabled: Arc<AtomicBool>,
+    poll_interval: Duration,
+    lockdown: Arc<RwLock<Option<Lockdown>>>,
+    collection_cache: Arc<Mutex<Option<CachedCollection>>>,
+    selftest_image: Option<PathBuf>,
+    selftest_person: String,
+    cors_allowed_origins: Option<Vec<HeaderValue>>,
+    /// `DENY_MESSAGES` overrides, by reason.
+    deny_messages: Arc<HashMap<DenyReason, String>>,
+    upload_limits: UploadLimits,
+    log_retention: LogRetention,
+    /// Read the client address from `X-Forwarded-For`/`X-Real-IP`.
+    trust_proxy: bool,
+    /// Time between preview frames; `None` when `PREVIEW_ENABLED` is off.
+    preview_interval: Option<Duration>,
+    /// `LOCAL_PREFILTER`: frames without a local face never reach Rekognition.
+    prefilter: Option<prefilter::Prefilter>,
+    grant_debounce: Duration,
+    last_grants: Arc<Mutex<HashMap<(String, String), Instant>>>,
+    /// By caller and `Idempotency-Key`.
+    idempotent_enrollments: Arc<Mutex<HashMap<(String, String), IdempotentEnrollment>>>,
+    auth_mode: AuthMode,
+    multi_face_policy: MultiFacePolicy,
+    aws_outage_policy: AwsOutagePolicy,
+    /// `MATCH_MARGIN`; 0 when off.
+    match_margin: f32,
+    webhook_url: Option<String>,
+    webhook_secret: Option<String>,
+    webhook_events: WebhookEvents,
+    mqtt: Option<mqtt::Publisher>,
+    email_alerts: Option<Arc<alerts::EmailAlerter>>,
+    version: Arc<version::VersionInfo>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -55,744 +617,5388 @@ struct ApiResponse<T> {
     success: bool,
     data: Option<T>,
     error: Option<String>,
+    /// Stable machine-readable code accompanying `error`, e.g. `NO_FACE_DETECTED`.
+    error_code: Option<String>,
+    /// Same as the `X-Request-ID` response header; grep the logs for it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 struct AccessCheckResponse {
     access_granted: bool,
     person_name: Option<String>,
+    /// Match strength normalized to 0-1: `similarity / 100`.
     confidence: Option<f32>,
+    /// The raw similarity reported by the face engine, 0-100. Thresholds
+    /// (global and per-person) are on this scale.
+    similarity: Option<f32>,
     timestamp: DateTime<Utc>,
+    attributes: Option<FaceAttributes>,
+    deny_reason: Option<DenyReason>,
+    /// Human-readable text for `deny_reason`, fit to show at the door.
+    deny_message: Option<String>,
+    /// Seconds until a lockout clears, when denied for `locked_out`.
+    retry_after_seconds: Option<u64>,
+    /// Where the recognized face sits in the frame.
+    face: Option<FaceGeometry>,
+    /// Whether this check physically unlocked a door. A grant can still
+    /// leave it shut: door control disabled, a debounced repeat, or the
+    /// Pico 2 failing, in which case `error_code` says why.
+    door_opened: bool,
+    /// Why the door stayed shut after a grant, or `NO_FACE_IN_IMAGE` when
+    /// there was nobody to recognize.
+    error_code: Option<String>,
+    /// How many faces were found in the frame; 0 when none were, or when
+    /// the decision didn't run detection.
+    faces_detected: usize,
+    /// The local pre-filter saw no face, so Rekognition was never called.
+    prefiltered: bool,
+    /// With `DOOR_CONFIRM`, whether the door reported itself open after
+    /// unlocking; `None` when it wasn't checked.
+    door_confirmed: Option<bool>,
+}
+
+impl AccessCheckResponse {
+    fn no_face() -> Self {
+        Self {
+            access_granted: false,
+            person_name: None,
+            confidence: None,
+            similarity: None,
+            timestamp: Utc::now(),
+            attributes: None,
+            deny_reason: None,
+            deny_message: None,
+            retry_after_seconds: None,
+            face: None,
+            door_opened: false,
+            error_code: Some("NO_FACE_IN_IMAGE".to_string()),
+            faces_detected: 0,
+            prefiltered: false,
+            door_confirmed: None,
+        }
+    }
+    
+    /// No face was found, by the pre-filter, detection or the search.
+    fn is_no_face(&self) -> bool {
+        self.error_code.as_deref() == Some("NO_FACE_IN_IMAGE")
+    }
+}
+
+/// Box and head pose of the face a decision was made on. The box is in
+/// ratios of the image size, so scale by `image_width`/`image_height`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FaceGeometry {
+    bounding_box: Option<FaceBox>,
+    pose: Option<FacePose>,
+    image_width: Option<u32>,
+    image_height: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FaceBox {
+    left: f32,
+    top: f32,
+    width: f32,
+    height: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FacePose {
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
+}
+
+/// A face sign-in from `/api/admin/verify`, accepted in place of an API key.
+#[derive(Debug, Clone)]
+struct AdminSession {
+    name: String,
+    expires_at: DateTime<Utc>,
 }
 
 #[derive(Serialize, Deserialize)]
-struct AddPersonResponse {
-    face_id: String,
-    message: String,
+struct AdminSessionResponse {
+    /// Sent back in the `X-Admin-Session` header.
+    token: String,
+    name: String,
+    expires_at: DateTime<Utc>,
 }
 
-impl AppState {
-    async fn new() -> Result<Self> {
-        // Load environment variables FIRST
-        dotenvy::dotenv().expect("Failed to load .env file");
-        
-        // Verify credentials are loaded
-        let aws_key = env::var("AWS_ACCESS_KEY_ID").expect("AWS_ACCESS_KEY_ID must be set");
-        let aws_secret = env::var("AWS_SECRET_ACCESS_KEY").expect("AWS_SECRET_ACCESS_KEY must be set");
-        let aws_region = env::var("AWS_REGION").expect("AWS_REGION must be set");
-        
-        info!("🔑 AWS Key: {}...", &aws_key[..8]);
-        info!("🌍 AWS Region: {}", aws_region);
-        
-        info!("🦀 Initializing Rust AWS Rekognition Door Lock...");
-        
-        let config = aws_config::defaults(BehaviorVersion::latest())
-            .load()
-            .await;
-        
-        let rekognition_client = RekognitionClient::new(&config);
-        let collection_id = env::var("COLLECTION_ID").unwrap_or_else(|_| "smart-door-faces".to_string());
-        let esp32_cam_url = env::var("ESP32_CAM_CAPTURE_URL").unwrap_or_else(|_| "http://192.168.1.140/capture".to_string());
-        let pico2_door_url = env::var("PICO2_DOOR_URL").unwrap_or_else(|_| "http://192.168.1.141/door".to_string());
-        let confidence_threshold = env::var("CONFIDENCE_THRESHOLD")
-            .unwrap_or_else(|_| "75.0".to_string())
-            .parse::<f32>()
-            .unwrap_or(75.0);
-        
-        let state = AppState {
-            rekognition_client: rekognition_client.clone(),
-            collection_id: collection_id.clone(),
-            access_log: Arc::new(Mutex::new(Vec::new())),
-            authorized_people: Arc::new(Mutex::new(HashMap::new())),
-            esp32_cam_url,
-            pico2_door_url,
-            confidence_threshold,
-        };
-        
-        // Initialize collection
-        state.ensure_collection_exists().await?;
-        state.load_existing_faces().await?;
-        
-        Ok(state)
-    }
-    
-    async fn ensure_collection_exists(&self) -> Result<()> {
-        info!("🔍 Checking collection '{}'...", self.collection_id);
-        
-        match self
-            .rekognition_client
-            .describe_collection()
-            .collection_id(&self.collection_id)
-            .send()
-            .await
-        {
-            Ok(_) => {
-                info!("✅ Collection '{}' exists", self.collection_id);
-            }
-            Err(_) => {
-                info!("🏗️ Creating collection '{}'...", self.collection_id);
-                
-                self.rekognition_client
-                    .create_collection()
-                    .collection_id(&self.collection_id)
-                    .send()
-                    .await?;
-                
-                info!("✅ Created collection '{}'", self.collection_id);
-            }
+#[derive(Serialize, Deserialize)]
+struct LivenessStartResponse {
+    session_id: String,
+    challenge: LivenessChallenge,
+    prompt: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum DoorAction {
+    Unlock,
+    Lock,
+}
+
+impl DoorAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DoorAction::Unlock => "unlock",
+            DoorAction::Lock => "lock",
         }
-        
-        Ok(())
     }
-    
-    async fn load_existing_faces(&self) -> Result<()> {
-        info!("👥 Loading existing authorized faces...");
-        
-        let response = self
-            .rekognition_client
-            .list_faces()
-            .collection_id(&self.collection_id)
-            .send()
-            .await?;
-        
-        let mut people = self.authorized_people.lock().unwrap();
-        
-        if let Some(faces) = response.faces {
-            for face in faces {
-                if let (Some(face_id), Some(external_id)) = (face.face_id, face.external_image_id) {
-                    let person = AuthorizedPerson {
-                        name: external_id.clone(),
-                        face_id: face_id.clone(),
-                        external_image_id: external_id.clone(),
-                        added_at: Utc::now(),
-                    };
-                    people.insert(face_id, person);
-                }
-            }
+}
+
+#[derive(Deserialize)]
+struct DoorRequest {
+    action: DoorAction,
+    /// Camera id whose door to drive; the default camera when omitted.
+    #[serde(default)]
+    camera: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: export::ExportFormat,
+}
+
+#[derive(Deserialize)]
+struct CameraQuery {
+    camera: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DoorTestQuery {
+    action: DoorAction,
+    camera: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PreviewQuery {
+    camera: Option<String>,
+    /// An MJPEG stream instead of a single frame.
+    #[serde(default)]
+    stream: bool,
+}
+
+#[derive(Deserialize)]
+struct CameraTestQuery {
+    camera: Option<String>,
+    /// Return the captured frame itself instead of a JSON summary.
+    #[serde(default)]
+    image: bool,
+}
+
+#[derive(Deserialize)]
+struct DebugSearchQuery {
+    max_faces: Option<i32>,
+    /// Lowest similarity (0-100) to list; far below any real threshold by default.
+    threshold: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct RenamePersonRequest {
+    new_name: String,
+}
+
+#[derive(Deserialize)]
+struct SetThresholdRequest {
+    /// Similarity percentage, 0-100. `null` clears a per-person override.
+    threshold: Option<f32>,
+}
+
+#[derive(Deserialize)]
+struct SetRoleRequest {
+    role: PersonRole,
+}
+
+#[derive(Deserialize)]
+struct SetPinRequest {
+    pin: String,
+}
+
+#[derive(Deserialize)]
+struct PollingRequest {
+    enabled: bool,
+}
+
+/// An emergency lockdown: every door locked and every match denied until
+/// it is cleared. Persisted, so it survives a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Lockdown {
+    since: DateTime<Utc>,
+    by: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LockdownStatus {
+    active: bool,
+    since: Option<DateTime<Utc>>,
+    by: Option<String>,
+}
+
+impl From<Option<Lockdown>> for LockdownStatus {
+    fn from(lockdown: Option<Lockdown>) -> Self {
+        Self {
+            active: lockdown.is_some(),
+            since: lockdown.as_ref().map(|lockdown| lockdown.since),
+            by: lockdown.map(|lockdown| lockdown.by),
         }
-        
-        info!("✅ Loaded {} authorized faces", people.len());
-        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PollingStatus {
+    enabled: bool,
+    interval_ms: u64,
+}
+
+#[derive(Deserialize)]
+struct SetWebhookRequest {
+    url: String,
+}
+
+/// A person's own grant notifications; a missing target is cleared.
+#[derive(Serialize, Deserialize)]
+struct NotifyTargets {
+    #[serde(default)]
+    webhook_url: Option<String>,
+    #[serde(default)]
+    email: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SetScheduleRequest {
+    /// `null` clears the schedule (always allowed).
+    windows: Option<Vec<AccessWindow>>,
+}
+
+#[derive(Deserialize)]
+struct UnlockLockoutQuery {
+    /// Clears every source when omitted.
+    source: Option<AccessSource>,
+}
+
+#[derive(Deserialize)]
+struct LogsQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SyncedFace {
+    name: String,
+    face_id: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ResyncSummary {
+    added: Vec<SyncedFace>,
+    removed: Vec<SyncedFace>,
+    unchanged: usize,
+}
+
+/// An `Idempotency-Key` sent to `POST /api/add-person`, kept for
+/// `IDEMPOTENCY_TTL`.
+#[derive(Debug)]
+struct IdempotentEnrollment {
+    created: Instant,
+    /// The person the key was first used for.
+    name: String,
+    /// Set once the first request succeeds. Retries wait on the lock while
+    /// it runs, so a double submit never indexes the face twice.
+    response: Arc<tokio::sync::Mutex<Option<AddPersonResponse>>>,
+}
+
+/// The closest match for anyone other than the best match's person.
+#[derive(Debug, Clone)]
+struct RunnerUp {
+    name: String,
+    similarity: f32,
+}
+
+/// `AppState::judge_match`'s ruling on a search match.
+enum MatchVerdict {
+    /// Rekognition knows the face, the registry doesn't.
+    Untracked,
+    Denied(AuthorizedPerson, DenyReason),
+    /// Cleared by the registry; the PIN and the debounce come after.
+    Allowed(AuthorizedPerson),
+}
+
+/// Last `describe_collection` answer.
+#[derive(Debug, Clone)]
+struct CachedCollection {
+    fetched: Instant,
+    fetched_at: DateTime<Utc>,
+    info: face::CollectionInfo,
+}
+
+/// The face engine's view of the collection next to ours; `in_sync` is
+/// false when the face counts differ.
+#[derive(Serialize, Deserialize)]
+struct CollectionStats {
+    face_count: u64,
+    face_model_version: Option<String>,
+    collection_arn: Option<String>,
+    local_face_count: usize,
+    local_people_count: usize,
+    in_sync: bool,
+    fetched_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RemovePersonResponse {
+    name: String,
+    faces_deleted: usize,
+}
+
+/// Outcome of `POST /api/test/door`. A device failure is reported here
+/// rather than as an error, since finding it is the point of the test.
+#[derive(Serialize, Deserialize)]
+struct DoorTestResult {
+    camera: String,
+    action: DoorAction,
+    ok: bool,
+    latency_ms: u64,
+    error: Option<String>,
+}
+
+/// Outcome of `GET /api/test/camera`.
+#[derive(Serialize, Deserialize)]
+struct CameraTestResult {
+    camera: String,
+    ok: bool,
+    latency_ms: u64,
+    bytes: Option<usize>,
+    width: Option<u32>,
+    height: Option<u32>,
+    error: Option<String>,
+}
+
+/// Every candidate match for a photo, from `POST /api/debug/search`.
+#[derive(Serialize, Deserialize)]
+struct DebugSearchResponse {
+    /// Always `true`: the search neither opens a door nor logs an access.
+    debug: bool,
+    search_threshold: f32,
+    global_threshold: f32,
+    candidates: Vec<SearchCandidate>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SearchCandidate {
+    /// The registry name, or the Rekognition external id for a face the
+    /// registry doesn't know.
+    name: String,
+    face_id: String,
+    similarity: f32,
+    in_registry: bool,
+    /// The person's own threshold, or the global one.
+    required_similarity: f32,
+    /// Whether this similarity alone would be enough to let them in.
+    meets_threshold: bool,
+}
+
+/// A stored snapshot judged again, from `POST /api/snapshots/:id/recheck`.
+#[derive(Serialize, Deserialize)]
+struct RecheckResponse {
+    snapshot: String,
+    /// Whether the face would be let in now. The door, the PIN and the
+    /// debounce play no part.
+    access_granted: bool,
+    person_name: Option<String>,
+    /// The best match's similarity, when anyone reached the search threshold.
+    similarity: Option<f32>,
+    deny_reason: Option<DenyReason>,
+    /// `AUTH_MODE=face_pin`: a grant would still need the person's PIN.
+    pin_required: bool,
+    faces_detected: usize,
+    search_threshold: f32,
+    global_threshold: f32,
+    /// Everyone the search returned, best first.
+    candidates: Vec<SearchCandidate>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HealthResponse {
+    rekognition: String,
+    esp32_cam: String,
+    pico2: String,
+    status: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DailyUniqueVisitors {
+    date: NaiveDate,
+    unique_visitors: usize,
+    grants: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersonStats {
+    name: String,
+    grants: i64,
+    last_seen: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DailyAccessCounts {
+    date: NaiveDate,
+    grants: i64,
+    denies: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StatsResponse {
+    total_grants: i64,
+    total_denies: i64,
+    people: Vec<PersonStats>,
+    /// Local hour of day (0-23) with the most grants; `None` before any grant.
+    busiest_hour: Option<u32>,
+    /// The last `STATS_DAYS` local days, oldest first, including empty days.
+    daily: Vec<DailyAccessCounts>,
+}
+
+#[derive(Deserialize)]
+struct DailyUniqueQuery {
+    days: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersonListing {
+    name: String,
+    guest: bool,
+    expires_at: Option<DateTime<Utc>>,
+    role: PersonRole,
+    /// `active` once any of their faces is approved.
+    status: PersonStatus,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct AddPersonResponse {
+    face_id: String,
+    face_ids: Vec<String>,
+    photos_skipped: usize,
+    message: String,
+    /// Photos that enrolled but may match poorly, or were rejected outright.
+    quality_warnings: Vec<PhotoQuality>,
+    /// The new faces can't open the door until approved.
+    #[serde(default)]
+    pending_approval: bool,
+}
+
+/// Quality metrics for one enrollment photo, reported when any is off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PhotoQuality {
+    /// 1-based position of the photo in the request.
+    photo: usize,
+    brightness: Option<f32>,
+    sharpness: Option<f32>,
+    /// Share of the frame covered by the face box, 0-1.
+    face_area: f32,
+    yaw: Option<f32>,
+    pitch: Option<f32>,
+    issues: Vec<String>,
+    /// Not enrolled because it fell below `ENROLL_MIN_QUALITY`.
+    rejected: bool,
+}
+
+impl AppState {
+    async fn new(config: config::Config) -> Result<Self> {
+        let config::Config {
+            dry_run,
+            aws,
+            collection,
+            database_url,
+            cameras: camera_list,
+            confidence_threshold,
+            match_margin,
+            timezone,
+            door_control_enabled,
+            capture_attributes,
+            liveness_challenge,
+            liveness_challenge_type,
+            liveness_session_seconds,
+            liveness_min_yaw_delta,
+            liveness_enabled,
+            liveness_min_sharpness,
+            liveness_max_pose_degrees,
+            snapshots_enabled,
+            snapshot_dir,
+            snapshot_retention_days,
+            log_retention_max_entries,
+            log_retention_days,
+            trust_proxy,
+            event_buffer,
+            preview_enabled,
+            preview_max_fps,
+            local_prefilter,
+            local_prefilter_model,
+            max_failed_attempts,
+            fail_window_seconds,
+            lockout_seconds,
+            mut polling_enabled,
+            poll_interval_ms,
+            auth_mode,
+            multi_face_policy,
+            aws_outage_policy,
+            webhook_url,
+            webhook_secret,
+            webhook_events,
+            grant_debounce_seconds,
+            lock_on_shutdown,
+            door_unlock_seconds,
+            door_debounce_ms,
+            door_confirm,
+            door_confirm_timeout_ms,
+            clock_skew_tolerance_seconds,
+            http_timeout_seconds,
+            http_max_retries,
+            http_base_delay_ms,
+            max_upload_mb,
+            max_check_upload_mb,
+            max_bulk_upload_mb,
+            protect_reads,
+            device_token,
+            enroll_min_quality,
+            enroll_requires_approval,
+            email,
+            esp32_capture_params,
+            esp32_capture_mode,
+            quality_filter,
+            selftest_image,
+            selftest_person,
+            cors_allowed_origins,
+            deny_messages,
+            api_keys,
+            rate_limit_per_min,
+            rate_limit_key,
+            mqtt,
+            tls,
+            port: _,
+            http_redirect_port: _,
+            metrics_port: _,
+        } = config;
+        
+        // DRY_RUN simulates Rekognition and the door hardware, so no AWS
+        // credentials or devices are needed
+        let rekognition = if dry_run {
+            warn!("🧪 DRY_RUN enabled - Rekognition and door hardware are simulated");
+            None
+        } else {
+            Some(face::RekognitionRecognizer::new(&aws, collection, quality_filter).await)
+        };
+        let default_camera = camera_list[0].id.clone();
+        let cameras: HashMap<String, CameraConfig> = camera_list
+            .into_iter()
+            .map(|camera| (camera.id.clone(), camera))
+            .collect();
+        if polling_enabled && liveness_challenge {
+            warn!("⚠️ POLL_ENABLED ignored - polling cannot answer a liveness challenge");
+            polling_enabled = false;
+        }
+        if polling_enabled && auth_mode == AuthMode::FacePin {
+            warn!("⚠️ POLL_ENABLED ignored - polling cannot collect a PIN");
+            polling_enabled = false;
+        }
+        if cors_allowed_origins.is_none() {
+            warn!("⚠️ CORS_ALLOWED_ORIGINS not set - any website can call this API from a browser");
+        }
+        let (mqtt, mqtt_eventloop) = match mqtt.map(mqtt::connect).transpose()? {
+            Some((publisher, eventloop)) => (Some(publisher), Some(eventloop)),
+            None => (None, None),
+        };
+        
+        let email_alerts = email.map(alerts::EmailAlerter::new).transpose()?.map(Arc::new);
+        let prefilter = if local_prefilter {
+            let prefilter = prefilter::Prefilter::load(&local_prefilter_model).map_err(|e| {
+                anyhow::anyhow!(
+                    "LOCAL_PREFILTER_MODEL: '{}' could not be loaded ({})",
+                    local_prefilter_model.display(),
+                    e
+                )
+            })?;
+            info!("🔎 Local face pre-filter loaded from {}", local_prefilter_model.display());
+            Some(prefilter)
+        } else {
+            None
+        };
+        
+        let http_client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(http_timeout_seconds))
+            .build()?;
+        let doors: Arc<dyn devices::DoorController> = if dry_run {
+            Arc::new(devices::DryRunDoorController)
+        } else {
+            Arc::new(devices::HttpDoorController::new(
+                http_client,
+                http_max_retries,
+                http_base_delay_ms,
+                esp32_capture_params,
+                esp32_capture_mode,
+            ))
+        };
+        
+        if api_keys.is_empty() {
+            warn!("⚠️ No API_KEY/API_KEYS set - mutating endpoints are unprotected");
+        }
+        
+        if !door_control_enabled {
+            info!("🚫 Door control disabled - running in identify-only mode");
+        }
+        
+        let metrics = telemetry::install()?;
+        
+        info!("🗄️ Opening database {}", database_url);
+        let store = Store::open(&database_url).await?;
+        
+        let people: HashMap<String, AuthorizedPerson> = store
+            .all_people()
+            .await?
+            .into_iter()
+            .map(|person| (person.face_id.clone(), person))
+            .collect();
+        let lockdown: Option<Lockdown> = store.setting(LOCKDOWN_SETTING).await?;
+        let mut logs = store.recent_logs(STARTUP_LOG_HISTORY).await?;
+        logs.reverse();
+        info!("🗄️ Restored {} people and {} log entries", people.len(), logs.len());
+        
+        let face_recognizer: Arc<dyn face::FaceRecognizer> = match rekognition {
+            Some(rekognition) => Arc::new(rekognition),
+            None => Arc::new(face::DryRunRecognizer::new(people.values().map(|person| face::StoredFace {
+                face_id: person.face_id.clone(),
+                external_id: person.name.clone(),
+            }))),
+        };
+        
+        let (log_writer, mut log_receiver) = mpsc::unbounded_channel::<LogCommand>();
+        let writer_store = store.clone();
+        tokio::spawn(async move {
+            while let Some(command) = log_receiver.recv().await {
+                match command {
+                    LogCommand::Write(log_entry) => {
+                        if let Err(e) = writer_store.insert_log(&log_entry).await {
+                            warn!("Failed to persist access log: {}", e);
+                        }
+                    }
+                    LogCommand::Flush(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        
+        let state = AppState {
+            face_recognizer,
+            access_log: Arc::new(RwLock::new(logs)),
+            authorized_people: Arc::new(RwLock::new(people)),
+            door_states: Arc::new(
+                cameras
+                    .keys()
+                    .map(|id| (id.clone(), tokio::sync::Mutex::new(DoorState::default())))
+                    .collect(),
+            ),
+            door_debounce: Duration::from_millis(door_debounce_ms),
+            door_confirm_timeout: door_confirm.then(|| Duration::from_millis(door_confirm_timeout_ms)),
+            clock_skew_tolerance: chrono::Duration::seconds(clock_skew_tolerance_seconds),
+            cameras: Arc::new(cameras),
+            default_camera,
+            confidence_threshold: Arc::new(Mutex::new(confidence_threshold)),
+            timezone,
+            door_control_enabled,
+            capture_attributes,
+            liveness_challenge,
+            liveness_challenge_type,
+            liveness_session_seconds,
+            liveness_min_yaw_delta,
+            liveness_sessions: Arc::new(Mutex::new(HashMap::new())),
+            admin_sessions: Arc::new(Mutex::new(HashMap::new())),
+            store,
+            log_writer,
+            door_unlock_seconds,
+            last_unlock: Arc::new(Mutex::new(HashMap::new())),
+            doors,
+            api_keys,
+            device_token,
+            enroll_min_quality,
+            enroll_requires_approval,
+            rate_limiter: ratelimit::RateLimiter::new(rate_limit_per_min, rate_limit_key).map(Arc::new),
+            protect_reads,
+            events: broadcast::channel(event_buffer).0,
+            liveness_enabled,
+            liveness_min_sharpness,
+            liveness_max_pose_degrees,
+            snapshots_enabled,
+            snapshot_dir,
+            snapshot_retention_days,
+            max_failed_attempts,
+            fail_window: Duration::from_secs(fail_window_seconds),
+            lockout_duration: Duration::from_secs(lockout_seconds),
+            failed_attempts: Arc::new(Mutex::new(HashMap::new())),
+            metrics,
+            lock_on_shutdown,
+            polling_enabled: Arc::new(AtomicBool::new(polling_enabled)),
+            poll_interval: Duration::from_millis(poll_interval_ms),
+            lockdown: Arc::new(RwLock::new(lockdown.clone())),
+            collection_cache: Arc::new(Mutex::new(None)),
+            selftest_image,
+            selftest_person,
+            cors_allowed_origins,
+            deny_messages: Arc::new(deny_messages),
+            upload_limits: UploadLimits {
+                default: max_upload_mb * 1024 * 1024,
+                check: max_check_upload_mb * 1024 * 1024,
+                bulk: max_bulk_upload_mb * 1024 * 1024,
+            },
+            log_retention: LogRetention {
+                max_entries: log_retention_max_entries,
+                max_age: (log_retention_days > 0).then(|| chrono::Duration::days(log_retention_days as i64)),
+            },
+            trust_proxy,
+            preview_interval: preview_enabled.then(|| Duration::from_secs(1) / preview_max_fps),
+            grant_debounce: Duration::from_secs(grant_debounce_seconds),
+            last_grants: Arc::new(Mutex::new(HashMap::new())),
+            idempotent_enrollments: Arc::new(Mutex::new(HashMap::new())),
+            auth_mode,
+            multi_face_policy,
+            aws_outage_policy,
+            match_margin,
+            webhook_url,
+            webhook_secret,
+            webhook_events,
+            version: Arc::new(version::VersionInfo::new(version::Features {
+                dry_run,
+                liveness: liveness_enabled || liveness_challenge,
+                mqtt: mqtt.is_some(),
+                tls: tls.is_some(),
+                local_prefilter: prefilter.is_some(),
+            })),
+            prefilter,
+            mqtt,
+            email_alerts,
+        };
+        
+        if let Some(eventloop) = mqtt_eventloop {
+            tokio::spawn(mqtt::run(eventloop, state.clone()));
+        }
+        
+        if let Some(lockdown) = lockdown {
+            warn!("🚨 Lockdown still active (since {} by {}) - all access is denied", lockdown.since, lockdown.by);
+            let state = state.clone();
+            tokio::spawn(async move { state.lock_all_doors().await });
+        }
+        
+        // Initialize collection
+        state.ensure_collection_exists().await?;
+        state.load_existing_faces().await?;
+        
+        Ok(state)
+    }
+    
+    async fn ensure_collection_exists(&self) -> Result<()> {
+        self.face_recognizer.ensure_collection().await
+    }
+    
+    async fn load_existing_faces(&self) -> Result<()> {
+        info!("👥 Loading existing authorized faces...");
+        
+        let faces = self.face_recognizer.list_faces().await?;
+        
+        let mut discovered = Vec::new();
+        {
+            let mut people = self.authorized_people.write().unwrap();
+            
+            for face in faces {
+                if people.contains_key(&face.face_id) {
+                    continue;
+                }
+                
+                let person = self.discovered_face(&face, &people);
+                people.insert(face.face_id, person.clone());
+                discovered.push(person);
+            }
+            
+            info!("✅ Loaded {} authorized faces ({} new)", people.len(), discovered.len());
+        }
+        self.update_enrollment_gauge();
+        
+        for person in &discovered {
+            self.store.upsert_person(person).await?;
+        }
+        
+        Ok(())
+    }
+    
+    /// A record for a face found in the collection but not in the registry.
+    /// It takes the settings of anyone in `people` with the same name, so a
+    /// face indexed out-of-band doesn't escape their schedule or expiry.
+    /// Nobody here enrolled it, so it waits for approval like any other
+    /// enrollment under `ENROLL_REQUIRES_APPROVAL`.
+    fn discovered_face(&self, face: &face::StoredFace, people: &HashMap<String, AuthorizedPerson>) -> AuthorizedPerson {
+        let mut person = AuthorizedPerson::new(&face.external_id, face.face_id.clone(), EnrollmentSource::Unknown);
+        if let Some(existing) = people.values().find(|p| p.name == face.external_id) {
+            person.inherit_settings(existing);
+        }
+        if self.needs_approval() {
+            person.status = PersonStatus::Pending;
+        }
+        person
+    }
+    
+    /// Reconciles the registry with the face collection, which may have been
+    /// edited out-of-band: unknown faces are added, vanished ones removed.
+    async fn resync_faces(&self) -> Result<ResyncSummary> {
+        let faces = self.face_recognizer.list_faces().await?;
+        let collection: HashSet<&str> = faces.iter().map(|face| face.face_id.as_str()).collect();
+        
+        let (added, removed, unchanged) = {
+            let mut people = self.authorized_people.write().unwrap();
+            
+            let removed: Vec<AuthorizedPerson> = people
+                .values()
+                .filter(|person| !collection.contains(person.face_id.as_str()))
+                .cloned()
+                .collect();
+            for person in &removed {
+                people.remove(&person.face_id);
+            }
+            
+            let mut added = Vec::new();
+            for face in &faces {
+                if !people.contains_key(&face.face_id) {
+                    let person = self.discovered_face(face, &people);
+                    people.insert(face.face_id.clone(), person.clone());
+                    added.push(person);
+                }
+            }
+            
+            let unchanged = people.len() - added.len();
+            (added, removed, unchanged)
+        };
+        self.update_enrollment_gauge();
+        
+        for person in &added {
+            self.store.upsert_person(person).await?;
+        }
+        for person in &removed {
+            self.store.delete_person(&person.face_id).await?;
+        }
+        
+        info!(
+            "🔄 Re-synced with collection: {} added, {} removed, {} unchanged",
+            added.len(),
+            removed.len(),
+            unchanged
+        );
+        
+        let synced = |people: Vec<AuthorizedPerson>| {
+            people
+                .into_iter()
+                .map(|person| SyncedFace {
+                    name: person.name,
+                    face_id: person.face_id,
+                })
+                .collect()
+        };
+        Ok(ResyncSummary {
+            added: synced(added),
+            removed: synced(removed),
+            unchanged,
+        })
+    }
+    
+    /// Looks up a camera by id, falling back to the default camera.
+    fn camera(&self, id: Option<&str>) -> Result<CameraConfig, ApiError> {
+        let id = id.unwrap_or(&self.default_camera);
+        self.cameras
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ApiError::UnknownCamera(id.to_string()))
+    }
+    
+    fn default_camera(&self) -> CameraConfig {
+        self.cameras[&self.default_camera].clone()
+    }
+    
+    async fn capture_from_esp32(&self, camera: &CameraConfig) -> Result<Bytes> {
+        info!("📸 Capturing image from ESP32-CAM '{}' at {}", camera.id, camera.capture_url);
+        
+        let started = Instant::now();
+        let result = self.doors.capture(camera).await;
+        telemetry::observe_esp32_capture(started.elapsed(), result.is_ok());
+        let image_data = result.map_err(camera_error)?;
+        
+        match images::dimensions(&image_data) {
+            Some((width, height)) => info!("📐 Frame from '{}' is {}x{}", camera.id, width, height),
+            None => warn!("⚠️ Could not read the dimensions of the frame from '{}'", camera.id),
+        }
+        Ok(image_data)
+    }
+    
+    /// A live view of `camera` for `/api/camera/preview?stream=true`.
+    async fn preview_camera(&self, camera: &CameraConfig) -> Result<devices::FrameStream, ApiError> {
+        let interval = self.preview_interval.ok_or(ApiError::PreviewDisabled)?;
+        info!("📺 Streaming a preview of camera '{}', one frame per {:?}", camera.id, interval);
+        self.doors.preview(camera, interval).await.map_err(camera_error)
+    }
+    
+    /// Unlocks are for `door_unlock_seconds`: the Pico relocks on its own
+    /// even if the server dies first, and `schedule_relock` is the backup.
+    async fn control_pico2_door(&self, camera: &CameraConfig, unlock: bool) -> Result<()> {
+        let hold_open = unlock.then(|| Duration::from_secs(self.door_unlock_seconds));
+        self.command_door(camera, unlock, hold_open, false).await
+    }
+    
+    /// Sends a door command, one at a time per door. A command matching the
+    /// door's last state is dropped unless `force`d, and a change of state
+    /// waits out `door_debounce` so the relay never chatters.
+    async fn command_door(
+        &self,
+        camera: &CameraConfig,
+        unlock: bool,
+        hold_open: Option<Duration>,
+        force: bool,
+    ) -> Result<()> {
+        let action = if unlock { "unlock" } else { "lock" };
+        // Held across the send, so concurrent commands to a door queue up
+        // and each sees the state the previous one left behind
+        let mut door = match self.door_states.get(&camera.id) {
+            Some(door) => Some(door.lock().await),
+            None => None,
+        };
+        
+        if let Some(door) = &door {
+            if !force && door.unlocked == Some(unlock) {
+                debug!("🚪 Door '{}' already {}ed - skipping redundant command", camera.id, action);
+                return Ok(());
+            }
+            if let Some(commanded_at) = door.commanded_at {
+                let wait = self.door_debounce.saturating_sub(commanded_at.elapsed());
+                if !wait.is_zero() {
+                    debug!("🚪 Holding {} for door '{}' {}ms to let the relay settle", action, camera.id, wait.as_millis());
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+        
+        info!("🚪 Sending {} command to Pico 2 door '{}'", action, camera.id);
+        
+        let result = self.doors.set_door(camera, unlock, hold_open).await;
+        telemetry::record_door_command(action, result.is_ok());
+        result.map_err(|e| ApiError::DoorUnreachable(e.to_string()))?;
+        
+        if let Some(door) = &mut door {
+            let now = Utc::now();
+            door.unlocked = Some(unlock);
+            door.commanded_at = Some(Instant::now());
+            door.since = Some(now);
+            // Every successful unlock is followed by `schedule_relock`
+            door.auto_relock_at = unlock.then(|| now + chrono::Duration::seconds(self.door_unlock_seconds as i64));
+        }
+        
+        info!("✅ Pico 2 door {} successful", action);
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.door_state(self.door_topic(camera), unlock);
+        }
+        Ok(())
+    }
+    
+    /// The door's lock state. When its firmware reports one that differs
+    /// from the last command (say it was locked by hand), the report wins
+    /// and becomes the tracked state.
+    async fn door_status(&self, camera: &CameraConfig) -> DoorStatus {
+        // Held while asking, so a command in flight can't be mistaken for
+        // a mismatch
+        let mut door = self.door_states[&camera.id].lock().await;
+        
+        let reported = if self.door_control_enabled {
+            match self.doors.door_state(camera).await {
+                Ok(reported) => reported,
+                Err(e) => {
+                    debug!("🚪 Door '{}' did not report its state: {}", camera.id, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        
+        if let Some(unlocked) = reported {
+            if door.unlocked != Some(unlocked) {
+                warn!(
+                    "🚪 Door '{}' reports itself {} - updating the tracked state",
+                    camera.id,
+                    if unlocked { "unlocked" } else { "locked" }
+                );
+                door.unlocked = Some(unlocked);
+                door.since = Some(Utc::now());
+                door.auto_relock_at = None;
+            }
+        }
+        
+        DoorStatus {
+            camera: camera.id.clone(),
+            state: match door.unlocked {
+                Some(true) => DoorPosition::Unlocked,
+                Some(false) => DoorPosition::Locked,
+                None => DoorPosition::Unknown,
+            },
+            since: door.since,
+            auto_relock_at: door.auto_relock_at,
+            reported: reported.is_some(),
+        }
+    }
+    
+    /// MQTT topic segment for a camera's door: the default camera keeps the
+    /// plain `door/...` topics, others get `door/<id>/...`.
+    fn door_topic(&self, camera: &CameraConfig) -> String {
+        if camera.id == self.default_camera {
+            "door".to_string()
+        } else {
+            format!("door/{}", camera.id)
+        }
+    }
+    
+    /// Refuses to unlock during a lockdown, whoever asks: the lockdown has
+    /// to be cleared first. Locking is always allowed.
+    async fn manual_door_control(&self, camera: &CameraConfig, action: DoorAction, caller: &str) -> Result<()> {
+        let unlock = action == DoorAction::Unlock;
+        if unlock && self.lockdown_active() {
+            warn!("🚨 Refused to unlock door '{}' for {} - lockdown active", camera.id, caller);
+            return Err(ApiError::LockdownActive.into());
+        }
+        // An explicit override is always sent, in case the door was moved by
+        // hand, and a manual unlock stays open until someone locks it
+        self.command_door(camera, unlock, None, true).await?;
+        
+        // An audit entry, not a grant: nobody was recognized, so it stays
+        // out of the grant stats, counters and webhooks
+        self.log_access(
+            format!(
+                "🛠️ Manual override - door '{}' {} by {}",
+                camera.id,
+                if unlock { "unlocked" } else { "locked" },
+                caller
+            ),
+            None,
+            None,
+            false,
+        );
+        
+        if unlock {
+            self.schedule_relock(camera);
+        }
+        
+        Ok(())
+    }
+    
+    /// Drives one door on its own, for checking the wiring during an
+    /// install. Forced like a manual override so the relay always clicks,
+    /// but an unlock still relocks after `door_unlock_seconds`.
+    async fn test_door(&self, camera: &CameraConfig, action: DoorAction, caller: &str) -> DoorTestResult {
+        let unlock = action == DoorAction::Unlock;
+        let hold_open = unlock.then(|| Duration::from_secs(self.door_unlock_seconds));
+        
+        let started = Instant::now();
+        let result = self.command_door(camera, unlock, hold_open, true).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        
+        self.log_access(
+            format!(
+                "🧪 Device test - door '{}' {} by {} ({})",
+                camera.id,
+                action.as_str(),
+                caller,
+                if result.is_ok() { "ok" } else { "failed" }
+            ),
+            None,
+            None,
+            false,
+        );
+        if unlock && result.is_ok() {
+            self.schedule_relock(camera);
+        }
+        
+        DoorTestResult {
+            camera: camera.id.clone(),
+            action,
+            ok: result.is_ok(),
+            latency_ms,
+            error: result.err().map(|e| e.to_string()),
+        }
+    }
+    
+    /// Grabs one frame from a camera without running recognition.
+    async fn test_camera(&self, camera: &CameraConfig) -> (CameraTestResult, Option<Bytes>) {
+        let started = Instant::now();
+        let result = self.capture_from_esp32(camera).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        
+        match result {
+            Ok(image_data) => {
+                let dimensions = images::dimensions(&image_data);
+                let summary = CameraTestResult {
+                    camera: camera.id.clone(),
+                    ok: true,
+                    latency_ms,
+                    bytes: Some(image_data.len()),
+                    width: dimensions.map(|(width, _)| width),
+                    height: dimensions.map(|(_, height)| height),
+                    error: None,
+                };
+                (summary, Some(image_data))
+            }
+            Err(e) => {
+                let summary = CameraTestResult {
+                    camera: camera.id.clone(),
+                    ok: false,
+                    latency_ms,
+                    bytes: None,
+                    width: None,
+                    height: None,
+                    error: Some(e.to_string()),
+                };
+                (summary, None)
+            }
+        }
+    }
+    
+    /// Polls the door's sensor until it reports open or `timeout` passes.
+    /// Errors and missing readings count as not open yet.
+    /// A reading with a `changed_at` from before the unlock is left over
+    /// from earlier, so it doesn't count either.
+    async fn confirm_door_opened(&self, camera: &CameraConfig, timeout: Duration) -> bool {
+        let unlocked_at = Utc::now();
+        let deadline = Instant::now() + timeout;
+        let mut stale_reading = None;
+        loop {
+            match self.doors.door_sensor(camera).await {
+                Ok(Some(reading)) if reading.open => {
+                    let skew = reading
+                        .changed_at
+                        .and_then(|changed_at| self.device_clock_skew(changed_at, unlocked_at, Utc::now()));
+                    match skew {
+                        None => {
+                            debug!("🚪 Door '{}' confirmed open", camera.id);
+                            return true;
+                        }
+                        Some(skew) => stale_reading = Some(skew),
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => debug!("🚪 Door '{}' sensor read failed: {}", camera.id, e),
+            }
+            
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                if let Some(skew) = stale_reading {
+                    warn!(
+                        "🕰️ Door '{}' reported opening {}s away from the unlock - it was already open, or its clock is off",
+                        camera.id,
+                        skew.num_seconds()
+                    );
+                }
+                warn!(
+                    "🚪 Door '{}' unlocked but didn't report open within {:?} - it may be jammed",
+                    camera.id, timeout
+                );
+                return false;
+            }
+            tokio::time::sleep(remaining.min(DOOR_CONFIRM_POLL_INTERVAL)).await;
+        }
+    }
+    
+    /// How far a timestamp from a device's clock falls outside `earliest` to
+    /// `latest` by ours, beyond `CLOCK_SKEW_TOLERANCE_SECONDS`; `None` when
+    /// it's within.
+    fn device_clock_skew(
+        &self,
+        reported: DateTime<Utc>,
+        earliest: DateTime<Utc>,
+        latest: DateTime<Utc>,
+    ) -> Option<chrono::Duration> {
+        if reported < earliest - self.clock_skew_tolerance {
+            Some(reported - earliest)
+        } else if reported > latest + self.clock_skew_tolerance {
+            Some(reported - latest)
+        } else {
+            None
+        }
+    }
+    
+    /// Relocks the door after `door_unlock_seconds`, unless another unlock
+    /// happens in the meantime (that unlock schedules its own relock).
+    fn schedule_relock(&self, camera: &CameraConfig) {
+        let unlocked_at = Instant::now();
+        self.last_unlock
+            .lock()
+            .unwrap()
+            .insert(camera.id.clone(), unlocked_at);
+        
+        self.log_access(
+            format!("🔓 Door '{}' unlocked - auto-relock in {}s", camera.id, self.door_unlock_seconds),
+            None,
+            None,
+            false,
+        );
+        
+        let state = self.clone();
+        let camera = camera.clone();
+        let delay = Duration::from_secs(self.door_unlock_seconds);
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            
+            if state.last_unlock.lock().unwrap().get(&camera.id) != Some(&unlocked_at) {
+                info!("🔓 Door '{}' was unlocked again - skipping stale relock", camera.id);
+                return;
+            }
+            
+            match state.control_pico2_door(&camera, false).await {
+                Ok(()) => state.log_access(format!("🔒 Door '{}' auto-relocked", camera.id), None, None, false),
+                Err(e) => warn!("Failed to relock door: {}", e),
+            }
+        });
+    }
+    
+    /// `expires_at` enrolls a temporary guest; `enrolled_by` is the caller.
+    async fn add_person(
+        &self,
+        name: String,
+        photos: Vec<Bytes>,
+        enrolled_via: EnrollmentSource,
+        enrolled_by: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<AddPersonResponse> {
+        form::validate_name(&name)?;
+        info!("➕ Adding person '{}' to collection from {} photo(s)", name, photos.len());
+        
+        let mut face_ids = Vec::new();
+        let mut photos_skipped = 0;
+        let mut quality_warnings = Vec::new();
+        
+        // A failure part-way leaves nobody half-enrolled
+        let indexed: Result<()> = async {
+            for (index, image_data) in photos.into_iter().enumerate() {
+                let image_data = normalize_upload(image_data).await?;
+                let Some(detail) = self.detect_primary_face(&image_data).await? else {
+                    warn!("⚠️ No face detected in photo {} for {}", index + 1, name);
+                    photos_skipped += 1;
+                    continue;
+                };
+                
+                let mut quality = photo_quality(index + 1, &detail);
+                if let Some(min) = self.enroll_min_quality {
+                    let below = |metric: Option<f32>| metric.is_some_and(|value| value < min);
+                    if below(quality.brightness) || below(quality.sharpness) {
+                        quality.rejected = true;
+                        quality.issues.push(format!("below ENROLL_MIN_QUALITY of {:.0}", min));
+                    }
+                }
+                if !quality.issues.is_empty() {
+                    warn!("📷 Photo {} for {}: {}", index + 1, name, quality.issues.join(", "));
+                    let rejected = quality.rejected;
+                    quality_warnings.push(quality);
+                    if rejected {
+                        photos_skipped += 1;
+                        continue;
+                    }
+                }
+                
+                match self.index_face(&name, image_data, enrolled_via, enrolled_by, expires_at).await? {
+                    Some(face_id) => face_ids.push(face_id),
+                    None => {
+                        warn!("⚠️ No face detected in photo {} for {}", index + 1, name);
+                        photos_skipped += 1;
+                    }
+                }
+            }
+            Ok(())
+        }
+        .await;
+        if let Err(e) = indexed {
+            if let Err(cleanup) = self.forget_faces(&face_ids).await {
+                warn!("⚠️ Failed to roll back the partial enrollment of {}: {}", name, cleanup);
+            }
+            return Err(e);
+        }
+        
+        let Some(first_face_id) = face_ids.first().cloned() else {
+            let rejected: Vec<String> = quality_warnings
+                .iter()
+                .filter(|quality| quality.rejected)
+                .map(|quality| format!("photo {}: {}", quality.photo, quality.issues.join(", ")))
+                .collect();
+            if !rejected.is_empty() {
+                return Err(ApiError::PoorPhotoQuality(rejected.join("; ")).into());
+            }
+            return Err(ApiError::NoFaceDetected.into());
+        };
+        self.update_enrollment_gauge();
+        
+        let pending_approval = self.needs_approval();
+        let message = if pending_approval {
+            self.log_access(
+                format!("⏳ Enrollment awaiting approval: {} ({} face(s))", name, face_ids.len()),
+                Some(name.clone()),
+                None,
+                false,
+            );
+            format!("⏳ Added {} with {} face(s), awaiting approval", name, face_ids.len())
+        } else {
+            self.log_access(
+                format!("➕ Added authorized person: {} ({} face(s))", name, face_ids.len()),
+                Some(name.clone()),
+                None,
+                false,
+            );
+            format!("✅ Successfully added {} with {} face(s)", name, face_ids.len())
+        };
+        
+        Ok(AddPersonResponse {
+            face_id: first_face_id,
+            message,
+            face_ids,
+            photos_skipped,
+            quality_warnings,
+            pending_approval,
+        })
+    }
+    
+    /// Deletes faces from the collection, the registry and the database.
+    async fn forget_faces(&self, face_ids: &[String]) -> Result<()> {
+        if face_ids.is_empty() {
+            return Ok(());
+        }
+        
+        self.face_recognizer.delete_faces(face_ids).await?;
+        self.authorized_people
+            .write()
+            .unwrap()
+            .retain(|face_id, _| !face_ids.contains(face_id));
+        for face_id in face_ids {
+            self.store.delete_person(face_id).await?;
+        }
+        Ok(())
+    }
+    
+    /// Whether newly indexed faces start out pending, under
+    /// `ENROLL_REQUIRES_APPROVAL`. Photos re-enrolled from a backup are new
+    /// faces too, whatever status the backup claims for them.
+    fn needs_approval(&self) -> bool {
+        self.enroll_requires_approval
+    }
+    
+    /// Indexes a single photo under `name`, returning the new face id or
+    /// `None` when Rekognition found no face to index.
+    async fn index_face(
+        &self,
+        name: &str,
+        image_data: Bytes,
+        enrolled_via: EnrollmentSource,
+        enrolled_by: &str,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<Option<String>> {
+        let Some(face_id) = self.face_recognizer.index_face(name, &image_data).await? else {
+            return Ok(None);
+        };
+        
+        let mut person = AuthorizedPerson::new(name, face_id.clone(), enrolled_via);
+        person.expires_at = expires_at;
+        person.enrolled_by = Some(enrolled_by.to_string());
+        // Extra photos of someone already enrolled keep their per-person
+        // settings. Not the role: whoever may add photos could otherwise mint
+        // an admin face under an admin's name without the role endpoint
+        let existing = self
+            .authorized_people
+            .read()
+            .unwrap()
+            .values()
+            .find(|p| p.name == name)
+            .cloned();
+        if let Some(existing) = existing {
+            person.inherit_settings(&existing);
+        }
+        if self.needs_approval() {
+            person.status = PersonStatus::Pending;
+        }
+        
+        self.store.upsert_person(&person).await?;
+        self.authorized_people
+            .write()
+            .unwrap()
+            .insert(face_id.clone(), person);
+        
+        Ok(Some(face_id))
+    }
+    
+    async fn remove_person(&self, name: &str) -> Result<Option<RemovePersonResponse>> {
+        info!("➖ Removing person '{}' from collection", name);
+        
+        let face_ids: Vec<String> = self
+            .authorized_people
+            .read()
+            .unwrap()
+            .values()
+            .filter(|p| p.name == name)
+            .map(|p| p.face_id.clone())
+            .collect();
+        
+        if face_ids.is_empty() {
+            return Ok(None);
+        }
+        
+        let faces_deleted = self.face_recognizer.delete_faces(&face_ids).await?;
+        
+        self.authorized_people
+            .write()
+            .unwrap()
+            .retain(|_, p| p.name != name);
+        for face_id in &face_ids {
+            self.store.delete_person(face_id).await?;
+        }
+        self.update_enrollment_gauge();
+        
+        self.log_access(
+            format!("➖ Removed authorized person: {}", name),
+            Some(name.to_string()),
+            None,
+            false,
+        );
+        
+        Ok(Some(RemovePersonResponse {
+            name: name.to_string(),
+            faces_deleted,
+        }))
+    }
+    
+    /// `camera` selects the door a grant unlocks. `pin` is the keypad code
+    /// entered alongside the photo; it is required when `AUTH_MODE=face_pin`
+    /// and checked whenever it is supplied.
+    /// An image without a face is not an access attempt: it gets a
+    /// `NO_FACE_IN_IMAGE` response and no log entry, so empty-doorway
+    /// frames from cameras don't look like failures or intruders.
+    /// If the client disconnects before the match is known, Rekognition is
+    /// abandoned and the door left alone; once it is known, the decision is
+    /// carried out in full either way.
+    async fn recognize_face(
+        &self,
+        image_data: Bytes,
+        source: AccessSource,
+        camera: &CameraConfig,
+        pin: Option<&str>,
+    ) -> Result<AccessCheckResponse> {
+        let state = self.clone();
+        let camera = camera.clone();
+        let pin = pin.map(str::to_string);
+        disconnect::detach(async move {
+            state
+                .recognize_frame(image_data, source, &camera, pin.as_deref())
+                .await
+        })
+        .await
+    }
+    
+    async fn recognize_frame(
+        &self,
+        image_data: Bytes,
+        source: AccessSource,
+        camera: &CameraConfig,
+        pin: Option<&str>,
+    ) -> Result<AccessCheckResponse> {
+        match self.detect_and_recognize(image_data.clone(), source, camera, pin).await {
+            Err(e) if error::is_outage(&e) => self.recognition_outage(e, image_data, source, camera, pin).await,
+            result => result,
+        }
+    }
+    
+    async fn detect_and_recognize(
+        &self,
+        image_data: Bytes,
+        source: AccessSource,
+        camera: &CameraConfig,
+        pin: Option<&str>,
+    ) -> Result<AccessCheckResponse> {
+        if self.prefilter_rejects(&image_data, source).await? {
+            return Ok(AccessCheckResponse {
+                prefiltered: true,
+                ..AccessCheckResponse::no_face()
+            });
+        }
+        
+        // Refused before anything is sent to Rekognition, so a locked-out
+        // source isn't billed for every attempt
+        if self.lockdown_active() {
+            let response = self.deny_access(DenyReason::Lockdown, None, None, Some(&image_data));
+            telemetry::record_decision(source, false, response.deny_reason);
+            return Ok(response);
+        }
+        if let Some(response) = self.check_lockout(source, Some(&image_data)) {
+            telemetry::record_decision(source, false, response.deny_reason);
+            return Ok(response);
+        }
+        
+        let faces = if self.needs_face_detail() {
+            let faces = self.detect_all_faces(&image_data).await?;
+            if faces.is_empty() {
+                debug!("🙈 No face in the {} image", source.as_str());
+                return Ok(AccessCheckResponse::no_face());
+            }
+            Some(faces)
+        } else {
+            None
+        };
+        
+        self.recognize_detected_faces(image_data, faces, source, camera, pin).await
+    }
+    
+    /// Whether a check needs a `DetectFaces` call on top of the search, for
+    /// passive liveness, attributes or counting faces for
+    /// `MULTI_FACE_POLICY`. Otherwise the search alone finds the face and
+    /// its box.
+    fn needs_face_detail(&self) -> bool {
+        self.liveness_enabled || self.capture_attributes || self.multi_face_policy != MultiFacePolicy::First
+    }
+    
+    /// True when `LOCAL_PREFILTER` is on and found no face, in which case
+    /// the frame should go no further.
+    async fn prefilter_rejects(&self, image_data: &Bytes, source: AccessSource) -> Result<bool> {
+        let Some(prefilter) = &self.prefilter else {
+            return Ok(false);
+        };
+        if prefilter.has_face(image_data.clone()).await? {
+            return Ok(false);
+        }
+        
+        debug!("🙈 Pre-filter found no face in the {} image", source.as_str());
+        telemetry::record_prefiltered(source);
+        Ok(true)
+    }
+    
+    /// Recognition of a frame that lockdown and the lockout let through.
+    /// `faces` are the detected faces, largest first, when detection ran.
+    /// The largest face is the one matched; the others only matter to
+    /// `MULTI_FACE_POLICY`.
+    async fn recognize_detected_faces(
+        &self,
+        image_data: Bytes,
+        faces: Option<Vec<face::DetectedFace>>,
+        source: AccessSource,
+        camera: &CameraConfig,
+        pin: Option<&str>,
+    ) -> Result<AccessCheckResponse> {
+        let (detail, companions) = match faces {
+            Some(faces) => {
+                let mut faces = faces.into_iter();
+                let Some(detail) = faces.next() else {
+                    return Ok(AccessCheckResponse::no_face());
+                };
+                (Some(detail), faces.collect())
+            }
+            None => (None, Vec::new()),
+        };
+        let faces_detected = 1 + companions.len();
+        let dimensions = images::dimensions(&image_data);
+        
+        if !self.companions_allowed(&image_data, &companions, source).await? {
+            let mut response = self.deny_access(DenyReason::MultipleFaces, None, None, Some(&image_data));
+            telemetry::record_decision(source, false, response.deny_reason);
+            response.face = detail
+                .as_ref()
+                .map(|detail| face_geometry(detail.bounding_box.clone(), detail.pose.clone(), dimensions));
+            response.faces_detected = faces_detected;
+            return Ok(response);
+        }
+        
+        info!("🔍 Attempting face recognition...");
+        
+        let search_threshold = self.search_threshold(self.global_threshold());
+        let started = Instant::now();
+        let best_match = disconnect::unless_client_gone(self.search_with_margin(&image_data, search_threshold)).await;
+        let best_match = match best_match {
+            // Checked again once the search is back: a client that left just
+            // as it finished must not get an unlock nobody is there for
+            Some(best_match) if !disconnect::client_gone() => best_match,
+            _ => {
+                info!(
+                    "🔌 Client disconnected during recognition of the {} image - search abandoned, door untouched",
+                    source.as_str()
+                );
+                telemetry::record_abandoned(source);
+                return Err(ApiError::ClientClosedRequest.into());
+            }
+        };
+        telemetry::observe_rekognition_search(source, started.elapsed());
+        let best_match = match best_match {
+            // Detection can find a face that search still rejects
+            Err(e) if error::is_no_face(&e) => {
+                debug!("🙈 Rekognition found no searchable face in the {} image", source.as_str());
+                return Ok(AccessCheckResponse::no_face());
+            }
+            result => result?,
+        };
+        
+        // Without detection there's no pose, but the search reports the box
+        let geometry = match &detail {
+            Some(detail) => Some(face_geometry(detail.bounding_box.clone(), detail.pose.clone(), dimensions)),
+            None => best_match
+                .as_ref()
+                .map(|(best_match, _)| face_geometry(best_match.searched_face.clone(), None, dimensions)),
+        };
+        
+        let mut response = self.match_face(image_data, best_match, detail, camera, pin).await?;
+        response.face = geometry;
+        response.faces_detected = faces_detected;
+        telemetry::record_decision(source, response.access_granted, response.deny_reason);
+        if response.access_granted {
+            self.clear_failures(source);
+        } else if response.deny_reason.is_some_and(|reason| reason.counts_as_failure()) {
+            self.record_failure(source);
+        }
+        
+        Ok(response)
+    }
+    
+    /// The most lenient threshold in use, to search at; stricter per-person
+    /// thresholds are enforced once we know who matched.
+    fn search_threshold(&self, global_threshold: f32) -> f32 {
+        self.authorized_people
+            .read()
+            .unwrap()
+            .values()
+            .filter_map(|person| person.match_threshold)
+            .fold(global_threshold, f32::min)
+    }
+    
+    /// The best match at or above `threshold`. With `MATCH_MARGIN` set, also
+    /// the closest match for anyone else, from a search wide enough to find
+    /// everyone within the margin.
+    async fn search_with_margin(
+        &self,
+        image_data: &Bytes,
+        threshold: f32,
+    ) -> Result<Option<(face::FaceMatch, Option<RunnerUp>)>> {
+        if self.match_margin <= 0.0 {
+            let best_match = self.face_recognizer.search_face(image_data, threshold).await?;
+            return Ok(best_match.map(|best_match| (best_match, None)));
+        }
+        
+        let candidates = self
+            .face_recognizer
+            .search_candidates(image_data, (threshold - self.match_margin).max(0.0), MATCH_MARGIN_CANDIDATES)
+            .await?;
+        Ok(self.best_with_runner_up(&candidates, threshold))
+    }
+    
+    /// The first of `candidates`, best first, if it reaches `threshold`,
+    /// and the closest of the rest who is someone else.
+    fn best_with_runner_up(
+        &self,
+        candidates: &[face::FaceMatch],
+        threshold: f32,
+    ) -> Option<(face::FaceMatch, Option<RunnerUp>)> {
+        let best_match = candidates.first().filter(|best_match| best_match.similarity >= threshold)?;
+        
+        // The same person's other faces are no rival
+        let best_name = self.match_name(best_match);
+        let runner_up = candidates[1..]
+            .iter()
+            .map(|candidate| RunnerUp {
+                name: self.match_name(candidate),
+                similarity: candidate.similarity,
+            })
+            .find(|candidate| candidate.name != best_name);
+        Some((best_match.clone(), runner_up))
+    }
+    
+    /// Who a match is, by the registry when it knows the face.
+    fn match_name(&self, face_match: &face::FaceMatch) -> String {
+        self.person_by_face(&face_match.face_id)
+            .map_or_else(|| face_match.external_id.clone(), |person| person.name)
+    }
+    
+    /// Decides on `best_match`, the search result for the frame. `detail`
+    /// is the matched face as detection saw it, when detection ran.
+    async fn match_face(
+        &self,
+        image_data: Bytes,
+        best_match: Option<(face::FaceMatch, Option<RunnerUp>)>,
+        detail: Option<face::DetectedFace>,
+        camera: &CameraConfig,
+        pin: Option<&str>,
+    ) -> Result<AccessCheckResponse> {
+        let timestamp = Utc::now();
+        
+        if let Some((best_match, runner_up)) = best_match {
+            let similarity = best_match.similarity;
+            let confidence = similarity_to_confidence(similarity);
+            
+            let person = match self.judge_match(&best_match, runner_up.as_ref(), detail.as_ref(), timestamp) {
+                MatchVerdict::Allowed(person) => person,
+                MatchVerdict::Untracked => {
+                    warn!(
+                        "⚠️ Rekognition matched face {} ({}) which is not tracked locally - re-syncing",
+                        best_match.face_id, best_match.external_id
+                    );
+                    // The face is tracked from now on, under its namesake's
+                    // settings, but it was never enrolled here, so this
+                    // check is refused rather than skipping them
+                    if let Err(e) = self.load_existing_faces().await {
+                        warn!("Failed to re-sync faces: {}", e);
+                    }
+                    
+                    return Ok(self.deny_access(
+                        DenyReason::NotInRegistry,
+                        Some(best_match.external_id),
+                        Some(confidence),
+                        Some(&image_data),
+                    ));
+                }
+                MatchVerdict::Denied(person, reason) => {
+                    if reason == DenyReason::GuestExpired {
+                        let state = self.clone();
+                        let name = person.name.clone();
+                        tokio::spawn(async move { state.purge_guest(&name).await });
+                    }
+                    return Ok(self.deny_access(reason, Some(person.name), Some(confidence), Some(&image_data)));
+                }
+            };
+            let person_name = &person.name;
+            
+            let pin_checked = self.auth_mode == AuthMode::FacePin || pin.is_some();
+            if pin_checked && !verify_pin(person.pin_hash.clone(), pin).await {
+                warn!("🔢 Face matched {} but the PIN factor failed", person_name);
+                return Ok(self.deny_access(
+                    DenyReason::PinMismatch,
+                    Some(person_name.clone()),
+                    Some(confidence),
+                    Some(&image_data),
+                ));
+            }
+            
+            if self.is_repeat_grant(camera, person_name) {
+                info!("🔁 {} granted again within debounce window - door not re-triggered", person_name);
+                return Ok(AccessCheckResponse {
+                    access_granted: true,
+                    person_name: Some(person_name.clone()),
+                    confidence: Some(confidence),
+                    similarity: Some(similarity),
+                    timestamp,
+                    attributes: None,
+                    deny_reason: None,
+                    deny_message: None,
+                    retry_after_seconds: None,
+                    face: None,
+                    door_opened: false,
+                    error_code: None,
+                    faces_detected: 0,
+                    prefiltered: false,
+                    door_confirmed: None,
+                });
+            }
+            
+            self.record_person_seen(person_name, timestamp).await;
+            
+            let attributes = detail.and_then(|detail| self.capture_attributes.then_some(detail.attributes));
+            let factors = if pin_checked { "face + PIN" } else { "face" };
+            return Ok(self
+                .grant_access(&person, factors, Some(similarity), attributes, camera, timestamp)
+                .await);
+        }
+        
+        Ok(self.deny_access(DenyReason::NotRecognized, None, None, Some(&image_data)))
+    }
+    
+    /// What the registry and the matched person's settings make of
+    /// `best_match`: everything `match_face` checks before the PIN, the
+    /// debounce and the door, with no side effects.
+    fn judge_match(
+        &self,
+        best_match: &face::FaceMatch,
+        runner_up: Option<&RunnerUp>,
+        detail: Option<&face::DetectedFace>,
+        timestamp: DateTime<Utc>,
+    ) -> MatchVerdict {
+        // An owned copy of the record, so no registry guard is held
+        // across the caller's awaits
+        let Some(person) = self.person_by_face(&best_match.face_id) else {
+            return MatchVerdict::Untracked;
+        };
+        
+        // The registry owns the display name; the Rekognition external
+        // ID is fixed when a face is indexed and goes stale on rename
+        let person_name = &person.name;
+        let similarity = best_match.similarity;
+        
+        if person.is_expired(timestamp) {
+            info!("⌛ Guest {} matched after their access expired", person_name);
+            return MatchVerdict::Denied(person, DenyReason::GuestExpired);
+        }
+        
+        if person.status == PersonStatus::Pending {
+            info!("⏳ {} matched a face that is awaiting approval", person_name);
+            return MatchVerdict::Denied(person, DenyReason::PendingApproval);
+        }
+        
+        let required = person.match_threshold.unwrap_or(self.global_threshold());
+        if similarity < required {
+            info!(
+                "🎯 {} matched at {:.1}%, below the required {:.1}%",
+                person_name, similarity, required
+            );
+            return MatchVerdict::Denied(person, DenyReason::LowConfidence);
+        }
+        
+        if let Some(runner_up) = runner_up {
+            let margin = similarity - runner_up.similarity;
+            info!(
+                "🎯 {} matched at {:.1}%, next best {} at {:.1}% (margin {:.1}, required {:.1})",
+                person_name, similarity, runner_up.name, runner_up.similarity, margin, self.match_margin
+            );
+            if margin < self.match_margin {
+                return MatchVerdict::Denied(person, DenyReason::AmbiguousMatch);
+            }
+        }
+        
+        if !person.within_schedule(timestamp.with_timezone(&self.timezone)) {
+            return MatchVerdict::Denied(person, DenyReason::OutsideSchedule);
+        }
+        
+        if self.liveness_enabled {
+            if let Err(problem) = self.check_passive_liveness(detail) {
+                warn!("🕵️ Liveness check rejected match for {}: {}", person_name, problem);
+                return MatchVerdict::Denied(person, DenyReason::SpoofSuspected);
+            }
+        }
+        
+        MatchVerdict::Allowed(person)
+    }
+    
+    /// Unlocks `camera`'s door for `person` and records the grant.
+    /// `factors` names what they proved themselves with, for the log.
+    async fn grant_access(
+        &self,
+        person: &AuthorizedPerson,
+        factors: &str,
+        similarity: Option<f32>,
+        attributes: Option<FaceAttributes>,
+        camera: &CameraConfig,
+        timestamp: DateTime<Utc>,
+    ) -> AccessCheckResponse {
+        let person_name = &person.name;
+        let confidence = similarity.map(similarity_to_confidence);
+        
+        // Control door; a failed unlock is still a grant, but the caller
+        // must know the door stayed shut
+        let mut door_opened = false;
+        let mut door_confirmed = None;
+        let mut door_error = None;
+        if self.door_control_enabled {
+            match self.control_pico2_door(camera, true).await {
+                Ok(()) => {
+                    self.schedule_relock(camera);
+                    door_opened = true;
+                    if let Some(timeout) = self.door_confirm_timeout {
+                        door_confirmed = Some(self.confirm_door_opened(camera, timeout).await);
+                    }
+                }
+                Err(e) => {
+                    warn!("🚪 Granted {} but the door failed to unlock: {}", person_name, e);
+                    door_error = Some(e.downcast_ref::<ApiError>().map_or("DOOR_UNREACHABLE", ApiError::code));
+                }
+            }
+        }
+        
+        let log_entry = AccessLog {
+            timestamp,
+            action: format!(
+                "🟢 Access GRANTED - {} ({}){}",
+                person_name,
+                factors,
+                if door_error.is_some() {
+                    " - door failed to unlock"
+                } else if door_confirmed == Some(false) {
+                    " - door not confirmed open"
+                } else {
+                    ""
+                }
+            ),
+            person_name: Some(person_name.clone()),
+            confidence,
+            access_granted: true,
+            attributes: attributes.clone(),
+            deny_reason: None,
+            snapshot: None,
+            request_id: request_id::current(),
+            client_ip: client_ip::current().map(|ip| ip.to_string()),
+            door_confirmed,
+        };
+        
+        // Only a door that actually unlocked starts the debounce window, so
+        // a transient Pico failure is retried on the next check
+        if door_error.is_none() {
+            self.record_grant(camera, person_name);
+        }
+        
+        self.notify_person(person, &log_entry);
+        
+        self.record_access(log_entry);
+        
+        AccessCheckResponse {
+            access_granted: true,
+            person_name: Some(person_name.clone()),
+            confidence,
+            similarity,
+            timestamp,
+            attributes,
+            deny_reason: None,
+            deny_message: None,
+            retry_after_seconds: None,
+            face: None,
+            door_opened,
+            error_code: door_error.map(str::to_string),
+            faces_detected: 0,
+            prefiltered: false,
+            door_confirmed,
+        }
+    }
+    
+    /// Applies `AWS_OUTAGE_POLICY` once Rekognition has failed because it
+    /// is down or unreachable, rather than because of the image.
+    async fn recognition_outage(
+        &self,
+        error: anyhow::Error,
+        image_data: Bytes,
+        source: AccessSource,
+        camera: &CameraConfig,
+        pin: Option<&str>,
+    ) -> Result<AccessCheckResponse> {
+        let response = match self.aws_outage_policy {
+            AwsOutagePolicy::Deny => {
+                warn!("☁️ Rekognition unavailable ({}) - AWS_OUTAGE_POLICY=deny, refusing the check", error);
+                return Err(error);
+            }
+            AwsOutagePolicy::ManualOnly => {
+                warn!(
+                    "☁️ Rekognition unavailable ({}) - AWS_OUTAGE_POLICY=manual_only, only a manual override opens the door",
+                    error
+                );
+                self.deny_access(DenyReason::RecognitionUnavailable, None, None, Some(&image_data))
+            }
+            AwsOutagePolicy::AllowPin => {
+                warn!(
+                    "☁️ Rekognition unavailable ({}) - AWS_OUTAGE_POLICY=allow_pin, falling back to PIN-only entry",
+                    error
+                );
+                self.pin_only_access(image_data, source, camera, pin).await
+            }
+        };
+        telemetry::record_decision(source, response.access_granted, response.deny_reason);
+        Ok(response)
+    }
+    
+    /// Entry on a PIN alone, while Rekognition is down. Lockdown, the
+    /// lockout, guest expiry and schedules still apply, and only PINs of at
+    /// least [`pins::PIN_ONLY_MIN_LENGTH`] digits are accepted.
+    async fn pin_only_access(
+        &self,
+        image_data: Bytes,
+        source: AccessSource,
+        camera: &CameraConfig,
+        pin: Option<&str>,
+    ) -> AccessCheckResponse {
+        if self.lockdown_active() {
+            return self.deny_access(DenyReason::Lockdown, None, None, Some(&image_data));
+        }
+        if let Some(response) = self.check_lockout(source, Some(&image_data)) {
+            return response;
+        }
+        let Some(pin) = pin else {
+            return self.deny_access(DenyReason::RecognitionUnavailable, None, None, Some(&image_data));
+        };
+        
+        if pin.len() < pins::PIN_ONLY_MIN_LENGTH {
+            warn!(
+                "🔢 PIN-only entry refused - PINs under {} digits need a face as well",
+                pins::PIN_ONLY_MIN_LENGTH
+            );
+            self.record_failure(source);
+            return self.deny_access(DenyReason::PinMismatch, None, None, Some(&image_data));
+        }
+        let Some(person) = self.person_by_pin(pin).await else {
+            warn!("🔢 PIN-only entry attempted with an unknown PIN");
+            self.record_failure(source);
+            return self.deny_access(DenyReason::PinMismatch, None, None, Some(&image_data));
+        };
+        
+        let timestamp = Utc::now();
+        if person.is_expired(timestamp) {
+            return self.deny_access(DenyReason::GuestExpired, Some(person.name.clone()), None, Some(&image_data));
+        }
+        if !self.has_approved_face(&person.name) {
+            return self.deny_access(DenyReason::PendingApproval, Some(person.name.clone()), None, Some(&image_data));
+        }
+        if !person.within_schedule(timestamp.with_timezone(&self.timezone)) {
+            return self.deny_access(DenyReason::OutsideSchedule, Some(person.name.clone()), None, Some(&image_data));
+        }
+        
+        self.clear_failures(source);
+        self.record_person_seen(&person.name, timestamp).await;
+        self.grant_access(&person, "PIN only - face recognition unavailable", None, None, camera, timestamp)
+            .await
+    }
+    
+    /// The person whose PIN this is, checked against each person once.
+    async fn person_by_pin(&self, pin: &str) -> Option<AuthorizedPerson> {
+        let candidates: Vec<AuthorizedPerson> = {
+            let people = self.authorized_people.read().unwrap();
+            let mut seen = HashSet::new();
+            people
+                .values()
+                .filter(|person| person.pin_hash.is_some() && seen.insert(person.name.clone()))
+                .cloned()
+                .collect()
+        };
+        
+        for person in candidates {
+            if verify_pin(person.pin_hash.clone(), Some(pin)).await {
+                return Some(person);
+            }
+        }
+        None
+    }
+    
+    fn global_threshold(&self) -> f32 {
+        *self.confidence_threshold.lock().unwrap()
+    }
+    
+    fn set_global_threshold(&self, threshold: f32) {
+        *self.confidence_threshold.lock().unwrap() = threshold;
+    }
+    
+    /// Bumps the access counter on every face record of `name`. A failed
+    /// write is only logged; it must not turn a grant into an error.
+    async fn record_person_seen(&self, name: &str, at: DateTime<Utc>) {
+        let result = self
+            .update_person(name, |person| {
+                person.access_count += 1;
+                person.last_seen = Some(at);
+            })
+            .await;
+        if let Err(e) = result {
+            warn!("⚠️ Failed to persist access stats for {}: {}", name, e);
+        }
+    }
+    
+    async fn set_person_threshold(&self, name: &str, threshold: Option<f32>) -> Result<bool> {
+        self.update_person(name, |person| person.match_threshold = threshold).await
+    }
+    
+    async fn set_person_pin(&self, name: &str, pin: &str) -> Result<bool> {
+        let pin = pin.to_string();
+        let hash = tokio::task::spawn_blocking(move || pins::hash(&pin)).await??;
+        self.update_person(name, |person| person.pin_hash = Some(hash.clone())).await
+    }
+    
+    /// True when `name` already had a door-triggering grant at `camera`
+    /// within `GRANT_DEBOUNCE_SECONDS`. Tracked per camera and person, so
+    /// someone else arriving meanwhile, or the same person at another door,
+    /// is unaffected. Suppressed grants don't extend the window, so a person
+    /// still waiting at the door is let in again once it lapses.
+    fn is_repeat_grant(&self, camera: &CameraConfig, name: &str) -> bool {
+        let mut last_grants = self.last_grants.lock().unwrap();
+        let now = Instant::now();
+        last_grants.retain(|_, granted_at| now.duration_since(*granted_at) < self.grant_debounce);
+        
+        last_grants.contains_key(&(camera.id.clone(), name.to_string()))
+    }
+    
+    /// Starts `name`'s debounce window at `camera`, once the door has
+    /// unlocked for them.
+    fn record_grant(&self, camera: &CameraConfig, name: &str) {
+        self.last_grants
+            .lock()
+            .unwrap()
+            .insert((camera.id.clone(), name.to_string()), Instant::now());
+    }
+    
+    async fn detect_primary_face(&self, image_data: &Bytes) -> Result<Option<face::DetectedFace>> {
+        Ok(self.detect_all_faces(image_data).await?.into_iter().next())
+    }
+    
+    /// Every face in the image, largest first. Rekognition searches with the
+    /// largest face, so that one is primary.
+    async fn detect_all_faces(&self, image_data: &Bytes) -> Result<Vec<face::DetectedFace>> {
+        let mut details = self.face_recognizer.detect_faces(image_data).await?;
+        details.sort_by(|a, b| b.area().total_cmp(&a.area()));
+        Ok(details)
+    }
+    
+    /// Whether `MULTI_FACE_POLICY` lets the faces beside the primary one
+    /// through. Under `all`, each of them is cropped out and searched on
+    /// its own, and must be an approved face of a known person whose access
+    /// hasn't expired; schedules and PINs apply only to the primary face.
+    async fn companions_allowed(
+        &self,
+        image_data: &Bytes,
+        companions: &[face::DetectedFace],
+        source: AccessSource,
+    ) -> Result<bool> {
+        if companions.is_empty() {
+            return Ok(true);
+        }
+        
+        match self.multi_face_policy {
+            MultiFacePolicy::First => Ok(true),
+            MultiFacePolicy::Deny => {
+                info!("👥 {} faces at the door - denying", companions.len() + 1);
+                Ok(false)
+            }
+            MultiFacePolicy::All => {
+                let global_threshold = self.global_threshold();
+                let now = Utc::now();
+                for (index, companion) in companions.iter().enumerate() {
+                    let Some(bounds) = companion.bounding_box.clone() else {
+                        return Ok(false);
+                    };
+                    let crop = crop_face(image_data.clone(), bounds).await?;
+                    
+                    let started = Instant::now();
+                    let found = self.face_recognizer.search_face(&crop, global_threshold).await;
+                    telemetry::observe_rekognition_search(source, started.elapsed());
+                    let found = match found {
+                        Err(e) if error::is_no_face(&e) => None,
+                        result => result?,
+                    };
+                    
+                    let known = found
+                        .and_then(|found| self.person_by_face(&found.face_id))
+                        .is_some_and(|person| person.status == PersonStatus::Active && !person.is_expired(now));
+                    if !known {
+                        info!("👥 Face {} of {} at the door is not authorized", index + 2, companions.len() + 1);
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+    
+    /// Single-frame anti-spoofing heuristic: printed or on-screen photos
+    /// tend to come out soft and at odd angles to the camera.
+    fn check_passive_liveness(&self, detail: Option<&face::DetectedFace>) -> std::result::Result<(), String> {
+        let Some(detail) = detail else {
+            return Err("no face detail returned".to_string());
+        };
+        
+        let sharpness = detail.sharpness.unwrap_or(0.0);
+        if sharpness < self.liveness_min_sharpness {
+            return Err(format!(
+                "sharpness {:.1} below minimum {:.1}",
+                sharpness, self.liveness_min_sharpness
+            ));
+        }
+        
+        let yaw = detail.pose.as_ref().map_or(0.0, |pose| pose.yaw);
+        let pitch = detail.pose.as_ref().map_or(0.0, |pose| pose.pitch);
+        if yaw.abs() > self.liveness_max_pose_degrees || pitch.abs() > self.liveness_max_pose_degrees {
+            return Err(format!("pose yaw {:.1}/pitch {:.1} too far off-axis", yaw, pitch));
+        }
+        
+        Ok(())
+    }
+    
+    fn start_liveness_session(&self) -> LivenessStartResponse {
+        let now = Utc::now();
+        let session_id = uuid::Uuid::new_v4().to_string();
+        let expires_at = now + chrono::Duration::seconds(self.liveness_session_seconds);
+        let challenge = self.liveness_challenge_type;
+        
+        let mut sessions = self.liveness_sessions.lock().unwrap();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(session_id.clone(), LivenessSession { challenge, expires_at });
+        
+        info!("👁️ Started {:?} liveness challenge {}", challenge, session_id);
+        
+        LivenessStartResponse {
+            session_id,
+            challenge,
+            prompt: challenge.prompt().to_string(),
+            expires_at,
+        }
+    }
+    
+    fn take_liveness_session(&self, session_id: &str) -> Option<LivenessSession> {
+        let session = self.liveness_sessions.lock().unwrap().remove(session_id)?;
+        (session.expires_at > Utc::now()).then_some(session)
+    }
+    
+    /// Signs in the admin whose face is in `image_data`, from `source`.
+    /// The match must pass what a door check would: the person's threshold,
+    /// `MATCH_MARGIN` and passive liveness when enabled. The admin's PIN is
+    /// always required as well. Refused sign-ins count towards `source`'s
+    /// lockout. Schedules don't apply.
+    async fn verify_admin(&self, image_data: Bytes, pin: &str, source: AccessSource) -> Result<AdminSessionResponse> {
+        if let Some(retry_after) = self.lockout_retry_after(source) {
+            warn!("🛡️ Admin sign-in refused - locked out for another {}s", retry_after);
+            return Err(ApiError::RateLimited(retry_after).into());
+        }
+        let Some(detail) = self.detect_all_faces(&image_data).await?.into_iter().next() else {
+            return Err(ApiError::NoFaceDetected.into());
+        };
+        
+        let global_threshold = self.global_threshold();
+        let best_match = self
+            .search_with_margin(&image_data, self.search_threshold(global_threshold))
+            .await?;
+        let admin = best_match.and_then(|(best_match, runner_up)| {
+            let person = self.person_by_face(&best_match.face_id)?;
+            let required = person.match_threshold.unwrap_or(global_threshold);
+            let ambiguous = runner_up.is_some_and(|runner_up| {
+                best_match.similarity - runner_up.similarity < self.match_margin
+            });
+            (person.role == PersonRole::Admin
+                && person.status == PersonStatus::Active
+                && !person.is_expired(Utc::now())
+                && best_match.similarity >= required
+                && !ambiguous)
+                .then_some(person)
+        });
+        let Some(admin) = admin else {
+            warn!("🛡️ Admin sign-in refused - the face is not a confident match for an admin");
+            self.record_failure(source);
+            return Err(ApiError::NotAdmin.into());
+        };
+        
+        if self.liveness_enabled {
+            if let Err(problem) = self.check_passive_liveness(Some(&detail)) {
+                warn!("🕵️ Liveness check rejected admin sign-in for {}: {}", admin.name, problem);
+                self.record_failure(source);
+                return Err(ApiError::NotAdmin.into());
+            }
+        }
+        
+        if !verify_pin(admin.pin_hash.clone(), Some(pin)).await {
+            warn!("🔢 Face matched admin {} but the PIN factor failed", admin.name);
+            self.record_failure(source);
+            return Err(ApiError::NotAdmin.into());
+        }
+        
+        Ok(self.start_admin_session(&admin.name))
+    }
+    
+    fn start_admin_session(&self, name: &str) -> AdminSessionResponse {
+        let now = Utc::now();
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let expires_at = now + chrono::Duration::minutes(ADMIN_SESSION_MINUTES);
+        
+        {
+            let mut sessions = self.admin_sessions.lock().unwrap();
+            sessions.retain(|_, session| session.expires_at > now);
+            sessions.insert(
+                token.clone(),
+                AdminSession {
+                    name: name.to_string(),
+                    expires_at,
+                },
+            );
+        }
+        self.log_access(
+            format!("🛡️ Admin session started for {} by face", name),
+            Some(name.to_string()),
+            None,
+            false,
+        );
+        
+        AdminSessionResponse {
+            token,
+            name: name.to_string(),
+            expires_at,
+        }
+    }
+    
+    /// Who holds the admin session `token`, while it is unexpired and they
+    /// are still an active, unexpired admin, as [`Self::verify_admin`]
+    /// requires.
+    fn admin_session(&self, token: &str) -> Option<String> {
+        let now = Utc::now();
+        let name = {
+            let sessions = self.admin_sessions.lock().unwrap();
+            let session = sessions.get(token)?;
+            (session.expires_at > now).then(|| session.name.clone())?
+        };
+        
+        let still_admin = self.authorized_people.read().unwrap().values().any(|person| {
+            person.name == name
+                && person.role == PersonRole::Admin
+                && person.status == PersonStatus::Active
+                && !person.is_expired(now)
+        });
+        still_admin.then_some(name)
+    }
+    
+    /// Both frames must show the challenge's change and the same person
+    /// (or the same nobody), so a photo of someone else can't stand in for
+    /// either half of it.
+    async fn verify_liveness(
+        &self,
+        challenge: LivenessChallenge,
+        first_frame: &Bytes,
+        second_frame: &Bytes,
+    ) -> Result<bool> {
+        let (Some(first), Some(second)) = (
+            self.detect_primary_face(first_frame).await?,
+            self.detect_primary_face(second_frame).await?,
+        ) else {
+            return Ok(false);
+        };
+        
+        let passed = match challenge {
+            LivenessChallenge::Blink => match (first.eyes_open, second.eyes_open) {
+                (Some(a), Some(b)) => a != b,
+                _ => false,
+            },
+            LivenessChallenge::Turn => {
+                let first_yaw = first.pose.as_ref().map(|pose| pose.yaw);
+                let second_yaw = second.pose.as_ref().map(|pose| pose.yaw);
+                match (first_yaw, second_yaw) {
+                    (Some(a), Some(b)) => (a - b).abs() >= self.liveness_min_yaw_delta,
+                    _ => false,
+                }
+            }
+        };
+        if !passed {
+            return Ok(false);
+        }
+        
+        let threshold = self.search_threshold(self.global_threshold());
+        let first_person = self.frame_match_name(first_frame, threshold).await?;
+        let second_person = self.frame_match_name(second_frame, threshold).await?;
+        if first_person != second_person {
+            warn!(
+                "🕵️ Liveness frames show different people ({} then {})",
+                first_person.as_deref().unwrap_or("nobody known"),
+                second_person.as_deref().unwrap_or("nobody known")
+            );
+            return Ok(false);
+        }
+        
+        Ok(true)
+    }
+    
+    /// Who the face in the frame matches, if anyone.
+    async fn frame_match_name(&self, image_data: &Bytes, threshold: f32) -> Result<Option<String>> {
+        match self.face_recognizer.search_face(image_data, threshold).await {
+            Ok(found) => Ok(found.map(|found| self.match_name(&found))),
+            Err(e) if error::is_no_face(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    
+    fn log_access(&self, action: String, person_name: Option<String>, confidence: Option<f32>, access_granted: bool) {
+        self.record_access(AccessLog {
+            timestamp: Utc::now(),
+            action,
+            person_name,
+            confidence,
+            access_granted,
+            attributes: None,
+            deny_reason: None,
+            snapshot: None,
+            request_id: request_id::current(),
+            client_ip: client_ip::current().map(|ip| ip.to_string()),
+            door_confirmed: None,
+        });
+    }
+    
+    /// Best effort: a failed write is logged and never affects the decision.
+    fn save_snapshot(&self, image_data: &Bytes, timestamp: DateTime<Utc>) -> Option<String> {
+        if !self.snapshots_enabled {
+            return None;
+        }
+        
+        // Written in the background, off the recognition path; the id is
+        // known up front so the log entry can point at it
+        let id = snapshots::new_id(image_data, timestamp);
+        let dir = self.snapshot_dir.clone();
+        let image_data = image_data.clone();
+        let snapshot_id = id.clone();
+        tokio::spawn(async move {
+            match snapshots::save(&dir, &snapshot_id, &image_data).await {
+                Ok(()) => info!("📷 Saved denied-access snapshot {}", snapshot_id),
+                Err(e) => warn!("Failed to save snapshot {}: {}", snapshot_id, e),
+            }
+        });
+        Some(id)
+    }
+    
+    async fn prune_snapshots(&self) {
+        let max_age = Duration::from_secs(self.snapshot_retention_days * 24 * 60 * 60);
+        match snapshots::prune(&self.snapshot_dir, max_age).await {
+            Ok(0) => {}
+            Ok(removed) => info!("🧹 Pruned {} snapshots older than {} days", removed, self.snapshot_retention_days),
+            Err(e) => warn!("Failed to prune snapshots: {}", e),
+        }
+    }
+    
+    /// Trims the in-memory log and the store to `log_retention`.
+    async fn prune_logs(&self) {
+        let LogRetention { max_entries, max_age } = self.log_retention;
+        let cutoff = max_age.map(|max_age| Utc::now() - max_age);
+        
+        let trimmed = {
+            let mut logs = self.access_log.write().unwrap();
+            // Entries are appended as they happen, so everything past either
+            // limit sits at the front and one drain removes it in place
+            let too_old = cutoff.map_or(0, |cutoff| logs.partition_point(|log| log.timestamp < cutoff));
+            let too_many = logs.len().saturating_sub(max_entries);
+            let trimmed = too_old.max(too_many);
+            logs.drain(..trimmed);
+            trimmed
+        };
+        
+        // Entries still queued for the writer would otherwise outlive the prune
+        self.flush_logs().await;
+        match self.store.prune_logs(max_entries, cutoff).await {
+            Ok(deleted) if trimmed > 0 || deleted > 0 => {
+                info!("🧹 Pruned {} log entries from memory and {} from the database", trimmed, deleted);
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to prune the access log: {}", e),
+        }
+    }
+    
+    fn log_size(&self) -> usize {
+        self.access_log.read().unwrap().len()
+    }
+    
+    fn deny_access(
+        &self,
+        reason: DenyReason,
+        person_name: Option<String>,
+        confidence: Option<f32>,
+        image_data: Option<&Bytes>,
+    ) -> AccessCheckResponse {
+        let timestamp = Utc::now();
+        let snapshot = image_data.and_then(|image_data| self.save_snapshot(image_data, timestamp));
+        let message = self.deny_message(reason);
+        let action = match &person_name {
+            Some(name) => format!("🔴 Access DENIED - {} ({})", message, name),
+            None => format!("🔴 Access DENIED - {}", message),
+        };
+        
+        let log_entry = AccessLog {
+            timestamp,
+            action,
+            person_name,
+            confidence,
+            access_granted: false,
+            attributes: None,
+            deny_reason: Some(reason),
+            snapshot,
+            request_id: request_id::current(),
+            client_ip: client_ip::current().map(|ip| ip.to_string()),
+            door_confirmed: None,
+        };
+        if let Some(alerts) = &self.email_alerts {
+            alerts.denied(&log_entry, image_data);
+        }
+        self.record_access(log_entry);
+        
+        AccessCheckResponse {
+            access_granted: false,
+            person_name: None,
+            confidence: None,
+            similarity: None,
+            timestamp,
+            attributes: None,
+            deny_reason: Some(reason),
+            deny_message: Some(message),
+            retry_after_seconds: None,
+            face: None,
+            door_opened: false,
+            error_code: None,
+            faces_detected: 0,
+            prefiltered: false,
+            door_confirmed: None,
+        }
+    }
+    
+    /// The human-readable text for `reason`: its `DENY_MESSAGES` override,
+    /// or the built-in message.
+    fn deny_message(&self, reason: DenyReason) -> String {
+        self.deny_messages
+            .get(&reason)
+            .cloned()
+            .unwrap_or_else(|| reason.message().to_string())
+    }
+    
+    /// Denies without calling Rekognition while `source` (and, for uploads,
+    /// the calling client) is locked out.
+    fn check_lockout(&self, source: AccessSource, image_data: Option<&Bytes>) -> Option<AccessCheckResponse> {
+        let retry_after = self.lockout_retry_after(source)?;
+        let mut response = self.deny_access(DenyReason::LockedOut, None, None, image_data);
+        response.retry_after_seconds = Some(retry_after);
+        Some(response)
+    }
+    
+    /// Seconds left on the lockout for `source`, if one is engaged.
+    fn lockout_retry_after(&self, source: AccessSource) -> Option<u64> {
+        let key = LockoutKey::current(source);
+        let remaining = {
+            let mut attempts = self.failed_attempts.lock().unwrap();
+            let tracker = attempts.get_mut(&key)?;
+            let locked_until = tracker.locked_until?;
+            let now = Instant::now();
+            if now >= locked_until {
+                tracker.locked_until = None;
+                tracker.failures.clear();
+                return None;
+            }
+            locked_until - now
+        };
+        
+        // Round up so clients never retry a moment too early
+        Some(remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0))
+    }
+    
+    fn record_failure(&self, source: AccessSource) {
+        let key = LockoutKey::current(source);
+        let engaged = {
+            let mut attempts = self.failed_attempts.lock().unwrap();
+            let tracker = attempts.entry(key).or_default();
+            let now = Instant::now();
+            while tracker
+                .failures
+                .front()
+                .is_some_and(|failed_at| now.duration_since(*failed_at) > self.fail_window)
+            {
+                tracker.failures.pop_front();
+            }
+            tracker.failures.push_back(now);
+            
+            if tracker.failures.len() >= self.max_failed_attempts {
+                tracker.failures.clear();
+                tracker.locked_until = Some(now + self.lockout_duration);
+                true
+            } else {
+                false
+            }
+        };
+        
+        if engaged {
+            warn!(
+                "🔒 {} failed attempts from {} within {}s - locking out for {}s",
+                self.max_failed_attempts,
+                key,
+                self.fail_window.as_secs(),
+                self.lockout_duration.as_secs()
+            );
+            self.log_access(
+                format!("🔒 Locked out {} after {} failed attempts", key, self.max_failed_attempts),
+                None,
+                None,
+                false,
+            );
+        }
+    }
+    
+    fn clear_failures(&self, source: AccessSource) {
+        self.failed_attempts.lock().unwrap().remove(&LockoutKey::current(source));
+    }
+    
+    /// Lifts the lockout for every client of `source`, or for every source
+    /// when `None`.
+    fn unlock_lockout(&self, source: Option<AccessSource>, caller: &str) {
+        {
+            let mut attempts = self.failed_attempts.lock().unwrap();
+            match source {
+                Some(source) => attempts.retain(|key, _| key.source != source),
+                None => attempts.clear(),
+            }
+        }
+        let scope = source.map_or("all sources", |source| source.as_str());
+        self.log_access(format!("🔓 Lockout cleared for {} by {}", scope, caller), None, None, false);
+    }
+    
+    /// Served from a short-lived cache so dashboard refreshes don't each
+    /// cost a Rekognition call; the local counts are always current.
+    async fn collection_stats(&self) -> Result<CollectionStats> {
+        let cached = self
+            .collection_cache
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|cached| cached.fetched.elapsed() < COLLECTION_CACHE_TTL);
+        let CachedCollection { fetched_at, info, .. } = match cached {
+            Some(cached) => cached,
+            None => {
+                let cached = CachedCollection {
+                    info: self.face_recognizer.describe_collection().await?,
+                    fetched: Instant::now(),
+                    fetched_at: Utc::now(),
+                };
+                *self.collection_cache.lock().unwrap() = Some(cached.clone());
+                cached
+            }
+        };
+        
+        let (local_face_count, local_people_count) = {
+            let people = self.authorized_people.read().unwrap();
+            let names: HashSet<&str> = people.values().map(|person| person.name.as_str()).collect();
+            (people.len(), names.len())
+        };
+        if info.face_count != local_face_count as u64 {
+            warn!(
+                "⚠️ Collection has {} face(s) but {} are tracked locally - consider POST /api/resync",
+                info.face_count, local_face_count
+            );
+        }
+        
+        Ok(CollectionStats {
+            in_sync: info.face_count == local_face_count as u64,
+            face_count: info.face_count,
+            face_model_version: info.face_model_version,
+            collection_arn: info.arn,
+            local_face_count,
+            local_people_count,
+            fetched_at,
+        })
+    }
+    
+    /// With `CORS_ALLOWED_ORIGINS`, only those origins may call the API
+    /// from a browser, and they may send credentials; without it, any
+    /// origin may.
+    fn cors_layer(&self) -> CorsLayer {
+        let Some(origins) = &self.cors_allowed_origins else {
+            return CorsLayer::permissive();
+        };
+        
+        // Credentials rule out wildcards, so methods and headers are listed
+        let request_id_header = header::HeaderName::from_static(request_id::REQUEST_ID_HEADER);
+        CorsLayer::new()
+            .allow_origin(origins.clone())
+            .allow_credentials(true)
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+            .allow_headers([
+                header::CONTENT_TYPE,
+                header::HeaderName::from_static(auth::API_KEY_HEADER),
+                header::HeaderName::from_static(auth::ADMIN_SESSION_HEADER),
+                header::HeaderName::from_static(IDEMPOTENCY_KEY_HEADER),
+                request_id_header.clone(),
+            ])
+            .expose_headers([
+                request_id_header,
+                header::HeaderName::from_static(IDEMPOTENT_REPLAYED_HEADER),
+            ])
+    }
+    
+    fn lockdown_active(&self) -> bool {
+        self.lockdown.read().unwrap().is_some()
+    }
+    
+    fn lockdown_status(&self) -> LockdownStatus {
+        self.lockdown.read().unwrap().clone().into()
+    }
+    
+    /// Starts or clears the lockdown and persists it; starting one also
+    /// locks every door.
+    async fn set_lockdown(&self, active: bool, caller: &str) -> Result<LockdownStatus> {
+        let lockdown = active.then(|| Lockdown {
+            since: Utc::now(),
+            by: caller.to_string(),
+        });
+        self.store.set_setting(LOCKDOWN_SETTING, lockdown.as_ref()).await?;
+        *self.lockdown.write().unwrap() = lockdown.clone();
+        
+        match &lockdown {
+            Some(_) => {
+                self.log_access(format!("🚨 Lockdown started by {}", caller), None, None, false);
+                self.lock_all_doors().await;
+            }
+            None => self.log_access(format!("✅ Lockdown cleared by {}", caller), None, None, false),
+        }
+        
+        Ok(lockdown.into())
+    }
+    
+    /// Forced, so doors are locked even if we believe they already are.
+    async fn lock_all_doors(&self) {
+        if !self.door_control_enabled {
+            return;
+        }
+        
+        for camera in self.cameras.values() {
+            match self.command_door(camera, false, None, true).await {
+                Ok(()) => info!("🔒 Door '{}' locked for lockdown", camera.id),
+                Err(e) => warn!("🚨 Failed to lock door '{}' for lockdown: {}", camera.id, e),
+            }
+        }
+    }
+    
+    fn record_access(&self, log_entry: AccessLog) {
+        info!(
+            person_name = log_entry.person_name.as_deref(),
+            access_granted = log_entry.access_granted,
+            confidence = log_entry.confidence,
+            deny_reason = log_entry.deny_reason.map(|reason| reason.code()),
+            "📝 {}",
+            log_entry.action
+        );
+        if self.log_writer.send(LogCommand::Write(log_entry.clone())).is_err() {
+            warn!("Log writer has stopped; entry not persisted");
+        }
+        if let Some(url) = &self.webhook_url {
+            if self.webhook_events.matches(&log_entry) {
+                spawn_webhook(url.clone(), log_entry.clone(), self.webhook_secret.clone());
+            }
+        }
+        if let Some(mqtt) = &self.mqtt {
+            if log_entry.access_granted || log_entry.deny_reason.is_some() {
+                mqtt.access(&log_entry);
+            }
+        }
+        self.access_log.write().unwrap().push(log_entry.clone());
+        // No subscribers is not an error
+        let _ = self.events.send(log_entry);
+    }
+    
+    fn polling_status(&self) -> PollingStatus {
+        PollingStatus {
+            enabled: self.polling_enabled.load(Ordering::Relaxed),
+            interval_ms: self.poll_interval.as_millis() as u64,
+        }
+    }
+    
+    /// One hands-free check of the default camera. Nothing is captured
+    /// during a lockdown or lockout, which would only log a denial per
+    /// poll, and frames without a face are not access attempts.
+    async fn poll_once(&self) -> Result<Option<AccessCheckResponse>> {
+        if self.lockdown_active() || self.lockout_retry_after(AccessSource::Esp32).is_some() {
+            return Ok(None);
+        }
+        
+        let camera = self.default_camera();
+        let image_data = self.capture_from_esp32(&camera).await?;
+        let response = self
+            .detect_and_recognize(image_data, AccessSource::Esp32, &camera, None)
+            .await?;
+        
+        Ok((!response.is_no_face()).then_some(response))
+    }
+    
+    /// Waits until every log entry recorded so far has been written to the
+    /// store.
+    async fn flush_logs(&self) {
+        let (done, flushed) = oneshot::channel();
+        if self.log_writer.send(LogCommand::Flush(done)).is_err() || flushed.await.is_err() {
+            warn!("Log writer has stopped; pending entries may be lost");
+        }
+    }
+    
+    /// Runs once the server has stopped accepting requests: optionally
+    /// locks the door so a crash or restart fails safe, then flushes logs.
+    async fn shutdown(&self) {
+        if self.door_control_enabled && self.lock_on_shutdown {
+            for camera in self.cameras.values() {
+                match self.control_pico2_door(camera, false).await {
+                    Ok(()) => info!("🔒 Door '{}' locked for shutdown", camera.id),
+                    Err(e) => warn!("Failed to lock door '{}' on shutdown: {}", camera.id, e),
+                }
+            }
+        }
+        
+        self.log_access("🛑 Server shutting down".to_string(), None, None, false);
+        self.flush_logs().await;
+    }
+    
+    fn get_recent_logs(&self, limit: usize) -> Vec<AccessLog> {
+        let logs = self.access_log.read().unwrap();
+        logs.iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+    
+    /// Distinct people granted entry on each of the last `days` local days,
+    /// newest first, including empty days, from the persisted access log.
+    /// Also updates the `daily_unique_visitors` gauge with today's count.
+    async fn daily_unique_visitors(&self, days: i64) -> Result<Vec<DailyUniqueVisitors>> {
+        self.flush_logs().await;
+        
+        let days = days.clamp(1, DAILY_UNIQUE_MAX_DAYS);
+        let today = Utc::now().with_timezone(&self.timezone).date_naive();
+        let first_day = today - chrono::Duration::days(days - 1);
+        let mut per_day: BTreeMap<NaiveDate, (HashSet<String>, usize)> = (0..days)
+            .map(|offset| (first_day + chrono::Duration::days(offset), Default::default()))
+            .collect();
+        
+        // A day early, whatever the offset; buckets outside the range are dropped
+        let since = Utc::now() - chrono::Duration::days(days + 1);
+        for visits in self.store.hourly_visits(since).await? {
+            let day = visits.hour.with_timezone(&self.timezone).date_naive();
+            if let Some((visitors, grants)) = per_day.get_mut(&day) {
+                visitors.insert(visits.name);
+                *grants += visits.grants as usize;
+            }
+        }
+        
+        let today_visitors = per_day.get(&today).map_or(0, |(visitors, _)| visitors.len());
+        telemetry::set_daily_unique_visitors(today_visitors);
+        
+        Ok(per_day
+            .into_iter()
+            .rev()
+            .map(|(date, (visitors, grants))| DailyUniqueVisitors {
+                date,
+                unique_visitors: visitors.len(),
+                grants,
+            })
+            .collect())
+    }
+    
+    /// Aggregates the persisted access log. Pending log writes are flushed
+    /// first so the totals include the most recent decisions.
+    async fn stats(&self) -> Result<StatsResponse> {
+        self.flush_logs().await;
+        
+        let (activity, hourly) = tokio::try_join!(self.store.person_activity(), self.store.hourly_counts())?;
+        
+        let today = Utc::now().with_timezone(&self.timezone).date_naive();
+        let first_day = today - chrono::Duration::days(STATS_DAYS - 1);
+        let mut daily: BTreeMap<NaiveDate, (i64, i64)> = (0..STATS_DAYS)
+            .map(|offset| (first_day + chrono::Duration::days(offset), (0, 0)))
+            .collect();
+        let mut grants_by_hour = [0i64; 24];
+        let (mut total_grants, mut total_denies) = (0, 0);
+        
+        for bucket in &hourly {
+            total_grants += bucket.grants;
+            total_denies += bucket.denies;
+            
+            let local = bucket.hour.with_timezone(&self.timezone);
+            grants_by_hour[local.hour() as usize] += bucket.grants;
+            if let Some((grants, denies)) = daily.get_mut(&local.date_naive()) {
+                *grants += bucket.grants;
+                *denies += bucket.denies;
+            }
+        }
+        
+        let busiest_hour = (0..24u32)
+            .max_by_key(|&hour| (grants_by_hour[hour as usize], std::cmp::Reverse(hour)))
+            .filter(|&hour| grants_by_hour[hour as usize] > 0);
+        
+        Ok(StatsResponse {
+            total_grants,
+            total_denies,
+            people: activity
+                .into_iter()
+                .map(|person| PersonStats {
+                    name: person.name,
+                    grants: person.grants,
+                    last_seen: person.last_seen,
+                })
+                .collect(),
+            busiest_hour,
+            daily: daily
+                .into_iter()
+                .map(|(date, (grants, denies))| DailyAccessCounts { date, grants, denies })
+                .collect(),
+        })
+    }
+    
+    /// Removes every guest whose access has expired, from Rekognition and
+    /// the store.
+    async fn purge_expired_guests(&self) {
+        let now = Utc::now();
+        let expired: BTreeSet<String> = self
+            .authorized_people
+            .read()
+            .unwrap()
+            .values()
+            .filter(|person| person.is_expired(now))
+            .map(|person| person.name.clone())
+            .collect();
+        
+        for name in expired {
+            self.purge_guest(&name).await;
+        }
+    }
+    
+    async fn purge_guest(&self, name: &str) {
+        match self.remove_person(name).await {
+            Ok(Some(_)) => self.log_access(format!("⌛ Guest access expired: {}", name), Some(name.to_string()), None, false),
+            Ok(None) => {}
+            Err(e) => warn!("Failed to remove expired guest {}: {}", name, e),
+        }
+    }
+    
+    fn person_by_face(&self, face_id: &str) -> Option<AuthorizedPerson> {
+        self.authorized_people.read().unwrap().get(face_id).cloned()
+    }
+    
+    /// Ranks every face in the collection against the photo without acting
+    /// on the result, to see how close the near misses were.
+    async fn debug_search(&self, image_data: &Bytes, threshold: f32, max_faces: i32) -> Result<DebugSearchResponse> {
+        let global_threshold = self.global_threshold();
+        let matches = self
+            .face_recognizer
+            .search_candidates(image_data, threshold, max_faces)
+            .await?;
+        
+        let candidates = matches
+            .iter()
+            .map(|face_match| self.search_candidate(face_match, global_threshold))
+            .collect();
+        
+        Ok(DebugSearchResponse {
+            debug: true,
+            search_threshold: threshold,
+            global_threshold,
+            candidates,
+        })
+    }
+    
+    fn search_candidate(&self, face_match: &face::FaceMatch, global_threshold: f32) -> SearchCandidate {
+        let person = self.person_by_face(&face_match.face_id);
+        let required_similarity = person
+            .as_ref()
+            .and_then(|person| person.match_threshold)
+            .unwrap_or(global_threshold);
+        SearchCandidate {
+            in_registry: person.is_some(),
+            name: person.map_or_else(|| face_match.external_id.clone(), |person| person.name),
+            face_id: face_match.face_id.clone(),
+            similarity: face_match.similarity,
+            required_similarity,
+            meets_threshold: face_match.similarity >= required_similarity,
+        }
+    }
+    
+    /// Runs a stored snapshot back through recognition as it would be
+    /// judged now. Lockdown, the lockout, the PIN, the debounce and the
+    /// door are left out, and nothing is logged or counted as a failure.
+    async fn recheck_snapshot(&self, id: &str) -> Result<RecheckResponse> {
+        let path = snapshots::path_for(&self.snapshot_dir, id)
+            .ok_or_else(|| ApiError::BadRequest(format!("Invalid snapshot id '{}'", id)))?;
+        let image_data = match tokio::fs::read(&path).await {
+            Ok(image_data) => Bytes::from(image_data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ApiError::SnapshotNotFound(id.to_string()).into());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        
+        let (detail, companions) = if self.needs_face_detail() {
+            let mut faces = self.detect_all_faces(&image_data).await?.into_iter();
+            let detail = faces.next().ok_or(ApiError::NoFaceDetected)?;
+            (Some(detail), faces.collect::<Vec<_>>())
+        } else {
+            (None, Vec::new())
+        };
+        
+        let global_threshold = self.global_threshold();
+        let search_threshold = self.search_threshold(global_threshold);
+        let mut response = RecheckResponse {
+            snapshot: id.to_string(),
+            access_granted: false,
+            person_name: None,
+            similarity: None,
+            deny_reason: None,
+            pin_required: self.auth_mode == AuthMode::FacePin,
+            faces_detected: 1 + companions.len(),
+            search_threshold,
+            global_threshold,
+            candidates: Vec::new(),
+        };
+        if !self.companions_allowed(&image_data, &companions, AccessSource::Upload).await? {
+            response.deny_reason = Some(DenyReason::MultipleFaces);
+            return Ok(response);
+        }
+        
+        // One search serves both the decision and the candidate list, down
+        // to the debug search's floor so near misses show up too
+        let floor = (search_threshold - self.match_margin).min(DEBUG_SEARCH_THRESHOLD).max(0.0);
+        let candidates = self
+            .face_recognizer
+            .search_candidates(&image_data, floor, MATCH_MARGIN_CANDIDATES)
+            .await?;
+        response.candidates = candidates
+            .iter()
+            .map(|face_match| self.search_candidate(face_match, global_threshold))
+            .collect();
+        
+        let Some((best_match, runner_up)) = self.best_with_runner_up(&candidates, search_threshold) else {
+            response.deny_reason = Some(DenyReason::NotRecognized);
+            return Ok(response);
+        };
+        let runner_up = runner_up.filter(|_| self.match_margin > 0.0);
+        response.similarity = Some(best_match.similarity);
+        match self.judge_match(&best_match, runner_up.as_ref(), detail.as_ref(), Utc::now()) {
+            MatchVerdict::Allowed(person) => {
+                response.access_granted = true;
+                response.person_name = Some(person.name);
+            }
+            MatchVerdict::Denied(person, reason) => {
+                response.person_name = Some(person.name);
+                response.deny_reason = Some(reason);
+            }
+            MatchVerdict::Untracked => {
+                response.person_name = Some(best_match.external_id);
+                response.deny_reason = Some(DenyReason::NotInRegistry);
+            }
+        }
+        
+        Ok(response)
+    }
+    
+    async fn set_person_webhook(&self, name: &str, url: Option<String>) -> Result<bool> {
+        self.update_person(name, |person| person.on_grant_webhook = url.clone()).await
+    }
+    
+    async fn set_person_notify(&self, name: &str, targets: &NotifyTargets) -> Result<bool> {
+        self.update_person(name, |person| {
+            person.on_grant_webhook = targets.webhook_url.clone();
+            person.notify_email = targets.email.clone();
+        })
+        .await
+    }
+    
+    /// Fires `person`'s own notifications for a grant. Both run in the
+    /// background, so a slow or failing target never holds up the door.
+    fn notify_person(&self, person: &AuthorizedPerson, log_entry: &AccessLog) {
+        if let Some(url) = person.on_grant_webhook.clone() {
+            spawn_webhook(url, log_entry.clone(), self.webhook_secret.clone());
+        }
+        
+        let Some(email) = &person.notify_email else {
+            return;
+        };
+        match (&self.email_alerts, email.parse()) {
+            (Some(alerts), Ok(to)) => alerts.arrived(to, log_entry),
+            (None, _) => warn!("⚠️ Not emailing {} about {}: email alerts are not configured", email, person.name),
+            (_, Err(e)) => warn!("⚠️ Not emailing {} about {}: {}", email, person.name, e),
+        }
+    }
+    
+    /// Renames every face record of `name` locally. Face IDs and their
+    /// Rekognition external IDs stay as they are, so no re-indexing is needed.
+    async fn rename_person(&self, name: &str, new_name: &str) -> Result<bool> {
+        if self.get_authorized_people().iter().any(|existing| existing == new_name) {
+            return Err(ApiError::PersonExists(new_name.to_string()).into());
+        }
+        
+        self.update_person(name, |person| person.name = new_name.to_string()).await
+    }
+    
+    async fn set_person_schedule(&self, name: &str, schedule: Option<Vec<AccessWindow>>) -> Result<bool> {
+        self.update_person(name, |person| person.schedule = schedule.clone()).await
+    }
+    
+    async fn set_person_role(&self, name: &str, role: PersonRole) -> Result<bool> {
+        self.update_person(name, |person| person.role = role).await
+    }
+    
+    fn has_approved_face(&self, name: &str) -> bool {
+        self.authorized_people
+            .read()
+            .unwrap()
+            .values()
+            .any(|person| person.name == name && person.status == PersonStatus::Active)
+    }
+    
+    /// Activates `name`'s pending faces, returning how many there were, or
+    /// `None` when no such person exists.
+    async fn approve_person(&self, name: &str) -> Result<Option<usize>> {
+        let mut approved = 0;
+        let found = self
+            .update_person(name, |person| {
+                if person.status == PersonStatus::Pending {
+                    person.status = PersonStatus::Active;
+                    approved += 1;
+                }
+            })
+            .await?;
+        
+        Ok(found.then_some(approved))
+    }
+    
+    /// Deletes `name`'s pending faces from the collection and the registry,
+    /// returning how many went, or `None` when no such person exists.
+    /// Approved faces are left alone.
+    async fn reject_person(&self, name: &str) -> Result<Option<usize>> {
+        let (found, face_ids) = {
+            let people = self.authorized_people.read().unwrap();
+            let records: Vec<&AuthorizedPerson> = people.values().filter(|p| p.name == name).collect();
+            let face_ids: Vec<String> = records
+                .iter()
+                .filter(|p| p.status == PersonStatus::Pending)
+                .map(|p| p.face_id.clone())
+                .collect();
+            (!records.is_empty(), face_ids)
+        };
+        
+        if !found {
+            return Ok(None);
+        }
+        if face_ids.is_empty() {
+            return Ok(Some(0));
+        }
+        
+        self.forget_faces(&face_ids).await?;
+        self.update_enrollment_gauge();
+        
+        Ok(Some(face_ids.len()))
+    }
+    
+    /// Applies `update` to every face record registered under `name` and
+    /// persists the result. Returns `false` when no such person exists.
+    async fn update_person<F: FnMut(&mut AuthorizedPerson)>(&self, name: &str, mut update: F) -> Result<bool> {
+        let updated: Vec<AuthorizedPerson> = {
+            let mut people = self.authorized_people.write().unwrap();
+            people
+                .values_mut()
+                .filter(|p| p.name == name)
+                .map(|person| {
+                    update(person);
+                    person.clone()
+                })
+                .collect()
+        };
+        
+        for person in &updated {
+            self.store.upsert_person(person).await?;
+        }
+        
+        Ok(!updated.is_empty())
+    }
+    
+    async fn check_rekognition(&self) -> bool {
+        matches!(
+            tokio::time::timeout(HEALTH_CHECK_TIMEOUT, self.face_recognizer.is_healthy()).await,
+            Ok(true)
+        )
+    }
+    
+    async fn check_device(&self, url: &str) -> bool {
+        matches!(
+            tokio::time::timeout(HEALTH_CHECK_TIMEOUT, self.doors.is_reachable(url)).await,
+            Ok(true)
+        )
+    }
+    
+    /// Whether every camera, and every door, answered.
+    async fn check_cameras(&self) -> (bool, bool) {
+        let mut checks = tokio::task::JoinSet::new();
+        for camera in self.cameras.values() {
+            let state = self.clone();
+            let camera = camera.clone();
+            checks.spawn(async move {
+                tokio::join!(
+                    state.check_device(&camera.capture_url),
+                    state.check_device(&camera.door_url),
+                )
+            });
+        }
+        
+        let (mut esp32_ok, mut pico2_ok) = (true, true);
+        while let Some(result) = checks.join_next().await {
+            let (camera_ok, door_ok) = result.unwrap_or((false, false));
+            esp32_ok &= camera_ok;
+            pico2_ok &= door_ok;
+        }
+        (esp32_ok, pico2_ok)
+    }
+    
+    async fn health(&self) -> (bool, HealthResponse) {
+        let (rekognition_ok, (esp32_ok, pico2_ok)) = tokio::join!(self.check_rekognition(), self.check_cameras());
+        
+        // The ESP32-CAM is not critical: uploads still work without it.
+        let critical_ok = rekognition_ok && (pico2_ok || !self.door_control_enabled);
+        let status = if !critical_ok {
+            "unhealthy"
+        } else if esp32_ok && pico2_ok {
+            "ok"
+        } else {
+            "degraded"
+        };
+        let describe = |ok: bool| String::from(if ok { "ok" } else { "unreachable" });
+        
+        (
+            critical_ok,
+            HealthResponse {
+                rekognition: describe(rekognition_ok),
+                esp32_cam: describe(esp32_ok),
+                pico2: describe(pico2_ok),
+                status: status.to_string(),
+            },
+        )
+    }
+    
+    /// Distinct names, since one person may be enrolled with several faces.
+    fn update_enrollment_gauge(&self) {
+        telemetry::set_people_enrolled(self.get_authorized_people().len());
+    }
+    
+    /// The cached outcome of enrolling `name` under `caller`'s `key`,
+    /// created empty for a new key. Expired keys are dropped first.
+    fn idempotent_enrollment(
+        &self,
+        caller: &str,
+        key: &str,
+        name: &str,
+    ) -> Result<Arc<tokio::sync::Mutex<Option<AddPersonResponse>>>, ApiError> {
+        let mut enrollments = self.idempotent_enrollments.lock().unwrap();
+        enrollments.retain(|_, enrollment| enrollment.created.elapsed() < IDEMPOTENCY_TTL);
+        
+        let enrollment = enrollments
+            .entry((caller.to_string(), key.to_string()))
+            .or_insert_with(|| IdempotentEnrollment {
+                created: Instant::now(),
+                name: name.to_string(),
+                response: Arc::new(tokio::sync::Mutex::new(None)),
+            });
+        if enrollment.name != name {
+            return Err(ApiError::BadRequest(format!(
+                "Idempotency-Key '{}' was already used to enroll '{}'",
+                key, enrollment.name
+            )));
+        }
+        
+        Ok(enrollment.response.clone())
+    }
+    
+    fn get_authorized_people(&self) -> Vec<String> {
+        self.authorized_people
+            .read()
+            .unwrap()
+            .values()
+            .map(|p| p.name.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+    
+    /// One entry per name, with guests marked by their expiry.
+    fn list_people(&self) -> Vec<PersonListing> {
+        let mut listings = BTreeMap::new();
+        for person in self.authorized_people.read().unwrap().values() {
+            let listing = listings.entry(person.name.clone()).or_insert_with(|| PersonListing {
+                name: person.name.clone(),
+                guest: person.expires_at.is_some(),
+                expires_at: person.expires_at,
+                role: person.role,
+                status: PersonStatus::Pending,
+            });
+            if person.status == PersonStatus::Active {
+                listing.status = PersonStatus::Active;
+            }
+            if person.role == PersonRole::Admin {
+                listing.role = PersonRole::Admin;
+            }
+        }
+        
+        listings.into_values().collect()
+    }
+    
+    fn get_people_details(&self) -> Vec<AuthorizedPerson> {
+        self.authorized_people
+            .read()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Optional capture time of a frame pushed to `/api/ingest`, in Unix
+/// seconds by the camera's clock.
+const CAPTURED_AT_HEADER: &str = "x-captured-at";
+
+/// Oldest frame `/api/ingest` acts on, before `CLOCK_SKEW_TOLERANCE_SECONDS`.
+const MAX_INGEST_FRAME_AGE: chrono::Duration = chrono::Duration::seconds(10);
+
+/// How often the door is asked whether it opened, with `DOOR_CONFIRM`.
+const DOOR_CONFIRM_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Listening port unless `PORT` overrides it.
+const SERVER_PORT: u16 = 3000;
+
+/// Longest guest pass: 30 days.
+const MAX_GUEST_MINUTES: i64 = 30 * 24 * 60;
+
+/// How long an admin face sign-in lasts.
+const ADMIN_SESSION_MINUTES: i64 = 5;
+
+/// How long an `Idempotency-Key` on `POST /api/add-person` is remembered.
+const IDEMPOTENCY_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Longest `Idempotency-Key` accepted; a UUID needs 36.
+const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// `true` on a response repeated for a known `Idempotency-Key`.
+const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// How often expired guests are purged.
+const GUEST_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long `/api/collection` reuses a `describe_collection` answer.
+const COLLECTION_CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Comment sent on idle `/api/events` streams.
+const SSE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Settings key the active lockdown is persisted under.
+const LOCKDOWN_SETTING: &str = "lockdown";
+
+/// Candidates fetched to find the runner-up for `MATCH_MARGIN`; enough to
+/// get past several faces enrolled for the best match.
+const MATCH_MARGIN_CANDIDATES: i32 = 20;
+
+/// Defaults for `POST /api/debug/search`, and the most candidates it returns.
+const DEBUG_SEARCH_MAX_FACES: i32 = 10;
+const DEBUG_SEARCH_MAX_FACES_LIMIT: i32 = 100;
+const DEBUG_SEARCH_THRESHOLD: f32 = 40.0;
+
+/// How long a `/ws` client has to send its key under `PROTECT_READS`.
+const WS_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Part boundary of `/api/camera/preview?stream=true`.
+const PREVIEW_BOUNDARY: &str = "frame";
+
+/// How often the access log is trimmed to `LOG_RETENTION_*`.
+const LOG_PRUNE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Days covered by the daily series in `/api/stats`.
+const STATS_DAYS: i64 = 7;
+
+/// Most days `/api/stats/daily-unique` reports at once.
+const DAILY_UNIQUE_MAX_DAYS: i64 = 366;
+
+/// How long in-flight requests get to finish after a shutdown signal.
+/// Open WebSocket and SSE streams never finish on their own, so this also bounds them.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(10);
+
+/// Checks `pin` against a person's stored hash; a missing PIN or a person
+/// without one never matches.
+async fn verify_pin(pin_hash: Option<String>, pin: Option<&str>) -> bool {
+    let (Some(pin), Some(hash)) = (pin.map(str::to_string), pin_hash) else {
+        return false;
+    };
+    
+    // Argon2 is deliberately slow; keep it off the async workers
+    tokio::task::spawn_blocking(move || pins::verify(&pin, &hash))
+        .await
+        .unwrap_or(false)
+}
+
+/// Below these a photo still enrolls, but later matches tend to suffer.
+const ENROLL_WARN_BRIGHTNESS: f32 = 40.0;
+const ENROLL_WARN_SHARPNESS: f32 = 40.0;
+/// Roughly a face filling a fifth of the frame's width and height.
+const ENROLL_WARN_FACE_AREA: f32 = 0.04;
+const ENROLL_WARN_POSE_DEGREES: f32 = 20.0;
+
+fn photo_quality(photo: usize, detail: &face::DetectedFace) -> PhotoQuality {
+    let yaw = detail.pose.as_ref().map(|pose| pose.yaw);
+    let pitch = detail.pose.as_ref().map(|pose| pose.pitch);
+    let mut issues = Vec::new();
+    
+    if let Some(brightness) = detail.brightness.filter(|&value| value < ENROLL_WARN_BRIGHTNESS) {
+        issues.push(format!("too dark (brightness {:.0})", brightness));
+    }
+    if let Some(sharpness) = detail.sharpness.filter(|&value| value < ENROLL_WARN_SHARPNESS) {
+        issues.push(format!("blurry (sharpness {:.0})", sharpness));
+    }
+    if detail.area() < ENROLL_WARN_FACE_AREA {
+        issues.push(format!("face too small ({:.1}% of the frame)", detail.area() * 100.0));
+    }
+    let off_angle = [yaw, pitch]
+        .into_iter()
+        .flatten()
+        .any(|angle| angle.abs() > ENROLL_WARN_POSE_DEGREES);
+    if off_angle {
+        issues.push(format!(
+            "face turned away (yaw {:.0}°, pitch {:.0}°)",
+            yaw.unwrap_or(0.0),
+            pitch.unwrap_or(0.0)
+        ));
+    }
+    
+    PhotoQuality {
+        photo,
+        brightness: detail.brightness,
+        sharpness: detail.sharpness,
+        face_area: detail.area(),
+        yaw,
+        pitch,
+        issues,
+        rejected: false,
+    }
+}
+
+/// Thresholds and engine scores are similarities in percent; API and log
+/// `confidence` values are the same number as a 0-1 fraction.
+fn similarity_to_confidence(similarity: f32) -> f32 {
+    similarity / 100.0
+}
+
+fn face_geometry(
+    bounding_box: Option<FaceBox>,
+    pose: Option<FacePose>,
+    dimensions: Option<(u32, u32)>,
+) -> FaceGeometry {
+    FaceGeometry {
+        bounding_box,
+        pose,
+        image_width: dimensions.map(|(width, _)| width),
+        image_height: dimensions.map(|(_, height)| height),
+    }
+}
+
+/// Exponential backoff with up to one base delay of random jitter.
+fn backoff_delay(base_delay_ms: u64, attempt: u32) -> Duration {
+    let exponential = base_delay_ms.saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1)));
+    let jitter = rand::thread_rng().gen_range(0..=base_delay_ms);
+    Duration::from_millis(exponential.saturating_add(jitter))
+}
+
+const WEBHOOK_MAX_ATTEMPTS: u32 = 3;
+
+/// Header carrying `sha256=<hex HMAC of the body>` when `WEBHOOK_SECRET` is set.
+const WEBHOOK_SIGNATURE_HEADER: &str = "x-signature-256";
+
+fn webhook_signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("sha256={}", digest)
+}
+
+/// Delivers `payload` as JSON in the background, retrying with backoff so
+/// the access decision never waits on the receiver.
+fn spawn_webhook<T: Serialize + Send + 'static>(url: String, payload: T, secret: Option<String>) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("⚠️ Failed to serialize webhook payload for {}: {}", url, e);
+                return;
+            }
+        };
+        let signature = secret.map(|secret| webhook_signature(&secret, &body));
+        
+        for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+            let mut request = client
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(WEBHOOK_SIGNATURE_HEADER, signature);
+            }
+            
+            match request.send().await {
+                Ok(response) if response.status().is_success() => {
+                    info!("🪝 Webhook delivered to {}", url);
+                    return;
+                }
+                Ok(response) => {
+                    warn!("⚠️ Webhook {} attempt {} failed: {}", url, attempt, response.status());
+                }
+                Err(e) => {
+                    warn!("⚠️ Webhook {} attempt {} failed: {}", url, attempt, e);
+                }
+            }
+            
+            if attempt < WEBHOOK_MAX_ATTEMPTS {
+                tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt - 1))).await;
+            }
+        }
+        
+        warn!("⚠️ Giving up on webhook {} after {} attempts", url, WEBHOOK_MAX_ATTEMPTS);
+    });
+}
+
+// Web handlers
+async fn dashboard(State(state): State<AppState>) -> Result<Html<String>, ApiError> {
+    let html = dashboard::render(&state).map_err(|e| ApiError::Internal(format!("Failed to render dashboard: {}", e)))?;
+    Ok(Html(html))
+}
+
+/// Background loop behind `POLL_ENABLED`; it idles while polling is
+/// switched off so `/api/polling` can turn it on without a restart.
+async fn run_guest_sweeper(state: AppState) {
+    loop {
+        tokio::time::sleep(GUEST_SWEEP_INTERVAL).await;
+        state.purge_expired_guests().await;
+    }
+}
+
+async fn run_polling(state: AppState) {
+    loop {
+        tokio::time::sleep(state.poll_interval).await;
+        if !state.polling_enabled.load(Ordering::Relaxed) {
+            continue;
+        }
+        
+        match state.poll_once().await {
+            // Let the door relock before looking again, instead of re-granting every tick
+            Ok(Some(response)) if response.access_granted => {
+                tokio::time::sleep(Duration::from_secs(state.door_unlock_seconds)).await;
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Polling check failed: {}", e),
+        }
+    }
+}
+
+/// A camera failure as the API reports it: the device's own error when it
+/// gave one, otherwise unreachable.
+fn camera_error(error: anyhow::Error) -> ApiError {
+    match error.downcast::<ApiError>() {
+        Ok(api_error) => api_error,
+        Err(e) => ApiError::Esp32Unreachable(e.to_string()),
+    }
+}
+
+/// Runs [`images::normalize`] on the blocking pool so large decodes don't
+/// stall the runtime.
+async fn normalize_upload(image_data: Bytes) -> Result<Bytes> {
+    let normalized = tokio::task::spawn_blocking(move || images::normalize(&image_data)).await??;
+    Ok(normalized)
+}
+
+async fn crop_face(image_data: Bytes, bounds: FaceBox) -> Result<Bytes> {
+    let cropped = tokio::task::spawn_blocking(move || images::crop(&image_data, &bounds)).await??;
+    Ok(cropped)
+}
+
+/// Reads an enrollment form: an optional `name` field and one or more
+/// `photo` fields.
+async fn read_enrollment_form(multipart: &mut Multipart) -> Result<(Option<String>, Vec<Bytes>), ApiError> {
+    let mut name = None;
+    let mut photos = Vec::new();
+    
+    while let Some(field) = multipart.next_field().await? {
+        let field_name = field.name().unwrap_or("");
+        
+        match field_name {
+            "name" => {
+                name = Some(form::name(field).await?);
+            }
+            "photo" => {
+                photos.push(form::image(field).await?);
+            }
+            _ => return Err(form::unexpected(&field)),
+        }
+    }
+    
+    if photos.is_empty() {
+        return Err(ApiError::BadRequest("At least one photo is required".to_string()));
+    }
+    
+    Ok((name, photos))
+}
+
+/// Reads a form with a single `photo` field.
+async fn read_photo_form(multipart: &mut Multipart) -> Result<Bytes, ApiError> {
+    let mut image_data = None;
+    
+    while let Some(field) = multipart.next_field().await? {
+        match field.name().unwrap_or("") {
+            "photo" => image_data = Some(form::image(field).await?),
+            _ => return Err(form::unexpected(&field)),
+        }
+    }
+    
+    image_data.ok_or_else(|| ApiError::BadRequest("Missing photo".to_string()))
+}
+
+/// With an `Idempotency-Key` header, a retry of a successful enrollment
+/// gets the original response back (marked `Idempotent-Replayed: true`)
+/// instead of indexing the photos again. A retry that arrives while the
+/// first request is still running waits for it.
+async fn add_person_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Response, ApiError> {
+    let (name, photos) = read_enrollment_form(&mut multipart).await?;
+    let name = name.ok_or_else(|| ApiError::BadRequest("Missing name".to_string()))?;
+    
+    let Some(key) = idempotency_key(&headers)? else {
+        let response = state.add_person(name, photos, EnrollmentSource::Upload, &caller.name, None).await?;
+        return Ok(enrolled(response).into_response());
+    };
+    
+    let enrollment = state.idempotent_enrollment(&caller.name, &key, &name)?;
+    let mut cached = enrollment.lock().await;
+    let replayed = cached.is_some();
+    let response = match cached.as_ref() {
+        Some(response) => {
+            info!("🔁 Replaying the enrollment of {} for Idempotency-Key '{}'", name, key);
+            response.clone()
+        }
+        None => {
+            let response = state.add_person(name, photos, EnrollmentSource::Upload, &caller.name, None).await?;
+            *cached = Some(response.clone());
+            response
+        }
+    };
+    
+    Ok((
+        [(IDEMPOTENT_REPLAYED_HEADER, if replayed { "true" } else { "false" })],
+        enrolled(response),
+    )
+        .into_response())
+}
+
+fn enrolled(response: AddPersonResponse) -> Json<ApiResponse<AddPersonResponse>> {
+    Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+/// The `Idempotency-Key` header, if sent: printable ASCII, at most
+/// `IDEMPOTENCY_KEY_MAX_LENGTH` long.
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, ApiError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+    
+    // Not printable ASCII reads as empty
+    let key = value.to_str().map(str::trim).unwrap_or("");
+    if key.is_empty() || key.len() > IDEMPOTENCY_KEY_MAX_LENGTH {
+        return Err(ApiError::BadRequest(format!(
+            "Idempotency-Key must be 1-{} printable ASCII characters",
+            IDEMPOTENCY_KEY_MAX_LENGTH
+        )));
+    }
+    
+    Ok(Some(key.to_string()))
+}
+
+/// Enrolls a temporary guest from `name`, `photo` and `expires_in_minutes`
+/// form fields. The guest's faces are purged once the time is up.
+async fn add_guest_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<AddPersonResponse>>, ApiError> {
+    let mut name = None;
+    let mut photos = Vec::new();
+    let mut expires_in_minutes = None;
+    
+    while let Some(field) = multipart.next_field().await? {
+        match field.name().unwrap_or("") {
+            "name" => name = Some(form::name(field).await?),
+            "photo" => photos.push(form::image(field).await?),
+            "expires_in_minutes" => expires_in_minutes = Some(form::text(field, form::MAX_TEXT_LENGTH).await?),
+            _ => return Err(form::unexpected(&field)),
+        }
+    }
+    
+    let missing = |field: &str| ApiError::BadRequest(format!("Missing {}", field));
+    let name = name.ok_or_else(|| missing("name"))?;
+    if photos.is_empty() {
+        return Err(missing("photo"));
+    }
+    let minutes = expires_in_minutes
+        .ok_or_else(|| missing("expires_in_minutes"))?
+        .trim()
+        .parse::<i64>()
+        .ok()
+        .filter(|minutes| (1..=MAX_GUEST_MINUTES).contains(minutes))
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!("expires_in_minutes must be between 1 and {}", MAX_GUEST_MINUTES))
+        })?;
+    
+    // A guest must not pick up (or expire) an existing person's faces
+    if state.get_authorized_people().contains(&name) {
+        return Err(ApiError::PersonExists(name));
+    }
+    
+    let expires_at = Utc::now() + chrono::Duration::minutes(minutes);
+    let response = state
+        .add_person(name.clone(), photos, EnrollmentSource::Upload, &caller.name, Some(expires_at))
+        .await?;
+    info!("🎟️ Guest {} can enter until {}", name, expires_at);
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Enrolls everyone in a zip of `PersonName.jpg` files (multipart field
+/// `archive`). Each person is enrolled independently; the result maps every
+/// name to `ok` or the lowercased error code that stopped it.
+async fn bulk_enroll_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<BTreeMap<String, String>>>, ApiError> {
+    let mut archive_data = None;
+    while let Some(field) = multipart.next_field().await? {
+        match field.name().unwrap_or("") {
+            "archive" => archive_data = Some(field.bytes().await?),
+            _ => return Err(form::unexpected(&field)),
+        }
+    }
+    let archive_data = archive_data.ok_or_else(|| ApiError::BadRequest("Missing archive".to_string()))?;
+    
+    let archive = tokio::task::spawn_blocking(move || bulk::read_archive(&archive_data))
+        .await
+        .map_err(|e| ApiError::Internal(e.to_string()))??;
+    info!(
+        "📦 Bulk enrollment of {} person(s) ({} rejected files)",
+        archive.photos.len(),
+        archive.rejected.len()
+    );
+    
+    let mut results = archive.rejected;
+    for (name, photos) in archive.photos {
+        let outcome = match state.add_person(name.clone(), photos, EnrollmentSource::Bulk, &caller.name, None).await {
+            Ok(_) => "ok".to_string(),
+            Err(e) => {
+                let error = ApiError::from(e);
+                warn!("⚠️ Bulk enrollment of {} failed: {}", name, error);
+                error.code().to_lowercase()
+            }
+        };
+        results.insert(name, outcome);
+    }
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(results),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn add_person_photos_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Path(name): Path<String>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<AddPersonResponse>>, ApiError> {
+    let (_, photos) = read_enrollment_form(&mut multipart).await?;
+    
+    if !state.get_authorized_people().contains(&name) {
+        return Err(ApiError::PersonNotFound(name));
+    }
+    
+    let response = state.add_person(name, photos, EnrollmentSource::Upload, &caller.name, None).await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn remove_person_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<ApiResponse<RemovePersonResponse>>, ApiError> {
+    let response = state
+        .remove_person(&name)
+        .await?
+        .ok_or(ApiError::PersonNotFound(name))?;
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn rename_person_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(request): Json<RenamePersonRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let new_name = request.new_name.trim().to_string();
+    if new_name.is_empty() {
+        return Err(ApiError::BadRequest("new_name must not be empty".to_string()));
+    }
+    form::validate_name(&new_name)?;
+    if new_name == name {
+        return Err(ApiError::BadRequest("new_name is the same as the current name".to_string()));
+    }
+    
+    if !state.rename_person(&name, &new_name).await? {
+        return Err(ApiError::PersonNotFound(name));
+    }
+    
+    let message = format!("✅ Renamed {} to {}", name, new_name);
+    state.log_access(format!("✏️ {}", message), Some(new_name), None, false);
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn check_access_handler(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<AccessCheckResponse>>, ApiError> {
+    if state.liveness_challenge {
+        return Err(ApiError::LivenessRequired);
+    }
+    if state.auth_mode == AuthMode::FacePin {
+        return Err(ApiError::PinRequired);
+    }
+    
+    let image_data = read_photo_form(&mut multipart).await?;
+    let image_data = normalize_upload(image_data).await?;
+    let response = state
+        .recognize_face(image_data, AccessSource::Upload, &state.default_camera(), None)
+        .await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Debugging aid: lists the candidate matches for a photo. Unlike an
+/// access check it never opens a door, logs an access or counts a failure.
+async fn debug_search_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DebugSearchQuery>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<DebugSearchResponse>>, ApiError> {
+    let threshold = query.threshold.unwrap_or(DEBUG_SEARCH_THRESHOLD);
+    if !(0.0..=100.0).contains(&threshold) {
+        return Err(ApiError::BadRequest("threshold must be between 0 and 100".to_string()));
+    }
+    let max_faces = query.max_faces.unwrap_or(DEBUG_SEARCH_MAX_FACES);
+    if !(1..=DEBUG_SEARCH_MAX_FACES_LIMIT).contains(&max_faces) {
+        return Err(ApiError::BadRequest(format!(
+            "max_faces must be between 1 and {}",
+            DEBUG_SEARCH_MAX_FACES_LIMIT
+        )));
+    }
+    
+    let image_data = read_photo_form(&mut multipart).await?;
+    let image_data = normalize_upload(image_data).await?;
+    let response = state.debug_search(&image_data, threshold, max_faces).await?;
+    info!(
+        "🔬 Debug search found {} candidate(s) at or above {:.1}%",
+        response.candidates.len(),
+        threshold
+    );
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Replays a stored snapshot through recognition, e.g. to try a new
+/// threshold on a real frame. Like the debug search it never opens a door,
+/// logs an access or counts a failure.
+async fn recheck_snapshot_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<RecheckResponse>>, ApiError> {
+    let response = state.recheck_snapshot(&id).await?;
+    info!(
+        "🔬 Recheck of snapshot {}: {}",
+        id,
+        if response.access_granted { "would be granted" } else { "would be denied" }
+    );
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn check_access_esp32_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CameraQuery>,
+) -> Result<Json<ApiResponse<AccessCheckResponse>>, ApiError> {
+    if state.liveness_challenge {
+        return Err(ApiError::LivenessRequired);
+    }
+    if state.auth_mode == AuthMode::FacePin {
+        return Err(ApiError::PinRequired);
+    }
+    
+    let camera = state.camera(query.camera.as_deref())?;
+    let image_data = state.capture_from_esp32(&camera).await?;
+    let response = state
+        .recognize_face(image_data, AccessSource::Esp32, &camera, None)
+        .await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// The push counterpart of `check_access_esp32_handler`: the camera sends
+/// its frame, either as a raw image body or as a multipart `photo` field,
+/// and gets the decision back. `?camera=` names the door to open. A frame
+/// with an `X-Captured-At` is refused once it's stale.
+async fn ingest_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CameraQuery>,
+    request: axum::extract::Request,
+) -> Result<Json<ApiResponse<AccessCheckResponse>>, ApiError> {
+    if state.liveness_challenge {
+        return Err(ApiError::LivenessRequired);
+    }
+    if state.auth_mode == AuthMode::FacePin {
+        return Err(ApiError::PinRequired);
+    }
+    
+    let camera = state.camera(query.camera.as_deref())?;
+    if let Some(captured_at) = request.headers().get(CAPTURED_AT_HEADER) {
+        let captured_at = captured_at
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse::<i64>().ok())
+            .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+            .ok_or_else(|| ApiError::BadRequest("X-Captured-At must be Unix seconds".to_string()))?;
+        let now = Utc::now();
+        if let Some(skew) = state.device_clock_skew(captured_at, now - MAX_INGEST_FRAME_AGE, now) {
+            warn!(
+                "🕰️ Camera '{}' pushed a frame captured {}s outside the freshness window - is its clock off?",
+                camera.id,
+                skew.num_seconds()
+            );
+            return Err(ApiError::StaleFrame(format!(
+                "captured at {}, {}s outside the freshness window",
+                captured_at.to_rfc3339(),
+                skew.num_seconds()
+            )));
+        }
+    }
+    
+    let is_multipart = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+    
+    let image_data = if is_multipart {
+        let mut multipart = Multipart::from_request(request, &state)
+            .await
+            .map_err(|e| ApiError::BadRequest(e.body_text()))?;
+        read_photo_form(&mut multipart).await?
+    } else {
+        Bytes::from_request(request, &state)
+            .await
+            .map_err(|e| ApiError::BadRequest(e.body_text()))?
+    };
+    if image_data.is_empty() {
+        return Err(ApiError::BadRequest("Empty image body".to_string()));
+    }
+    
+    info!("📥 Received {} bytes pushed by camera '{}'", image_data.len(), camera.id);
+    let image_data = normalize_upload(image_data).await?;
+    let response = state
+        .recognize_face(image_data, AccessSource::Esp32, &camera, None)
+        .await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Signs an admin in by face and PIN. The face is a `photo`, or without
+/// one a frame captured from `camera` (or the default camera). Under
+/// `LIVENESS_CHALLENGE` it is the `frame1`/`frame2` answer to a challenge
+/// from `/api/liveness/start` instead, as for `/api/liveness/verify`. The
+/// token goes in `X-Admin-Session` in place of an API key.
+async fn admin_verify_handler(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<AdminSessionResponse>>, ApiError> {
+    let mut pin = None;
+    let mut camera = None;
+    let mut image_data = None;
+    let mut session_id = None;
+    let mut first_frame = None;
+    let mut second_frame = None;
+    
+    while let Some(field) = multipart.next_field().await? {
+        match field.name().unwrap_or("") {
+            "pin" => {
+                pin = Some(form::text(field, form::MAX_TEXT_LENGTH).await?);
+            }
+            "camera" => {
+                camera = Some(form::text(field, form::MAX_TEXT_LENGTH).await?);
+            }
+            "photo" => {
+                image_data = Some(form::image(field).await?);
+            }
+            "session_id" => {
+                session_id = Some(form::text(field, form::MAX_TEXT_LENGTH).await?);
+            }
+            "frame1" => {
+                first_frame = Some(form::image(field).await?);
+            }
+            "frame2" => {
+                second_frame = Some(form::image(field).await?);
+            }
+            _ => return Err(form::unexpected(&field)),
+        }
+    }
+    
+    let missing = |field: &str| ApiError::BadRequest(format!("Missing {}", field));
+    let pin = pin.ok_or_else(|| missing("pin"))?;
+    let session = if state.liveness_challenge {
+        // A single photo or capture can't answer the challenge
+        let session_id = session_id.ok_or_else(|| missing("session_id"))?;
+        let first_frame = normalize_upload(first_frame.ok_or_else(|| missing("frame1"))?).await?;
+        let second_frame = normalize_upload(second_frame.ok_or_else(|| missing("frame2"))?).await?;
+        if let Some(retry_after) = state.lockout_retry_after(AccessSource::Upload) {
+            return Err(ApiError::RateLimited(retry_after));
+        }
+        let challenge = state
+            .take_liveness_session(&session_id)
+            .ok_or(ApiError::LivenessSessionExpired)?
+            .challenge;
+        if !state.verify_liveness(challenge, &first_frame, &second_frame).await? {
+            warn!("🕵️ Liveness challenge failed for an admin sign-in");
+            state.record_failure(AccessSource::Upload);
+            return Err(ApiError::NotAdmin);
+        }
+        state.verify_admin(second_frame, &pin, AccessSource::Upload).await?
+    } else if let Some(image_data) = image_data {
+        let image_data = normalize_upload(image_data).await?;
+        state.verify_admin(image_data, &pin, AccessSource::Upload).await?
+    } else {
+        let camera = state.camera(camera.as_deref())?;
+        let image_data = state.capture_from_esp32(&camera).await?;
+        state.verify_admin(image_data, &pin, AccessSource::Esp32).await?
+    };
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(session),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Face + keypad PIN. The photo is optional: without one, a frame is
+/// captured from the ESP32-CAM next to the keypad (the `camera` field, or
+/// the default camera).
+async fn check_access_2fa_handler(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<AccessCheckResponse>>, ApiError> {
+    if state.liveness_challenge {
+        return Err(ApiError::LivenessRequired);
+    }
+    
+    let mut image_data = None;
+    let mut pin = None;
+    let mut camera = None;
+    
+    while let Some(field) = multipart.next_field().await? {
+        match field.name().unwrap_or("") {
+            "photo" => {
+                image_data = Some(form::image(field).await?);
+            }
+            "pin" => {
+                pin = Some(form::text(field, form::MAX_TEXT_LENGTH).await?);
+            }
+            "camera" => {
+                camera = Some(form::text(field, form::MAX_TEXT_LENGTH).await?);
+            }
+            _ => return Err(form::unexpected(&field)),
+        }
+    }
+    
+    let pin = pin.ok_or_else(|| ApiError::BadRequest("Missing pin".to_string()))?;
+    let camera = state.camera(camera.as_deref())?;
+    
+    let response = match image_data {
+        Some(image_data) => {
+            let image_data = normalize_upload(image_data).await?;
+            state
+                .recognize_face(image_data, AccessSource::Upload, &camera, Some(&pin))
+                .await?
+        }
+        None => {
+            let image_data = state.capture_from_esp32(&camera).await?;
+            state
+                .recognize_face(image_data, AccessSource::Esp32, &camera, Some(&pin))
+                .await?
+        }
+    };
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn liveness_start_handler(State(state): State<AppState>) -> Json<ApiResponse<LivenessStartResponse>> {
+    Json(ApiResponse {
+        success: true,
+        data: Some(state.start_liveness_session()),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+async fn liveness_verify_handler(
+    State(state): State<AppState>,
+    mut multipart: Multipart,
+) -> Result<Json<ApiResponse<AccessCheckResponse>>, ApiError> {
+    let mut session_id = None;
+    let mut first_frame = None;
+    let mut second_frame = None;
+    let mut pin = None;
+    
+    while let Some(field) = multipart.next_field().await? {
+        let field_name = field.name().unwrap_or("");
+        
+        match field_name {
+            "session_id" => {
+                session_id = Some(form::text(field, form::MAX_TEXT_LENGTH).await?);
+            }
+            "frame1" => {
+                first_frame = Some(form::image(field).await?);
+            }
+            "frame2" => {
+                second_frame = Some(form::image(field).await?);
+            }
+            "pin" => {
+                pin = Some(form::text(field, form::MAX_TEXT_LENGTH).await?);
+            }
+            _ => return Err(form::unexpected(&field)),
+        }
+    }
+    
+    let missing = |field: &str| ApiError::BadRequest(format!("Missing {}", field));
+    let session_id = session_id.ok_or_else(|| missing("session_id"))?;
+    let first_frame = first_frame.ok_or_else(|| missing("frame1"))?;
+    let second_frame = second_frame.ok_or_else(|| missing("frame2"))?;
+    if state.auth_mode == AuthMode::FacePin && pin.is_none() {
+        return Err(missing("pin"));
+    }
+    let first_frame = normalize_upload(first_frame).await?;
+    let second_frame = normalize_upload(second_frame).await?;
+    
+    if let Some(response) = state.check_lockout(AccessSource::Upload, None) {
+        telemetry::record_decision(AccessSource::Upload, false, response.deny_reason);
+        return Ok(Json(ApiResponse {
+            success: true,
+            data: Some(response),
+            error: None,
+            error_code: None,
+            request_id: request_id::current(),
+        }));
+    }
+    
+    let session = state
+        .take_liveness_session(&session_id)
+        .ok_or(ApiError::LivenessSessionExpired)?;
+    
+    let response = if state.verify_liveness(session.challenge, &first_frame, &second_frame).await? {
+        state
+            .recognize_face(second_frame, AccessSource::Upload, &state.default_camera(), pin.as_deref())
+            .await?
+    } else {
+        let response = state.deny_access(DenyReason::LivenessFailed, None, None, Some(&second_frame));
+        state.record_failure(AccessSource::Upload);
+        telemetry::record_decision(AccessSource::Upload, false, response.deny_reason);
+        response
+    };
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(response),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn list_people_handler(State(state): State<AppState>) -> Json<ApiResponse<Vec<PersonListing>>> {
+    let people = state.list_people();
+    Json(ApiResponse {
+        success: true,
+        data: Some(people),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+async fn door_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Json(request): Json<DoorRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if !state.door_control_enabled {
+        return Err(ApiError::DoorControlDisabled);
+    }
+    
+    let camera = state.camera(request.camera.as_deref())?;
+    state.manual_door_control(&camera, request.action, &caller.name).await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(format!("✅ Door '{}' {} command sent", camera.id, request.action.as_str())),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Install-time check of a single door, outside the access flow. Like a
+/// manual override, it can't unlock during a lockdown.
+async fn test_door_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Query(query): Query<DoorTestQuery>,
+) -> Result<Json<ApiResponse<DoorTestResult>>, ApiError> {
+    if !state.door_control_enabled {
+        return Err(ApiError::DoorControlDisabled);
+    }
+    
+    if query.action == DoorAction::Unlock && state.lockdown_active() {
+        return Err(ApiError::LockdownActive);
+    }
+    
+    let camera = state.camera(query.camera.as_deref())?;
+    let result = state.test_door(&camera, query.action, &caller.name).await;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(result),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Install-time check of a single camera, outside the access flow.
+async fn test_camera_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CameraTestQuery>,
+) -> Result<Response, ApiError> {
+    let camera = state.camera(query.camera.as_deref())?;
+    let (summary, image_data) = state.test_camera(&camera).await;
+    
+    if let (true, Some(image_data)) = (query.image, image_data) {
+        return Ok(([(header::CONTENT_TYPE, images::mime_type(&image_data))], image_data).into_response());
+    }
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(summary),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+    .into_response())
+}
+
+/// The camera's current frame, or with `stream=true` an MJPEG stream of
+/// `multipart/x-mixed-replace` parts at no more than `PREVIEW_MAX_FPS`,
+/// so a browser can watch a camera it can't reach itself.
+async fn camera_preview_handler(
+    State(state): State<AppState>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<Response, ApiError> {
+    if state.preview_interval.is_none() {
+        return Err(ApiError::PreviewDisabled);
+    }
+    let camera = state.camera(query.camera.as_deref())?;
+    let no_store = (header::CACHE_CONTROL, "no-store".to_string());
+    
+    if !query.stream {
+        let image_data = state.capture_from_esp32(&camera).await?;
+        let content_type = (header::CONTENT_TYPE, images::mime_type(&image_data).to_string());
+        return Ok(([content_type, no_store], image_data).into_response());
+    }
+    
+    let frames = state.preview_camera(&camera).await?;
+    let camera_id = camera.id.clone();
+    let parts = frames
+        .take_until(tokio::time::sleep(devices::PREVIEW_MAX_DURATION))
+        .scan((), move |_, frame| {
+            let part = match frame {
+                Ok(frame) => Some(mjpeg_part(&frame)),
+                Err(e) => {
+                    warn!("⚠️ Preview of camera '{}' ended: {}", camera_id, e);
+                    None
+                }
+            };
+            future::ready(part)
+        })
+        .map(Ok::<_, Infallible>);
+    let content_type = (
+        header::CONTENT_TYPE,
+        format!("multipart/x-mixed-replace; boundary={}", PREVIEW_BOUNDARY),
+    );
+    Ok(([content_type, no_store], Body::from_stream(parts)).into_response())
+}
+
+/// One frame of a preview stream, boundary first.
+fn mjpeg_part(frame: &Bytes) -> Bytes {
+    let mut part = format!(
+        "--{}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+        PREVIEW_BOUNDARY,
+        images::mime_type(frame),
+        frame.len()
+    )
+    .into_bytes();
+    part.extend_from_slice(frame);
+    part.extend_from_slice(b"\r\n");
+    Bytes::from(part)
+}
+
+async fn door_status_handler(
+    State(state): State<AppState>,
+    Query(query): Query<CameraQuery>,
+) -> Result<Json<ApiResponse<DoorStatus>>, ApiError> {
+    let camera = state.camera(query.camera.as_deref())?;
+    let status = state.door_status(&camera).await;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(status),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn version_handler(State(state): State<AppState>) -> Json<ApiResponse<version::VersionInfo>> {
+    Json(ApiResponse {
+        success: true,
+        data: Some(state.version.as_ref().clone()),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+async fn backup_handler(State(state): State<AppState>) -> Json<ApiResponse<backup::Backup>> {
+    Json(ApiResponse {
+        success: true,
+        data: Some(backup::export(&state)),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+async fn restore_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Json(backup): Json<backup::Backup>,
+) -> Result<Json<ApiResponse<backup::RestoreSummary>>, ApiError> {
+    // A backup carries statuses, roles and PIN hashes, so restoring one is
+    // as good as approving and promoting anyone in it
+    if !caller.admin {
+        return Err(ApiError::Forbidden("Only the admin API key can restore a backup".to_string()));
+    }
+    
+    let summary = backup::restore(&state, backup, &caller.name).await?;
+    state.log_access(format!("♻️ Backup restored by {}", caller.name), None, None, false);
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(summary),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn selftest_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+) -> Json<ApiResponse<selftest::SelfTestReport>> {
+    info!("🧪 Self-test requested by {}", caller.name);
+    let report = selftest::run(&state).await;
+    state.log_access(
+        format!(
+            "🧪 Self-test {} - requested by {}",
+            if report.passed { "passed" } else { "FAILED" },
+            caller.name
+        ),
+        None,
+        None,
+        false,
+    );
+    Json(ApiResponse {
+        success: true,
+        data: Some(report),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+async fn collection_handler(State(state): State<AppState>) -> Result<Json<ApiResponse<CollectionStats>>, ApiError> {
+    let stats = state.collection_stats().await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(stats),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn resync_handler(State(state): State<AppState>) -> Result<Json<ApiResponse<ResyncSummary>>, ApiError> {
+    let summary = state.resync_faces().await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(summary),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn polling_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Json(request): Json<PollingRequest>,
+) -> Result<Json<ApiResponse<PollingStatus>>, ApiError> {
+    if request.enabled && state.liveness_challenge {
+        return Err(ApiError::LivenessRequired);
+    }
+    if request.enabled && state.auth_mode == AuthMode::FacePin {
+        return Err(ApiError::PinRequired);
+    }
+    
+    state.polling_enabled.store(request.enabled, Ordering::Relaxed);
+    state.log_access(
+        format!(
+            "🔁 Auto-polling {} by {}",
+            if request.enabled { "enabled" } else { "disabled" },
+            caller.name
+        ),
+        None,
+        None,
+        false,
+    );
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(state.polling_status()),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Public, since browsers can't set headers on a WebSocket; with
+/// `PROTECT_READS` on, the client's first message must be an API key or
+/// admin session token before any event is sent.
+async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
+    ws.on_upgrade(move |socket| async move {
+        let Some(socket) = authenticate_socket(&state, socket).await else {
+            return;
+        };
+        let events = state.events.subscribe();
+        stream_access_events(socket, events).await
+    })
+}
+
+/// The socket, once its first message proved the client may read; it is
+/// closed with a policy-violation code otherwise.
+async fn authenticate_socket(state: &AppState, mut socket: WebSocket) -> Option<WebSocket> {
+    if !state.protect_reads {
+        return Some(socket);
+    }
+    
+    let authorized = match tokio::time::timeout(WS_AUTH_TIMEOUT, socket.recv()).await {
+        Ok(Some(Ok(Message::Text(token)))) => auth::authenticate_token(state, token.trim()).is_some(),
+        _ => false,
+    };
+    if authorized {
+        return Some(socket);
+    }
+    
+    warn!("🔑 WebSocket client closed - no valid API key in its first message");
+    let close = CloseFrame {
+        code: close_code::POLICY,
+        reason: "API key required".into(),
+    };
+    let _ = socket.send(Message::Close(Some(close))).await;
+    None
+}
+
+/// Sent to a live-event client in place of the events it fell too far
+/// behind to receive, so it knows its view is incomplete.
+#[derive(Serialize)]
+struct MissedEvents {
+    missed: u64,
+}
+
+async fn stream_access_events(mut socket: WebSocket, mut events: broadcast::Receiver<AccessLog>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let json = match event {
+                    Ok(log_entry) => serde_json::to_string(&log_entry),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("⚠️ WebSocket client lagged, skipped {} events", skipped);
+                        serde_json::to_string(&MissedEvents { missed: skipped })
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                let Ok(json) = json else {
+                    continue;
+                };
+                if socket.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// The same events as `/ws`, as Server-Sent Events for a plain
+/// `EventSource`. A heartbeat comment keeps idle proxies from closing it.
+async fn events_handler(State(state): State<AppState>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let events = futures::stream::unfold(state.events.subscribe(), |mut events| async move {
+        loop {
+            match events.recv().await {
+                Ok(log_entry) => match Event::default().json_data(&log_entry) {
+                    Ok(event) => return Some((Ok(event), events)),
+                    Err(e) => warn!("Failed to serialize SSE event: {}", e),
+                },
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("⚠️ SSE client lagged, skipped {} events", skipped);
+                    match Event::default().event("missed").json_data(MissedEvents { missed: skipped }) {
+                        Ok(event) => return Some((Ok(event), events)),
+                        Err(e) => warn!("Failed to serialize SSE event: {}", e),
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    
+    Sse::new(events).keep_alive(KeepAlive::new().interval(SSE_HEARTBEAT_INTERVAL).text("heartbeat"))
+}
+
+/// Served through `ServeFile`, so `Range` requests get 206 and the
+/// dashboard can seek without downloading the whole image first.
+async fn snapshot_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    request: Request,
+) -> Result<Response, ApiError> {
+    let path = snapshots::path_for(&state.snapshot_dir, &id)
+        .ok_or_else(|| ApiError::BadRequest(format!("Invalid snapshot id '{}'", id)))?;
+    let response = ServeFile::new(path)
+        .oneshot(request)
+        .await
+        .unwrap_or_else(|never| match never {});
+    
+    match response.status() {
+        StatusCode::NOT_FOUND => Err(ApiError::SnapshotNotFound(id)),
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            // Keep `Content-Range: bytes */<len>` so the client can retry
+            let mut error = ApiError::RangeNotSatisfiable.into_response();
+            if let Some(range) = response.headers().get(header::CONTENT_RANGE) {
+                error.headers_mut().insert(header::CONTENT_RANGE, range.clone());
+            }
+            Ok(error)
+        }
+        _ => Ok(response.map(Body::new)),
+    }
+}
+
+/// Downloads the full access log as CSV (default) or JSON, streamed from
+/// the store.
+async fn export_logs_handler(State(state): State<AppState>, Query(query): Query<ExportQuery>) -> Response {
+    state.flush_logs().await;
+    
+    let format = query.format;
+    let filename = format!("access-log-{}.{}", Utc::now().format("%Y-%m-%d"), format.extension());
+    (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        Body::from_stream(export::stream(state.store.clone(), format)),
+    )
+        .into_response()
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> ([(header::HeaderName, &'static str); 1], String) {
+    // Today's count changes at midnight without any grant, so it's taken
+    // at scrape time rather than kept up to date
+    if let Err(e) = state.daily_unique_visitors(1).await {
+        warn!("Failed to count today's unique visitors: {}", e);
+    }
+    
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+async fn health_handler(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let (healthy, response) = state.health().await;
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    
+    (status, Json(response))
+}
+
+async fn logs_handler(
+    State(state): State<AppState>,
+    Query(query): Query<LogsQuery>,
+) -> Json<ApiResponse<Vec<AccessLog>>> {
+    let logs = state.get_recent_logs(query.limit.unwrap_or(50));
+    Json(ApiResponse {
+        success: true,
+        data: Some(logs),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+async fn set_person_webhook_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(request): Json<SetWebhookRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    reqwest::Url::parse(&request.url).map_err(|e| ApiError::BadRequest(format!("Invalid webhook URL: {}", e)))?;
+    
+    if !state.set_person_webhook(&name, Some(request.url.clone())).await? {
+        return Err(ApiError::PersonNotFound(name));
+    }
+    
+    info!("🪝 Set grant webhook for {} to {}", name, request.url);
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(format!("✅ Grant webhook set for {}", name)),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn clear_person_webhook_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if !state.set_person_webhook(&name, None).await? {
+        return Err(ApiError::PersonNotFound(name));
     }
     
-    async fn capture_from_esp32(&self) -> Result<Bytes> {
-        info!("📸 Capturing image from ESP32-CAM at {}", self.esp32_cam_url);
-        
-        let response = reqwest::get(&self.esp32_cam_url).await?;
-        
-        if response.status().is_success() {
-            let image_data = response.bytes().await?;
-            info!("✅ Captured {} bytes from ESP32-CAM", image_data.len());
-            Ok(image_data)
-        } else {
-            Err(anyhow::anyhow!("ESP32-CAM capture failed: {}", response.status()))
+    info!("🪝 Cleared grant webhook for {}", name);
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(format!("✅ Grant webhook cleared for {}", name)),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn set_person_notify_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(targets): Json<NotifyTargets>,
+) -> Result<Json<ApiResponse<NotifyTargets>>, ApiError> {
+    if let Some(url) = &targets.webhook_url {
+        reqwest::Url::parse(url).map_err(|e| ApiError::BadRequest(format!("Invalid webhook URL: {}", e)))?;
+    }
+    if let Some(email) = &targets.email {
+        email
+            .parse::<lettre::message::Mailbox>()
+            .map_err(|e| ApiError::BadRequest(format!("Invalid email address: {}", e)))?;
+        if state.email_alerts.is_none() {
+            return Err(ApiError::BadRequest(
+                "Email notifications need EMAIL_ALERTS_ENABLED and the SMTP settings".to_string(),
+            ));
         }
     }
     
-    async fn control_pico2_door(&self, unlock: bool) -> Result<()> {
-        let action = if unlock { "unlock" } else { "lock" };
-        info!("🚪 Sending {} command to Pico 2", action);
-        
-        let payload = serde_json::json!({
-            "action": action,
-            "timestamp": Utc::now().timestamp()
-        });
-        
-        let client = reqwest::Client::new();
-        let response = client
-            .post(&self.pico2_door_url)
-            .json(&payload)
-            .send()
-            .await?;
-        
-        if response.status().is_success() {
-            info!("✅ Pico 2 door {} successful", action);
-        } else {
-            warn!("⚠️ Pico 2 door {} failed: {}", action, response.status());
-        }
-        
-        Ok(())
+    if !state.set_person_notify(&name, &targets).await? {
+        return Err(ApiError::PersonNotFound(name));
     }
     
-    async fn add_person(&self, name: String, image_data: Bytes) -> Result<AddPersonResponse> {
-        info!("➕ Adding person '{}' to collection", name);
-        
-        let image = Image::builder()
-            .bytes(image_data.to_vec().into())
-            .build();
-        
-        let response = self
-            .rekognition_client
-            .index_faces()
-            .collection_id(&self.collection_id)
-            .image(image)
-            .external_image_id(&name)
-            .max_faces(1)
-            .quality_filter(QualityFilter::Auto)
-            .send()
-            .await?;
-        
-        if let Some(face_records) = response.face_records {
-            if let Some(face_record) = face_records.first() {
-                if let Some(face) = &face_record.face {
-                    if let Some(face_id) = &face.face_id {
-                        let person = AuthorizedPerson {
-                            name: name.clone(),
-                            face_id: face_id.clone(),
-                            external_image_id: name.clone(),
-                            added_at: Utc::now(),
-                        };
-                        
-                        self.authorized_people
-                            .lock()
-                            .unwrap()
-                            .insert(face_id.clone(), person);
-                        
-                        self.log_access(
-                            format!("➕ Added authorized person: {}", name),
-                            Some(name.clone()),
-                            None,
-                            false,
-                        );
-                        
-                        return Ok(AddPersonResponse {
-                            face_id: face_id.clone(),
-                            message: format!("✅ Successfully added {}", name),
-                        });
-                    }
-                }
-            }
+    info!(
+        "🔔 Set notifications for {}: webhook {}, email {}",
+        name,
+        targets.webhook_url.as_deref().unwrap_or("none"),
+        targets.email.as_deref().unwrap_or("none")
+    );
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(targets),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn set_person_schedule_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(request): Json<SetScheduleRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if let Some(windows) = &request.windows {
+        if windows.iter().any(|window| window.start >= window.end || window.days.is_empty()) {
+            return Err(ApiError::BadRequest(
+                "Each window needs at least one day and a start before its end".to_string(),
+            ));
         }
-        
-        Err(anyhow::anyhow!("No face detected in image"))
     }
     
-    async fn recognize_face(&self, image_data: Bytes) -> Result<AccessCheckResponse> {
-        info!("🔍 Attempting face recognition...");
-        
-        let image = Image::builder()
-            .bytes(image_data.to_vec().into())
-            .build();
-        
-        let response = self
-            .rekognition_client
-            .search_faces_by_image()
-            .collection_id(&self.collection_id)
-            .image(image)
-            .max_faces(1)
-            .face_match_threshold(self.confidence_threshold)
-            .send()
-            .await?;
-        
-        let timestamp = Utc::now();
-        
-        if let Some(face_matches) = response.face_matches {
-            if let Some(face_match) = face_matches.first() {
-                if let (Some(face), Some(similarity)) = (&face_match.face, face_match.similarity) {
-                    if let Some(external_id) = &face.external_image_id {
-                        let confidence = similarity / 100.0;
-                        
-                        // Control door
-                        if let Err(e) = self.control_pico2_door(true).await {
-                            warn!("Failed to unlock door: {}", e);
-                        }
-                        
-                        self.log_access(
-                            format!("🟢 Access GRANTED - {}", external_id),
-                            Some(external_id.clone()),
-                            Some(confidence),
-                            true,
-                        );
-                        
-                        return Ok(AccessCheckResponse {
-                            access_granted: true,
-                            person_name: Some(external_id.clone()),
-                            confidence: Some(confidence),
-                            timestamp,
-                        });
-                    }
-                }
-            }
-        }
-        
-        self.log_access(
-            "🔴 Access DENIED - Face not recognized".to_string(),
-            None,
-            None,
-            false,
-        );
-        
-        Ok(AccessCheckResponse {
-            access_granted: false,
-            person_name: None,
-            confidence: None,
-            timestamp,
-        })
+    if !state.set_person_schedule(&name, request.windows.clone()).await? {
+        return Err(ApiError::PersonNotFound(name));
     }
     
-    fn log_access(&self, action: String, person_name: Option<String>, confidence: Option<f32>, access_granted: bool) {
-        let log_entry = AccessLog {
-            timestamp: Utc::now(),
-            action: action.clone(),
-            person_name,
-            confidence,
-            access_granted,
-        };
-        
-        self.access_log.lock().unwrap().push(log_entry);
-        info!("📝 {}", action);
+    let message = match &request.windows {
+        Some(windows) => format!("✅ Schedule for {} set to {} window(s)", name, windows.len()),
+        None => format!("✅ Schedule for {} cleared", name),
+    };
+    state.log_access(format!("🗓️ {}", message), Some(name), None, false);
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// Admins only: the PIN is the second factor of `/api/admin/verify`.
+async fn set_person_pin_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Path(name): Path<String>,
+    Json(request): Json<SetPinRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if !caller.admin {
+        return Err(ApiError::Forbidden("Only the admin API key or an admin's face session can set PINs".to_string()));
     }
+    pins::validate(&request.pin).map_err(ApiError::BadRequest)?;
     
-    fn get_recent_logs(&self, limit: usize) -> Vec<AccessLog> {
-        let logs = self.access_log.lock().unwrap();
-        logs.iter()
-            .rev()
-            .take(limit)
-            .cloned()
-            .collect()
+    if !state.set_person_pin(&name, &request.pin).await? {
+        return Err(ApiError::PersonNotFound(name));
     }
     
-    fn get_authorized_people(&self) -> Vec<String> {
-        self.authorized_people
-            .lock()
-            .unwrap()
-            .values()
-            .map(|p| p.name.clone())
-            .collect()
+    let message = format!("✅ PIN set for {}", name);
+    state.log_access(format!("🔢 {}", message), Some(name), None, false);
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+fn validate_threshold(threshold: f32) -> Result<(), ApiError> {
+    if !(0.0..=100.0).contains(&threshold) {
+        return Err(ApiError::BadRequest("Threshold must be between 0 and 100".to_string()));
     }
+    
+    Ok(())
 }
 
-// Web handlers
-async fn dashboard(State(state): State<AppState>) -> Html<String> {
-    let logs = state.get_recent_logs(10);
-    let people = state.get_authorized_people();
-    
-    let html = format!(r#"
-<!DOCTYPE html>
-<html>
-<head>
-    <title>🦀 Smart Door Lock - Rust + AWS</title>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <style>
-        * {{ box-sizing: border-box; }}
-        body {{ 
-            font-family: 'SF Pro Display', -apple-system, BlinkMacSystemFont, 'Segoe UI', Roboto, sans-serif;
-            margin: 0; padding: 20px; background: linear-gradient(135deg, #667eea 0%, #764ba2 100%);
-            min-height: 100vh; color: #333;
-        }}
-        .container {{ max-width: 1200px; margin: 0 auto; }}
-        .card {{ 
-            background: rgba(255, 255, 255, 0.95); backdrop-filter: blur(10px);
-            padding: 25px; margin: 20px 0; border-radius: 16px; 
-            box-shadow: 0 8px 32px rgba(0,0,0,0.1); border: 1px solid rgba(255,255,255,0.2);
-        }}
-        .status {{ 
-            padding: 20px; margin: 15px 0; border-radius: 12px; 
-            border-left: 5px solid;
-        }}
-        .success {{ 
-            background: linear-gradient(135deg, #d4edda, #c3e6cb); 
-            color: #155724; border-left-color: #28a745; 
-        }}
-        .info {{ 
-            background: linear-gradient(135deg, #d1ecf1, #bee5eb); 
-            color: #0c5460; border-left-color: #17a2b8; 
-        }}
-        .warning {{ 
-            background: linear-gradient(135deg, #fff3cd, #ffeaa7); 
-            color: #856404; border-left-color: #ffc107; 
-        }}
-        button {{ 
-            padding: 12px 24px; margin: 8px; border: none; border-radius: 8px; 
-            cursor: pointer; font-weight: 600; font-size: 14px;
-            transition: all 0.3s ease; text-transform: uppercase; letter-spacing: 0.5px;
-        }}
-        .btn-primary {{ background: linear-gradient(135deg, #007bff, #0056b3); color: white; }}
-        .btn-success {{ background: linear-gradient(135deg, #28a745, #1e7e34); color: white; }}
-        .btn-warning {{ background: linear-gradient(135deg, #ffc107, #e0a800); color: #212529; }}
-        .btn-danger {{ background: linear-gradient(135deg, #dc3545, #c82333); color: white; }}
-        button:hover {{ transform: translateY(-2px); box-shadow: 0 8px 25px rgba(0,0,0,0.15); }}
-        input[type="file"], input[type="text"] {{ 
-            margin: 10px 0; padding: 12px; border: 2px solid #ddd; 
-            border-radius: 8px; width: 280px; font-size: 14px;
-        }}
-        .stats {{ display: grid; grid-template-columns: repeat(auto-fit, minmax(200px, 1fr)); gap: 20px; }}
-        .stat {{ text-align: center; padding: 20px; }}
-        .stat-number {{ font-size: 3em; font-weight: 800; color: #007bff; margin-bottom: 5px; }}
-        .stat-label {{ font-size: 14px; color: #666; text-transform: uppercase; letter-spacing: 1px; }}
-        h1 {{ 
-            color: white; text-align: center; margin-bottom: 30px; font-size: 2.5em; 
-            text-shadow: 0 2px 4px rgba(0,0,0,0.3); font-weight: 700;
-        }}
-        h3 {{ 
-            color: #2c3e50; border-bottom: 3px solid #3498db; 
-            padding-bottom: 10px; margin-bottom: 20px; font-size: 1.2em;
-        }}
-        .log-entry {{ 
-            padding: 12px; margin: 5px 0; border-radius: 8px;
-            display: flex; justify-content: space-between; align-items: center;
-            background: rgba(248, 249, 250, 0.8);
-        }}
-        .access-granted {{ 
-            background: linear-gradient(135deg, #d4edda, #c3e6cb) !important;
-            border-left: 4px solid #28a745; color: #155724; font-weight: 600;
-        }}
-        .access-denied {{ 
-            background: linear-gradient(135deg, #f8d7da, #f1c2c7) !important;
-            border-left: 4px solid #dc3545; color: #721c24; font-weight: 600;
-        }}
-        .rust-badge {{
-            position: absolute; top: 20px; right: 20px; 
-            background: linear-gradient(135deg, #ce422b, #a33622);
-            color: white; padding: 8px 16px; border-radius: 20px;
-            font-size: 12px; font-weight: 600; text-transform: uppercase;
-        }}
-        .feature-grid {{ 
-            display: grid; grid-template-columns: repeat(auto-fit, minmax(250px, 1fr)); 
-            gap: 15px; margin: 20px 0; 
-        }}
-        .feature {{ 
-            padding: 15px; background: rgba(255,255,255,0.7); 
-            border-radius: 10px; text-align: center; 
-        }}
-    </style>
-</head>
-<body>
-    <div class="rust-badge">⚡ Powered by Rust</div>
-    <div class="container">
-        <h1>🦀 Smart Door Lock</h1>
-        
-        <div class="status success">
-            <h3>🎯 System Status</h3>
-            <div class="stats">
-                <div class="stat">
-                    <div class="stat-number">{}</div>
-                    <div class="stat-label">Authorized People</div>
-                </div>
-                <div class="stat">
-                    <div class="stat-number">{}</div>
-                    <div class="stat-label">Access Attempts</div>
-                </div>
-                <div class="stat">
-                    <div class="stat-number">AWS</div>
-                    <div class="stat-label">Rekognition</div>
-                </div>
-            </div>
-        </div>
-        
-        <div class="feature-grid">
-            <div class="feature">
-                <h4>🚀 High Performance</h4>
-                <p>Rust's zero-cost abstractions for maximum speed</p>
-            </div>
-            <div class="feature">
-                <h4>🔒 Memory Safe</h4>
-                <p>No buffer overflows or memory leaks</p>
-            </div>
-            <div class="feature">
-                <h4>☁️ AWS Powered</h4>
-                <p>Enterprise-grade face recognition</p>
-            </div>
-            <div class="feature">
-                <h4>🔗 IoT Ready</h4>
-                <p>ESP32-CAM + Pico 2 integration</p>
-            </div>
-        </div>
-        
-        <div class="card">
-            <h3>➕ Add Authorized Person</h3>
-            <input type="text" id="person-name" placeholder="Enter person name">
-            <input type="file" id="face-photo" accept="image/*">
-            <button class="btn-success" onclick="addPerson()">Add Person</button>
-        </div>
-        
-        <div class="card">
-            <h3>🔍 Access Control</h3>
-            <button class="btn-primary" onclick="checkAccessESP32()">📸 Check Access (ESP32-CAM)</button>
-            <input type="file" id="test-photo" accept="image/*" style="display: inline-block; width: 200px;">
-            <button class="btn-warning" onclick="testAccessUpload()">🧪 Test Upload</button>
-            <button class="btn-info" onclick="listPeople()">👥 List People</button>
-        </div>
-        
-        <div class="card">
-            <h3>📋 Recent Access Log</h3>
-            <div id="log">
-                {}
-            </div>
-        </div>
-        
-        <div class="status info">
-            <h3>🔗 Hardware Integration</h3>
-            <p><strong>ESP32-CAM:</strong> Captures images automatically</p>
-            <p><strong>Pico 2 (Rust):</strong> Controls door lock mechanism</p>
-            <p><strong>Current Mode:</strong> Manual testing + Hardware ready</p>
-        </div>
-    </div>
-    
-    <script>
-        async function addPerson() {{
-            const name = document.getElementById('person-name').value;
-            const fileInput = document.getElementById('face-photo');
-            
-            if (!name || !fileInput.files[0]) {{
-                alert('Please enter name and select a photo');
-                return;
-            }}
-            
-            const formData = new FormData();
-            formData.append('name', name);
-            formData.append('photo', fileInput.files[0]);
-            
-            try {{
-                const response = await fetch('/api/add-person', {{
-                    method: 'POST',
-                    body: formData
-                }});
-                
-                const data = await response.json();
-                
-                if (data.success) {{
-                    alert('✅ ' + data.data.message);
-                    location.reload();
-                }} else {{
-                    alert('❌ Error: ' + data.error);
-                }}
-            }} catch (error) {{
-                alert('❌ Network error: ' + error.message);
-            }}
-        }}
-        
-        async function checkAccessESP32() {{
-            try {{
-                const response = await fetch('/api/check-access-esp32', {{
-                    method: 'POST'
-                }});
-                
-                const data = await response.json();
-                
-                if (data.success) {{
-                    const result = data.data.access_granted ? '🟢 ACCESS GRANTED' : '🔴 ACCESS DENIED';
-                    const person = data.data.person_name || 'Unknown';
-                    const confidence = data.data.confidence ? Math.round(data.data.confidence * 100) + '%' : 'N/A';
-                    
-                    alert(`${{result}}\\n\\nPerson: ${{person}}\\nConfidence: ${{confidence}}`);
-                    location.reload();
-                }} else {{
-                    alert('❌ Error: ' + data.error);
-                }}
-            }} catch (error) {{
-                alert('❌ Network error: ' + error.message);
-            }}
-        }}
-        
-        async function testAccessUpload() {{
-            const fileInput = document.getElementById('test-photo');
-            
-            if (!fileInput.files[0]) {{
-                alert('Please select a photo to test');
-                return;
-            }}
-            
-            const formData = new FormData();
-            formData.append('photo', fileInput.files[0]);
-            
-            try {{
-                const response = await fetch('/api/check-access', {{
-                    method: 'POST',
-                    body: formData
-                }});
-                
-                const data = await response.json();
-                
-                if (data.success) {{
-                    const result = data.data.access_granted ? '🟢 ACCESS GRANTED' : '🔴 ACCESS DENIED';
-                    const person = data.data.person_name || 'Unknown';
-                    const confidence = data.data.confidence ? Math.round(data.data.confidence * 100) + '%' : 'N/A';
-                    
-                    alert(`${{result}}\\n\\nPerson: ${{person}}\\nConfidence: ${{confidence}}`);
-                    location.reload();
-                }} else {{
-                    alert('❌ Error: ' + data.error);
-                }}
-            }} catch (error) {{
-                alert('❌ Network error: ' + error.message);
-            }}
-        }}
-        
-        async function listPeople() {{
-            try {{
-                const response = await fetch('/api/list-people');
-                const data = await response.json();
-                
-                if (data.success && data.data.length > 0) {{
-                    const people = data.data.join('\\n• ');
-                    alert(`👥 Authorized People (${{data.data.length}})::\\n\\n• ${{people}}`);
-                }} else {{
-                    alert('👥 No authorized people found\\n\\nAdd someone using the form above!');
-                }}
-            }} catch (error) {{
-                alert('❌ Network error: ' + error.message);
-            }}
-        }}
-    </script>
-</body>
-</html>
-    "#, 
-    people.len(),
-    logs.len(),
-    logs.iter()
-        .map(|log| {
-            let status_class = if log.access_granted { "access-granted" } else { "access-denied" };
-            let confidence = log.confidence
-                .map(|c| format!(" ({}%)", (c * 100.0) as i32))
-                .unwrap_or_default();
-            
-            format!(
-                r#"<div class="log-entry {}">
-                    <span><strong>{}</strong> - {}</span>
-                    <span>{}</span>
-                </div>"#,
-                status_class,
-                log.timestamp.format("%m-%d %H:%M:%S"),
-                log.action,
-                confidence
-            )
-        })
-        .collect::<Vec<_>>()
-        .join("")
-    );
+async fn set_global_threshold_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Json(request): Json<SetThresholdRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let threshold = request
+        .threshold
+        .ok_or_else(|| ApiError::BadRequest("Missing threshold".to_string()))?;
+    validate_threshold(threshold)?;
     
-    Html(html)
+    state.set_global_threshold(threshold);
+    let message = format!("✅ Match threshold set to {:.1}%", threshold);
+    state.log_access(format!("🎯 {} by {}", message, caller.name), None, None, false);
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
 }
 
-async fn add_person_handler(
+async fn approve_person_handler(
     State(state): State<AppState>,
-    mut multipart: Multipart,
-) -> Result<Json<ApiResponse<AddPersonResponse>>, StatusCode> {
-    let mut name = None;
-    let mut image_data = None;
+    Extension(caller): Extension<auth::Caller>,
+    Path(name): Path<String>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if !caller.admin {
+        return Err(ApiError::Forbidden("Only the admin API key or an admin's face session can approve".to_string()));
+    }
     
-    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
-        let field_name = field.name().unwrap_or("");
-        
-        match field_name {
-            "name" => {
-                name = Some(field.text().await.map_err(|_| StatusCode::BAD_REQUEST)?);
-            }
-            "photo" => {
-                image_data = Some(field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?);
-            }
-            _ => {}
-        }
+    let approved = state
+        .approve_person(&name)
+        .await?
+        .ok_or_else(|| ApiError::PersonNotFound(name.clone()))?;
+    if approved == 0 {
+        return Err(ApiError::BadRequest(format!("{} has no faces awaiting approval", name)));
     }
     
-    let name = name.ok_or(StatusCode::BAD_REQUEST)?;
-    let image_data = image_data.ok_or(StatusCode::BAD_REQUEST)?;
+    let message = format!("✅ Approved {} face(s) of {}", approved, name);
+    state.log_access(format!("{} by {}", message, caller.name), Some(name), None, false);
     
-    match state.add_person(name, image_data).await {
-        Ok(response) => Ok(Json(ApiResponse {
-            success: true,
-            data: Some(response),
-            error: None,
-        })),
-        Err(e) => Ok(Json(ApiResponse {
-            success: false,
-            data: None,
-            error: Some(e.to_string()),
-        })),
-    }
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
 }
 
-async fn check_access_handler(
+/// Deletes the pending faces; someone with approved faces keeps those.
+async fn reject_person_handler(
     State(state): State<AppState>,
-    mut multipart: Multipart,
-) -> Result<Json<ApiResponse<AccessCheckResponse>>, StatusCode> {
-    let mut image_data = None;
+    Extension(caller): Extension<auth::Caller>,
+    Path(name): Path<String>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if !caller.admin {
+        return Err(ApiError::Forbidden("Only the admin API key or an admin's face session can reject".to_string()));
+    }
     
-    while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
-        if field.name() == Some("photo") {
-            image_data = Some(field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?);
-            break;
-        }
+    let rejected = state
+        .reject_person(&name)
+        .await?
+        .ok_or_else(|| ApiError::PersonNotFound(name.clone()))?;
+    if rejected == 0 {
+        return Err(ApiError::BadRequest(format!("{} has no faces awaiting approval", name)));
     }
     
-    let image_data = image_data.ok_or(StatusCode::BAD_REQUEST)?;
+    let message = format!("🚫 Rejected {} face(s) of {}", rejected, name);
+    state.log_access(format!("{} by {}", message, caller.name), Some(name), None, false);
     
-    match state.recognize_face(image_data).await {
-        Ok(response) => Ok(Json(ApiResponse {
-            success: true,
-            data: Some(response),
-            error: None,
-        })),
-        Err(e) => Ok(Json(ApiResponse {
-            success: false,
-            data: None,
-            error: Some(e.to_string()),
-        })),
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn set_person_role_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Path(name): Path<String>,
+    Json(request): Json<SetRoleRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    // Otherwise any key could make its own face an admin
+    if !caller.admin {
+        return Err(ApiError::Forbidden("Only the admin API key can change roles".to_string()));
+    }
+    
+    if !state.set_person_role(&name, request.role).await? {
+        return Err(ApiError::PersonNotFound(name));
     }
+    
+    let message = match request.role {
+        PersonRole::Admin => format!("✅ {} is now an admin", name),
+        PersonRole::User => format!("✅ {} is now a regular user", name),
+    };
+    state.log_access(format!("🛡️ {} (by {})", message, caller.name), Some(name), None, false);
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
 }
 
-async fn check_access_esp32_handler(
+async fn set_person_threshold_handler(
     State(state): State<AppState>,
-) -> Json<ApiResponse<AccessCheckResponse>> {
-    match state.capture_from_esp32().await {
-        Ok(image_data) => {
-            match state.recognize_face(image_data).await {
-                Ok(response) => Json(ApiResponse {
-                    success: true,
-                    data: Some(response),
-                    error: None,
-                }),
-                Err(e) => Json(ApiResponse {
-                    success: false,
-                    data: None,
-                    error: Some(e.to_string()),
-                }),
-            }
-        }
-        Err(e) => Json(ApiResponse {
-            success: false,
-            data: None,
-            error: Some(format!("ESP32-CAM capture failed: {}", e)),
-        }),
+    Path(name): Path<String>,
+    Json(request): Json<SetThresholdRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if let Some(threshold) = request.threshold {
+        validate_threshold(threshold)?;
+    }
+    
+    if !state.set_person_threshold(&name, request.threshold).await? {
+        return Err(ApiError::PersonNotFound(name));
     }
+    
+    let message = match request.threshold {
+        Some(threshold) => format!("✅ Match threshold for {} set to {:.1}%", name, threshold),
+        None => format!("✅ Match threshold for {} reset to the global default", name),
+    };
+    state.log_access(format!("🎯 {}", message), Some(name), None, false);
+    
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(message),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn unlock_lockout_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+    Query(query): Query<UnlockLockoutQuery>,
+) -> Json<ApiResponse<String>> {
+    state.unlock_lockout(query.source, &caller.name);
+    let scope = query.source.map_or("all sources", |source| source.as_str());
+    Json(ApiResponse {
+        success: true,
+        data: Some(format!("✅ Lockout cleared for {}", scope)),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
+}
+
+async fn lockdown_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+) -> Result<Json<ApiResponse<LockdownStatus>>, ApiError> {
+    let status = state.set_lockdown(true, &caller.name).await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(status),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn clear_lockdown_handler(
+    State(state): State<AppState>,
+    Extension(caller): Extension<auth::Caller>,
+) -> Result<Json<ApiResponse<LockdownStatus>>, ApiError> {
+    let status = state.set_lockdown(false, &caller.name).await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(status),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn lockdown_status_handler(State(state): State<AppState>) -> Json<ApiResponse<LockdownStatus>> {
+    Json(ApiResponse {
+        success: true,
+        data: Some(state.lockdown_status()),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    })
 }
 
-async fn list_people_handler(State(state): State<AppState>) -> Json<ApiResponse<Vec<String>>> {
-    let people = state.get_authorized_people();
+async fn people_handler(State(state): State<AppState>) -> Json<ApiResponse<Vec<AuthorizedPerson>>> {
+    let people = state.get_people_details();
     Json(ApiResponse {
         success: true,
         data: Some(people),
         error: None,
+        error_code: None,
+        request_id: request_id::current(),
     })
 }
 
+async fn stats_handler(State(state): State<AppState>) -> Result<Json<ApiResponse<StatsResponse>>, ApiError> {
+    let stats = state.stats().await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(stats),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+async fn daily_unique_handler(
+    State(state): State<AppState>,
+    Query(query): Query<DailyUniqueQuery>,
+) -> Result<Json<ApiResponse<Vec<DailyUniqueVisitors>>>, ApiError> {
+    let days = state.daily_unique_visitors(query.days.unwrap_or(30)).await?;
+    Ok(Json(ApiResponse {
+        success: true,
+        data: Some(days),
+        error: None,
+        error_code: None,
+        request_id: request_id::current(),
+    }))
+}
+
+/// `LOG_FORMAT=json` emits one JSON object per event, with span context,
+/// for log aggregators; anything else keeps the human-readable format.
+/// Levels come from `RUST_LOG`, defaulting to `info`.
+fn init_tracing() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let json = env::var("LOG_FORMAT")
+        .map(|format| format.eq_ignore_ascii_case("json"))
+        .unwrap_or(false);
+    
+    if json {
+        tracing_subscriber::fmt()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .with_env_filter(filter)
+            .init();
+    } else {
+        tracing_subscriber::fmt().with_env_filter(filter).init();
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+    // Before tracing starts, so LOG_FORMAT/RUST_LOG from .env apply
+    let _ = dotenvy::dotenv();
+    init_tracing();
     
-    let state = AppState::new().await?;
+    let config = config::Config::from_env()?;
+    let (port, http_redirect_port, metrics_port) = (config.port, config.http_redirect_port, config.metrics_port);
+    let tls_settings = config.tls.clone();
+    let state = AppState::new(config).await?;
     
-    let app = Router::new()
-        .route("/", get(dashboard))
+    if state.snapshots_enabled {
+        let pruning_state = state.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+            loop {
+                interval.tick().await;
+                pruning_state.prune_snapshots().await;
+            }
+        });
+    }
+    
+    let pruning_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(LOG_PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            pruning_state.prune_logs().await;
+        }
+    });
+    
+    tokio::spawn(run_polling(state.clone()));
+    tokio::spawn(run_guest_sweeper(state.clone()));
+    
+    let static_dir = env::var("STATIC_DIR").unwrap_or_else(|_| "static".to_string());
+    let tls = match &tls_settings {
+        Some(settings) => Some(tls::load(settings).await?),
+        None => None,
+    };
+    
+    let limits = state.upload_limits;
+    // Routes an admin session mustn't reach, however it was signed in
+    let key_only = middleware::from_fn_with_state(state.clone(), auth::require_api_key_only);
+    
+    // Each of these calls Rekognition, so they share the per-client rate limit
+    let enrollment = Router::new()
         .route("/api/add-person", post(add_person_handler))
+        .route("/api/guests", post(add_guest_handler))
+        .route("/api/people/:name/photos", post(add_person_photos_handler))
+        .route("/api/restore", post(restore_handler).route_layer(key_only.clone()))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_api_key))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ratelimit::limit))
+        .layer(RequestBodyLimitLayer::new(limits.default));
+    
+    // A whole archive of photos, so it gets a larger limit of its own
+    let bulk = Router::new()
+        .route("/api/bulk-enroll", post(bulk_enroll_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_api_key))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ratelimit::limit))
+        .layer(RequestBodyLimitLayer::new(limits.bulk));
+    
+    let recognition = Router::new()
         .route("/api/check-access", post(check_access_handler))
         .route("/api/check-access-esp32", post(check_access_esp32_handler))
+        .route("/api/check-access-2fa", post(check_access_2fa_handler))
+        .route("/api/liveness/start", post(liveness_start_handler))
+        .route("/api/liveness/verify", post(liveness_verify_handler))
+        .route("/api/admin/verify", post(admin_verify_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ratelimit::limit))
+        .layer(RequestBodyLimitLayer::new(limits.check));
+    
+    // Cameras that can reach us but not the other way round push frames here
+    let mut ingest = Router::new()
+        .route("/api/ingest", post(ingest_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_device_token));
+    if tls.as_ref().is_some_and(|tls| tls.client_auth) {
+        ingest = ingest.route_layer(middleware::from_fn(auth::require_client_cert));
+    }
+    let ingest = ingest.layer(RequestBodyLimitLayer::new(limits.check));
+    
+    let protected = Router::new()
+        .route("/api/people/:name", delete(remove_person_handler).patch(rename_person_handler))
+        .route("/api/people/:name/schedule", put(set_person_schedule_handler))
+        .route("/api/people/:name/notify", put(set_person_notify_handler))
+        .route("/api/debug/search", post(debug_search_handler))
+        .route("/api/snapshots/:id/recheck", post(recheck_snapshot_handler))
+        .route("/api/test/door", post(test_door_handler))
+        .route("/api/test/camera", get(test_camera_handler))
+        .route("/api/camera/preview", get(camera_preview_handler))
+        .route("/api/people/:name/pin", put(set_person_pin_handler))
+        .route("/api/people/:name/threshold", put(set_person_threshold_handler))
+        .route("/api/people/:name/role", put(set_person_role_handler).route_layer(key_only.clone()))
+        .route("/api/people/:name/approve", post(approve_person_handler))
+        .route("/api/people/:name/reject", post(reject_person_handler))
+        .route("/api/config/threshold", put(set_global_threshold_handler))
+        .route("/api/door", post(door_handler))
+        .route("/api/unlock-lockout", post(unlock_lockout_handler))
+        .route("/api/polling", post(polling_handler))
+        .route("/api/resync", post(resync_handler))
+        .route("/api/lockdown", post(lockdown_handler))
+        .route("/api/lockdown/clear", post(clear_lockdown_handler))
+        .route("/api/selftest", post(selftest_handler))
+        .route("/api/backup", get(backup_handler).route_layer(key_only))
+        .route(
+            "/api/people/:name/webhook",
+            put(set_person_webhook_handler).delete(clear_person_webhook_handler),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_api_key))
+        .layer(RequestBodyLimitLayer::new(limits.default));
+    
+    let reads = Router::new()
         .route("/api/list-people", get(list_people_handler))
+        .route("/api/people", get(people_handler))
+        .route("/api/logs", get(logs_handler))
+        .route("/api/logs/export", get(export_logs_handler))
+        .route("/api/events", get(events_handler))
+        .route("/api/snapshots/:id", get(snapshot_handler))
+        .route("/api/stats", get(stats_handler))
+        .route("/api/lockdown/status", get(lockdown_status_handler))
+        .route("/api/door", get(door_status_handler))
+        .route("/api/version", get(version_handler))
+        .route("/api/collection", get(collection_handler))
+        .route("/api/stats/daily-unique", get(daily_unique_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_api_key_for_reads));
+    
+    // With METRICS_PORT set these get a listener of their own, meant to be
+    // reachable from the monitoring network only, and leave the public one
+    let observability = Router::new()
+        .route("/health", get(health_handler))
+        .route(
+            "/metrics",
+            get(metrics_handler).route_layer(middleware::from_fn_with_state(
+                state.clone(),
+                auth::require_api_key_for_reads,
+            )),
+        );
+    // Set once on a shutdown signal, so every listener drains together
+    let (shutting_down, mut shutdown_requested) = watch::channel(false);
+    let observability = match metrics_port {
+        Some(metrics_port) => {
+            let listener = tokio::net::TcpListener::bind(("0.0.0.0", metrics_port)).await?;
+            let metrics_app = observability.fallback(error::not_found).with_state(state.clone());
+            info!("📈 Metrics and health on http://localhost:{}", metrics_port);
+            let mut shutdown_requested = shutdown_requested.clone();
+            let shutdown = async move {
+                let _ = shutdown_requested.wait_for(|requested| *requested).await;
+            };
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(listener, metrics_app).with_graceful_shutdown(shutdown).await {
+                    warn!("⚠️ Metrics server stopped: {}", e);
+                }
+            });
+            Router::new()
+        }
+        None => observability,
+    };
+    
+    let request_id_header = header::HeaderName::from_static(request_id::REQUEST_ID_HEADER);
+    // The page is only a shell under PROTECT_READS, and /ws checks the key
+    // itself, so both stay reachable without a header
+    let app = Router::new()
+        .route("/", get(dashboard))
+        .route("/ws", get(ws_handler))
+        .merge(observability)
+        .nest_service("/static", ServeDir::new(static_dir))
+        .merge(enrollment)
+        .merge(bulk)
+        .merge(recognition)
+        .merge(ingest)
+        .merge(protected)
+        .merge(reads)
+        .fallback(error::not_found)
+        .layer(middleware::map_response(error::method_not_allowed))
+        .layer(middleware::map_response(error::payload_too_large))
+        .layer(tower::ServiceBuilder::new()
+            // The per-group RequestBodyLimitLayers replace axum's own 2MB default
+            .layer(DefaultBodyLimit::disable())
+            .layer(state.cors_layer())
+        )
+        // Outermost, so the id exists before anything logs and is echoed
+        // even on rejected requests
         .layer(tower::ServiceBuilder::new()
-            .layer(tower_http::limit::RequestBodyLimitLayer::new(10 * 1024 * 1024)) // 10MB
-            .layer(CorsLayer::permissive())
+            .layer(SetRequestIdLayer::new(request_id_header.clone(), MakeRequestUuid))
+            .layer(TraceLayer::new_for_http().make_span_with(request_id::make_span))
+            .layer(PropagateRequestIdLayer::new(request_id_header))
+            .layer(middleware::from_fn(request_id::scope))
+            .layer(middleware::from_fn_with_state(state.clone(), client_ip::scope))
         )
-        .with_state(state);
+        .with_state(state.clone());
     
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let scheme = if tls.is_some() { "https" } else { "http" };
     
-    info!("🦀 Smart Door Lock server running on http://localhost:3000");
+    info!("🦀 Smart Door Lock server running on {}://localhost:{}", scheme, port);
+    state.version.log_banner();
     info!("🔒 High-performance Rust + AWS Rekognition");
     info!("🔗 ESP32-CAM + Pico 2 integration ready");
     
-    axum::serve(listener, app).await?;
+    let draining = Arc::new(Notify::new());
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("🛑 Shutdown requested - waiting for in-flight requests");
+        shutting_down.send_replace(true);
+    });
+    let shutdown = {
+        let draining = draining.clone();
+        async move {
+            let _ = shutdown_requested.wait_for(|requested| *requested).await;
+            draining.notify_one();
+        }
+    };
+    let server = async {
+        match tls {
+            Some(tls) => {
+                if let Some(redirect_port) = http_redirect_port {
+                    tls::spawn_http_redirect(redirect_port, port);
+                }
+                
+                let handle = axum_server::Handle::new();
+                tokio::spawn({
+                    let handle = handle.clone();
+                    async move {
+                        shutdown.await;
+                        handle.graceful_shutdown(None);
+                    }
+                });
+                axum_server::bind(addr)
+                    .acceptor(tls::ClientCertAcceptor::new(tls.config))
+                    .handle(handle)
+                    .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                    .await?;
+            }
+            None => {
+                let listener = tokio::net::TcpListener::bind(addr).await?;
+                axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                    .with_graceful_shutdown(shutdown)
+                    .await?;
+            }
+        }
+        Ok::<(), anyhow::Error>(())
+    };
+    let drain_deadline = async {
+        draining.notified().await;
+        tokio::time::sleep(SHUTDOWN_GRACE).await;
+    };
+    
+    tokio::select! {
+        result = server => result?,
+        _ = drain_deadline => warn!(
+            "⏱️ Requests still running after {}s - shutting down anyway",
+            SHUTDOWN_GRACE.as_secs()
+        ),
+    }
+    
+    state.shutdown().await;
+    info!("👋 Smart Door Lock server stopped cleanly");
     
     Ok(())
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
\ No newline at end of file
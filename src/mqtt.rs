@@ -0,0 +1,145 @@
+use anyhow::Result;
+use rumqttc::{AsyncClient, Event, EventLoop, MqttOptions, Packet, QoS};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::{AccessLog, AppState, DoorAction};
+
+/// How long to wait before polling again after the broker connection drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Publishing half of the MQTT integration, held in `AppState`.
+#[derive(Debug, Clone)]
+pub(crate) struct Publisher {
+    client: AsyncClient,
+    prefix: String,
+}
+
+impl Publisher {
+    pub(crate) fn access(&self, log_entry: &AccessLog) {
+        match serde_json::to_vec(log_entry) {
+            Ok(payload) => self.publish("access", payload, false),
+            Err(e) => warn!("Failed to serialize MQTT access event: {}", e),
+        }
+    }
+    
+    /// Published to `<prefix>/<door>/state`, where `door` comes from
+    /// `AppState::door_topic`. Retained, so subscribers see the last known
+    /// state on connect.
+    pub(crate) fn door_state(&self, door: String, unlocked: bool) {
+        let state = if unlocked { "unlocked" } else { "locked" };
+        self.publish(&format!("{}/state", door), state.as_bytes().to_vec(), true);
+    }
+    
+    fn publish(&self, topic: &str, payload: Vec<u8>, retain: bool) {
+        let topic = format!("{}/{}", self.prefix, topic);
+        // Never block an access decision on the broker; drop the message instead
+        if let Err(e) = self.client.try_publish(&topic, QoS::AtLeastOnce, retain, payload) {
+            warn!("⚠️ Dropped MQTT message on {}: {}", topic, e);
+        }
+    }
+}
+
+/// Where to connect when `MQTT_ENABLED` is set.
+#[derive(Debug, Clone)]
+pub(crate) struct MqttSettings {
+    pub(crate) broker_url: String,
+    pub(crate) topic_prefix: String,
+}
+
+/// The returned event loop must be driven by [`run`] for anything to be
+/// sent or received.
+pub(crate) fn connect(settings: MqttSettings) -> Result<(Publisher, EventLoop)> {
+    let MqttSettings { broker_url, topic_prefix: prefix } = settings;
+    
+    // rumqttc takes the client id from the URL
+    let url = if broker_url.contains("client_id=") {
+        broker_url
+    } else {
+        let separator = if broker_url.contains('?') { '&' } else { '?' };
+        format!("{}{}client_id=smart-door-{}", broker_url, separator, std::process::id())
+    };
+    let mut options = MqttOptions::parse_url(url)?;
+    options.set_keep_alive(Duration::from_secs(30));
+    
+    let (client, eventloop) = AsyncClient::new(options, 64);
+    info!("📡 MQTT enabled with topic prefix '{}'", prefix);
+    
+    Ok((Publisher { client, prefix }, eventloop))
+}
+
+/// Drives the MQTT connection for the lifetime of the server, resubscribing
+/// to `<prefix>/door/set` (default camera) and `<prefix>/door/<camera>/set`
+/// after every (re)connect and routing lock/unlock commands to the door.
+pub(crate) async fn run(mut eventloop: EventLoop, state: AppState) {
+    let Some(publisher) = state.mqtt.clone() else {
+        return;
+    };
+    let command_topics = [
+        format!("{}/door/set", publisher.prefix),
+        format!("{}/door/+/set", publisher.prefix),
+    ];
+    let door_prefix = format!("{}/door/", publisher.prefix);
+    
+    loop {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                info!("📡 Connected to MQTT broker");
+                for topic in &command_topics {
+                    if let Err(e) = publisher.client.try_subscribe(topic, QoS::AtLeastOnce) {
+                        warn!("Failed to subscribe to {}: {}", topic, e);
+                    }
+                }
+            }
+            Ok(Event::Incoming(Packet::Publish(message))) => {
+                // "set" for the default camera, "<camera>/set" for the others
+                let Some(camera) = message
+                    .topic
+                    .strip_prefix(&door_prefix)
+                    .and_then(|rest| if rest == "set" { Some("") } else { rest.strip_suffix("/set") })
+                    .map(str::to_string)
+                else {
+                    continue;
+                };
+                let state = state.clone();
+                tokio::spawn(async move { handle_command(&state, &camera, &message.payload).await });
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("⚠️ MQTT connection error: {} - reconnecting in {}s", e, RECONNECT_DELAY.as_secs());
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        }
+    }
+}
+
+/// Accepts `lock`/`unlock` as plain text or as a JSON string. An empty
+/// `camera` means the default camera.
+async fn handle_command(state: &AppState, camera: &str, payload: &[u8]) {
+    let command = String::from_utf8_lossy(payload).trim().trim_matches('"').to_lowercase();
+    let action = match command.as_str() {
+        "unlock" => DoorAction::Unlock,
+        "lock" => DoorAction::Lock,
+        other => {
+            warn!("⚠️ Ignoring unknown MQTT door command '{}'", other);
+            return;
+        }
+    };
+    
+    if !state.door_control_enabled {
+        warn!("⚠️ Ignoring MQTT door {} - door control disabled", action.as_str());
+        return;
+    }
+    
+    let camera = match state.camera(Some(camera).filter(|id| !id.is_empty())) {
+        Ok(camera) => camera,
+        Err(e) => {
+            warn!("⚠️ Ignoring MQTT door {}: {}", action.as_str(), e);
+            return;
+        }
+    };
+    
+    if let Err(e) = state.manual_door_control(&camera, action, "mqtt").await {
+        warn!("Failed to {} door '{}' from MQTT: {}", action.as_str(), camera.id, e);
+    }
+}
@@ -0,0 +1,35 @@
+use anyhow::Result;
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+/// Keypad PINs are digits only; short enough to type, long enough that the
+/// lockout makes guessing impractical.
+pub(crate) fn validate(pin: &str) -> std::result::Result<(), String> {
+    if !(4..=12).contains(&pin.len()) || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err("PIN must be 4-12 digits".to_string());
+    }
+    
+    Ok(())
+}
+
+/// Shortest PIN accepted on its own under `AWS_OUTAGE_POLICY=allow_pin`.
+/// Without a face the PIN is tried against everyone who has one, so each
+/// guess has as many chances as there are PIN holders.
+pub(crate) const PIN_ONLY_MIN_LENGTH: usize = 6;
+
+/// Salted Argon2id hash in PHC string format.
+pub(crate) fn hash(pin: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| anyhow::anyhow!("Failed to hash PIN: {}", e))
+}
+
+pub(crate) fn verify(pin: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .map(|parsed| Argon2::default().verify_password(pin.as_bytes(), &parsed).is_ok())
+        .unwrap_or(false)
+}
@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use image::imageops::FilterType;
+use rustface::ImageData;
+use std::{fmt, path::Path, sync::mpsc, thread};
+use tokio::sync::oneshot;
+
+/// Frames are shrunk to at most this width before detection; faces at a
+/// door fill enough of the frame that nothing is lost, and it keeps a
+/// check in the tens of milliseconds on a Raspberry Pi.
+const MAX_WIDTH: u32 = 640;
+
+/// Smallest face looked for, in pixels of the shrunk frame.
+const MIN_FACE_SIZE: u32 = 40;
+
+/// SeetaFace's classifier score cut-off. Lower finds more faces, including
+/// false ones; a false positive only costs one Rekognition call.
+const SCORE_THRESHOLD: f64 = 2.0;
+
+type Job = (Bytes, oneshot::Sender<Result<bool>>);
+
+/// Local face detector run before any Rekognition call, so frames with
+/// nobody in them never cost an AWS request. rustface's detector isn't
+/// `Send`, so it lives on a thread of its own and frames are queued to it.
+#[derive(Clone)]
+pub(crate) struct Prefilter {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl fmt::Debug for Prefilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Prefilter").finish_non_exhaustive()
+    }
+}
+
+impl Prefilter {
+    /// Loads a SeetaFace frontal detection model (`seeta_fd_frontal_v1.0.bin`).
+    pub(crate) fn load(model: &Path) -> Result<Self> {
+        let path = model
+            .to_str()
+            .ok_or_else(|| anyhow!("path is not valid UTF-8"))?
+            .to_string();
+        
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (loaded, load_result) = mpsc::channel::<Result<()>>();
+        thread::Builder::new().name("prefilter".to_string()).spawn(move || {
+            let mut detector = match rustface::create_detector(&path) {
+                Ok(detector) => detector,
+                Err(e) => {
+                    let _ = loaded.send(Err(anyhow!("{}", e)));
+                    return;
+                }
+            };
+            detector.set_min_face_size(MIN_FACE_SIZE);
+            detector.set_score_thresh(SCORE_THRESHOLD);
+            detector.set_pyramid_scale_factor(0.8);
+            detector.set_slide_window_step(4, 4);
+            let _ = loaded.send(Ok(()));
+            
+            // Ends once every Prefilter handle, and so every sender, is gone
+            for (image_data, reply) in queue {
+                let result = decode(&image_data).map(|gray| {
+                    let image = ImageData::new(gray.as_raw(), gray.width(), gray.height());
+                    !detector.detect(&image).is_empty()
+                });
+                let _ = reply.send(result);
+            }
+        })?;
+        load_result
+            .recv()
+            .map_err(|_| anyhow!("prefilter thread exited while loading the model"))??;
+        
+        Ok(Self { jobs })
+    }
+    
+    /// Whether the image shows at least one face. Frames are checked one at
+    /// a time on the detector thread.
+    pub(crate) async fn has_face(&self, image_data: Bytes) -> Result<bool> {
+        let (reply, result) = oneshot::channel();
+        self.jobs
+            .send((image_data, reply))
+            .map_err(|_| anyhow!("prefilter thread has stopped"))?;
+        result.await.map_err(|_| anyhow!("prefilter thread has stopped"))?
+    }
+}
+
+/// Grayscale frame, shrunk to [`MAX_WIDTH`].
+fn decode(image_data: &Bytes) -> Result<image::GrayImage> {
+    let mut frame = image::load_from_memory(image_data)?;
+    if frame.width() > MAX_WIDTH {
+        frame = frame.resize(MAX_WIDTH, u32::MAX, FilterType::Triangle);
+    }
+    Ok(frame.to_luma8())
+}
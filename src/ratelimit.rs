@@ -0,0 +1,122 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+use crate::{client_ip, error::ApiError, AppState};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Tracked clients above which stale windows are pruned.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Where the client address comes from. Behind a reverse proxy the peer is
+/// the proxy itself, so either set `TRUST_PROXY` or name the proxy's
+/// forwarding header here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ClientKey {
+    /// The client address: the TCP peer, or the address the proxy
+    /// reported when `TRUST_PROXY` is set.
+    Peer,
+    /// The last address in `X-Forwarded-For`, the one the proxy appended.
+    ForwardedFor,
+    /// `X-Real-IP`, as set by nginx.
+    RealIp,
+}
+
+impl ClientKey {
+    /// Falls back to the peer address when the header is missing or invalid.
+    fn client_ip(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Option<IpAddr> {
+        match self {
+            ClientKey::Peer => peer,
+            ClientKey::ForwardedFor => client_ip::header_ip(headers, "x-forwarded-for").or(peer),
+            ClientKey::RealIp => client_ip::header_ip(headers, "x-real-ip").or(peer),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    requests: u32,
+}
+
+/// Fixed one-minute window of requests per client IP.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    per_minute: u32,
+    key: ClientKey,
+    windows: Mutex<HashMap<IpAddr, Window>>,
+}
+
+impl RateLimiter {
+    /// `None` when `per_minute` is 0, which turns rate limiting off.
+    pub(crate) fn new(per_minute: u32, key: ClientKey) -> Option<Self> {
+        if per_minute == 0 {
+            return None;
+        }
+        
+        info!("🚦 Rate limiting recognition and enrollment to {}/min per client ({:?})", per_minute, key);
+        
+        Some(Self {
+            per_minute,
+            key,
+            windows: Mutex::new(HashMap::new()),
+        })
+    }
+    
+    /// Counts a request, returning how long to wait when over the limit.
+    fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() > PRUNE_THRESHOLD {
+            windows.retain(|_, window| now.duration_since(window.started) < WINDOW);
+        }
+        
+        let window = windows.entry(ip).or_insert(Window { started: now, requests: 0 });
+        if now.duration_since(window.started) >= WINDOW {
+            *window = Window { started: now, requests: 0 };
+        }
+        
+        if window.requests >= self.per_minute {
+            return Err(WINDOW - now.duration_since(window.started));
+        }
+        window.requests += 1;
+        Ok(())
+    }
+}
+
+/// Answers 429 with `Retry-After` once a client exceeds its per-minute
+/// budget. Requests whose client address can't be determined pass through.
+pub(crate) async fn limit(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(limiter) = &state.rate_limiter else {
+        return next.run(request).await;
+    };
+    
+    let peer = client_ip::current();
+    let Some(ip) = limiter.key.client_ip(request.headers(), peer) else {
+        return next.run(request).await;
+    };
+    
+    match limiter.check(ip) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = wait.as_secs().max(1);
+            warn!("🚦 Rate limit exceeded for {} on {}", ip, request.uri().path());
+            let mut response = ApiError::RateLimited(retry_after).into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
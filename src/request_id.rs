@@ -0,0 +1,64 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+use tower_http::request_id::RequestId;
+use tracing::Span;
+
+/// Set on every request (a UUID unless the caller sent one), echoed on
+/// the response and forwarded to the ESP32-CAM and Pico 2.
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The id of the request being handled; `None` in background tasks such as
+/// polling or the auto-relock timer.
+pub(crate) fn current() -> Option<String> {
+    REQUEST_ID.try_with(String::clone).ok()
+}
+
+/// Runs `work` with `id` as the current request id, for work moved onto
+/// another task.
+pub(crate) async fn with<F: std::future::Future>(id: Option<String>, work: F) -> F::Output {
+    match id {
+        Some(id) => REQUEST_ID.scope(id, work).await,
+        None => work.await,
+    }
+}
+
+/// Makes the id set by `SetRequestIdLayer` available to [`current`] for
+/// the rest of the request.
+pub(crate) async fn scope(request: Request, next: Next) -> Response {
+    let id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(str::to_string);
+    
+    match id {
+        Some(id) => REQUEST_ID.scope(id, next.run(request)).await,
+        None => next.run(request).await,
+    }
+}
+
+/// Span for `TraceLayer`, so every log line of a request carries its id.
+pub(crate) fn make_span<B>(request: &axum::http::Request<B>) -> Span {
+    let id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or("-");
+    tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path()
+    )
+}
+
+/// Forwards the current request id to a device call.
+pub(crate) fn forward(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match current() {
+        Some(id) => request.header(REQUEST_ID_HEADER, id),
+        None => request,
+    }
+}
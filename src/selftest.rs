@@ -0,0 +1,189 @@
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+use crate::{normalize_upload, AppState};
+
+/// Used when `SELFTEST_IMAGE` is unset. It only matches in dry-run mode;
+/// against Rekognition, point `SELFTEST_IMAGE` at a photo of the enrolled
+/// test person.
+const BUNDLED_TEST_IMAGE: &[u8] = include_bytes!("../assets/dry-run-capture.png");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum StepStatus {
+    Passed,
+    Failed,
+    /// Not run because a step it depends on failed.
+    Skipped,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SelfTestStep {
+    name: String,
+    status: StepStatus,
+    detail: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct SelfTestReport {
+    pub(crate) passed: bool,
+    test_person: String,
+    steps: Vec<SelfTestStep>,
+    duration_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct Steps(Vec<SelfTestStep>);
+
+impl Steps {
+    fn record(&mut self, name: &str, result: Result<String, String>) {
+        let (status, detail) = match result {
+            Ok(detail) => (StepStatus::Passed, detail),
+            Err(detail) => (StepStatus::Failed, detail),
+        };
+        self.0.push(SelfTestStep {
+            name: name.to_string(),
+            status,
+            detail,
+        });
+    }
+    
+    fn skip(&mut self, name: &str, detail: &str) {
+        self.0.push(SelfTestStep {
+            name: name.to_string(),
+            status: StepStatus::Skipped,
+            detail: detail.to_string(),
+        });
+    }
+}
+
+/// Walks the test image through every stage of a real check against the
+/// default camera's hardware, but never opens the door and never records
+/// a grant: recognition stages are called one by one rather than through
+/// `recognize_face`, so no access decision is made at all.
+pub(crate) async fn run(state: &AppState) -> SelfTestReport {
+    let started = Instant::now();
+    let camera = state.default_camera();
+    let test_person = state.selftest_person.clone();
+    let mut steps = Steps::default();
+    
+    steps.record(
+        "camera_reachable",
+        if state.check_device(&camera.capture_url).await {
+            Ok(format!("camera '{}' answered at {}", camera.id, camera.capture_url))
+        } else {
+            Err(format!("camera '{}' did not answer at {}", camera.id, camera.capture_url))
+        },
+    );
+    
+    let image = match test_image(state).await {
+        Ok(image) => normalize_upload(image).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e),
+    };
+    let image = match image {
+        Ok(image) => {
+            steps.record("image_decoded", Ok(format!("{} bytes after normalizing", image.len())));
+            Some(image)
+        }
+        Err(e) => {
+            steps.record("image_decoded", Err(e));
+            None
+        }
+    };
+    
+    let detected = match &image {
+        Some(image) => match state.detect_primary_face(image).await {
+            Ok(Some(face)) => {
+                steps.record(
+                    "face_detected",
+                    Ok(format!("face covering {:.0}% of the frame", face.area() * 100.0)),
+                );
+                true
+            }
+            Ok(None) => {
+                steps.record("face_detected", Err("no face found in the test image".to_string()));
+                false
+            }
+            Err(e) => {
+                steps.record("face_detected", Err(e.to_string()));
+                false
+            }
+        },
+        None => {
+            steps.skip("face_detected", "image could not be decoded");
+            false
+        }
+    };
+    
+    let threshold = state.global_threshold();
+    let found = match &image {
+        Some(image) if detected => match state.face_recognizer.search_face(image, threshold).await {
+            Ok(found) => {
+                steps.record("search_executed", Ok(format!("searched at {:.1}% similarity", threshold)));
+                Some(found)
+            }
+            Err(e) => {
+                steps.record("search_executed", Err(e.to_string()));
+                None
+            }
+        },
+        _ => {
+            steps.skip("search_executed", "no face to search with");
+            None
+        }
+    };
+    
+    match found {
+        Some(Some(found)) => {
+            let name = state.person_by_face(&found.face_id).map(|person| person.name);
+            steps.record(
+                "match_found",
+                match name {
+                    Some(name) if name == test_person => {
+                        Ok(format!("matched '{}' at {:.1}%", name, found.similarity))
+                    }
+                    Some(name) => Err(format!("matched '{}' instead of '{}'", name, test_person)),
+                    None => Err(format!("matched face {} which is not tracked locally", found.face_id)),
+                },
+            );
+        }
+        Some(None) => steps.record(
+            "match_found",
+            Err(format!("no match - enroll '{}' with the self-test image", test_person)),
+        ),
+        None => steps.skip("match_found", "search did not run"),
+    }
+    
+    if state.door_control_enabled {
+        steps.record(
+            "door_reachable",
+            if state.check_device(&camera.door_url).await {
+                Ok(format!("door '{}' answered at {} (not unlocked)", camera.id, camera.door_url))
+            } else {
+                Err(format!("door '{}' did not answer at {}", camera.id, camera.door_url))
+            },
+        );
+    } else {
+        steps.skip("door_reachable", "door control is disabled");
+    }
+    
+    let steps = steps.0;
+    SelfTestReport {
+        // Recognition steps are only skipped after a failure, so this is enough
+        passed: steps.iter().all(|step| step.status != StepStatus::Failed),
+        test_person,
+        steps,
+        duration_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+async fn test_image(state: &AppState) -> Result<Bytes, String> {
+    match &state.selftest_image {
+        Some(path) => tokio::fs::read(path)
+            .await
+            .map(Bytes::from)
+            .map_err(|e| format!("could not read SELFTEST_IMAGE {}: {}", path.display(), e)),
+        None => Ok(Bytes::from_static(BUNDLED_TEST_IMAGE)),
+    }
+}
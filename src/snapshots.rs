@@ -0,0 +1,76 @@
+use anyhow::Result;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// A new snapshot id (its file name), derived from the capture timestamp.
+pub(crate) fn new_id(image_data: &Bytes, timestamp: DateTime<Utc>) -> String {
+    let extension = if image_data.starts_with(b"\x89PNG") { "png" } else { "jpg" };
+    format!(
+        "{}-{}.{}",
+        timestamp.format("%Y%m%dT%H%M%S%.3fZ"),
+        &uuid::Uuid::new_v4().simple().to_string()[..8],
+        extension
+    )
+}
+
+/// Writes `image_data` into `dir` under `id`.
+pub(crate) async fn save(dir: &Path, id: &str, image_data: &Bytes) -> Result<()> {
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(dir.join(id), image_data).await?;
+    
+    Ok(())
+}
+
+/// Resolves a snapshot id to a path inside `dir`, rejecting anything that
+/// could escape the directory.
+pub(crate) fn path_for(dir: &Path, id: &str) -> Option<PathBuf> {
+    let valid = !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'));
+    
+    valid.then(|| dir.join(id))
+}
+
+pub(crate) fn content_type(id: &str) -> &'static str {
+    if id.ends_with(".png") {
+        "image/png"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// Deletes snapshots last modified more than `max_age` ago, returning how
+/// many were removed.
+pub(crate) async fn prune(dir: &Path, max_age: Duration) -> Result<usize> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    
+    let now = SystemTime::now();
+    let mut removed = 0;
+    
+    while let Some(entry) = entries.next_entry().await? {
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        
+        let age = now
+            .duration_since(metadata.modified()?)
+            .unwrap_or_default();
+        if age > max_age {
+            tokio::fs::remove_file(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    
+    Ok(removed)
+}
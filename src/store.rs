@@ -0,0 +1,361 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow},
+    Row, SqlitePool,
+};
+use std::str::FromStr;
+
+use crate::{AccessLog, AuthorizedPerson};
+
+/// Grant totals for one person across the whole access log.
+#[derive(Debug, Clone)]
+pub(crate) struct PersonActivity {
+    pub(crate) name: String,
+    pub(crate) grants: i64,
+    pub(crate) last_seen: DateTime<Utc>,
+}
+
+/// Grants and denies within one UTC hour.
+#[derive(Debug, Clone)]
+pub(crate) struct HourlyCounts {
+    pub(crate) hour: DateTime<Utc>,
+    pub(crate) grants: i64,
+    pub(crate) denies: i64,
+}
+
+/// Grants of one person within one UTC hour.
+#[derive(Debug, Clone)]
+pub(crate) struct HourlyVisits {
+    pub(crate) hour: DateTime<Utc>,
+    pub(crate) name: String,
+    pub(crate) grants: i64,
+}
+
+/// SQLite-backed persistence for the access log and authorized people.
+///
+/// The in-memory collections on `AppState` stay the source of truth for
+/// request handling; every mutation is written through to this store so
+/// state survives restarts.
+#[derive(Debug, Clone)]
+pub(crate) struct Store {
+    pool: SqlitePool,
+}
+
+impl Store {
+    pub(crate) async fn open(database_url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await?;
+        
+        sqlx::migrate!("./migrations").run(&pool).await?;
+        
+        Ok(Self { pool })
+    }
+    
+    pub(crate) async fn insert_log(&self, log: &AccessLog) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO access_logs \
+             (timestamp, action, person_name, confidence, access_granted, attributes, deny_reason, snapshot, request_id, client_ip, door_confirmed) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(log.timestamp)
+        .bind(&log.action)
+        .bind(log.person_name.clone())
+        .bind(log.confidence)
+        .bind(log.access_granted)
+        .bind(log.attributes.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(log.deny_reason.as_ref().map(enum_to_text))
+        .bind(log.snapshot.clone())
+        .bind(log.request_id.clone())
+        .bind(log.client_ip.clone())
+        .bind(log.door_confirmed)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Most recent entries first.
+    pub(crate) async fn recent_logs(&self, limit: usize) -> Result<Vec<AccessLog>> {
+        let rows = sqlx::query(
+            "SELECT timestamp, action, person_name, confidence, access_granted, attributes, deny_reason, snapshot, request_id, client_ip, door_confirmed \
+             FROM access_logs ORDER BY id DESC LIMIT ?",
+        )
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter().map(log_from_row).collect()
+    }
+    
+    /// Deletes entries older than `cutoff`, then all but the newest
+    /// `max_entries`, returning how many rows went.
+    pub(crate) async fn prune_logs(&self, max_entries: usize, cutoff: Option<DateTime<Utc>>) -> Result<u64> {
+        let mut deleted = 0;
+        if let Some(cutoff) = cutoff {
+            deleted += sqlx::query("DELETE FROM access_logs WHERE julianday(timestamp) < julianday(?)")
+                .bind(cutoff)
+                .execute(&self.pool)
+                .await?
+                .rows_affected();
+        }
+        deleted += sqlx::query(
+            "DELETE FROM access_logs WHERE id <= \
+             (SELECT id FROM access_logs ORDER BY id DESC LIMIT 1 OFFSET ?)",
+        )
+        .bind(max_entries as i64)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        
+        Ok(deleted)
+    }
+    
+    /// Up to `limit` entries with an id above `after_id`, oldest first, with
+    /// their ids so callers can page through the whole log.
+    pub(crate) async fn logs_after(&self, after_id: i64, limit: i64) -> Result<Vec<(i64, AccessLog)>> {
+        let rows = sqlx::query(
+            "SELECT id, timestamp, action, person_name, confidence, access_granted, attributes, deny_reason, snapshot, request_id, client_ip, \
+             door_confirmed \
+             FROM access_logs WHERE id > ? ORDER BY id LIMIT ?",
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter()
+            .map(|row| Ok((row.try_get("id")?, log_from_row(row)?)))
+            .collect()
+    }
+    
+    /// Grant count and most recent grant per person, busiest first.
+    pub(crate) async fn person_activity(&self) -> Result<Vec<PersonActivity>> {
+        let rows = sqlx::query(
+            "SELECT person_name, COUNT(*) AS grants, MAX(timestamp) AS last_seen \
+             FROM access_logs WHERE access_granted AND person_name IS NOT NULL \
+             GROUP BY person_name ORDER BY grants DESC, person_name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter()
+            .map(|row| {
+                Ok(PersonActivity {
+                    name: row.try_get("person_name")?,
+                    grants: row.try_get("grants")?,
+                    last_seen: row.try_get("last_seen")?,
+                })
+            })
+            .collect()
+    }
+    
+    /// Access decisions bucketed by UTC hour, oldest first. Administrative
+    /// entries (no grant, no deny reason) are left out. Hourly buckets let
+    /// callers regroup by local day or hour of day in any timezone.
+    pub(crate) async fn hourly_counts(&self) -> Result<Vec<HourlyCounts>> {
+        let rows = sqlx::query(
+            "SELECT strftime('%Y-%m-%dT%H:00:00Z', timestamp) AS hour, \
+             SUM(access_granted) AS grants, \
+             SUM(NOT access_granted) AS denies \
+             FROM access_logs WHERE access_granted OR deny_reason IS NOT NULL \
+             GROUP BY hour ORDER BY hour",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter()
+            .map(|row| {
+                Ok(HourlyCounts {
+                    hour: row.try_get("hour")?,
+                    grants: row.try_get("grants")?,
+                    denies: row.try_get("denies")?,
+                })
+            })
+            .collect()
+    }
+    
+    /// Grants since `since`, bucketed by UTC hour and person, oldest first,
+    /// so callers can count distinct visitors per local day.
+    pub(crate) async fn hourly_visits(&self, since: DateTime<Utc>) -> Result<Vec<HourlyVisits>> {
+        let rows = sqlx::query(
+            "SELECT strftime('%Y-%m-%dT%H:00:00Z', timestamp) AS hour, person_name, COUNT(*) AS grants \
+             FROM access_logs \
+             WHERE access_granted AND person_name IS NOT NULL AND julianday(timestamp) >= julianday(?) \
+             GROUP BY hour, person_name ORDER BY hour",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter()
+            .map(|row| {
+                Ok(HourlyVisits {
+                    hour: row.try_get("hour")?,
+                    name: row.try_get("person_name")?,
+                    grants: row.try_get("grants")?,
+                })
+            })
+            .collect()
+    }
+    
+    pub(crate) async fn upsert_person(&self, person: &AuthorizedPerson) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO authorized_people \
+             (face_id, name, external_image_id, added_at, enrolled_via, on_grant_webhook, notify_email, schedule, pin_hash, match_threshold, expires_at, access_count, last_seen, role, status, enrolled_by) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT(face_id) DO UPDATE SET \
+             name = excluded.name, \
+             external_image_id = excluded.external_image_id, \
+             added_at = excluded.added_at, \
+             enrolled_via = excluded.enrolled_via, \
+             on_grant_webhook = excluded.on_grant_webhook, \
+             notify_email = excluded.notify_email, \
+             schedule = excluded.schedule, \
+             pin_hash = excluded.pin_hash, \
+             match_threshold = excluded.match_threshold, \
+             expires_at = excluded.expires_at, \
+             access_count = excluded.access_count, \
+             last_seen = excluded.last_seen, \
+             role = excluded.role, \
+             status = excluded.status, \
+             enrolled_by = excluded.enrolled_by",
+        )
+        .bind(&person.face_id)
+        .bind(&person.name)
+        .bind(&person.external_image_id)
+        .bind(person.added_at)
+        .bind(enum_to_text(&person.enrolled_via))
+        .bind(person.on_grant_webhook.clone())
+        .bind(person.notify_email.clone())
+        .bind(person.schedule.as_ref().map(serde_json::to_string).transpose()?)
+        .bind(person.pin_hash.clone())
+        .bind(person.match_threshold)
+        .bind(person.expires_at)
+        .bind(person.access_count as i64)
+        .bind(person.last_seen)
+        .bind(enum_to_text(&person.role))
+        .bind(enum_to_text(&person.status))
+        .bind(person.enrolled_by.clone())
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    pub(crate) async fn delete_person(&self, face_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM authorized_people WHERE face_id = ?")
+            .bind(face_id)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(())
+    }
+    
+    /// A JSON value stored under `key`, for state that isn't worth a table
+    /// of its own.
+    pub(crate) async fn setting<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        let value: Option<String> = sqlx::query_scalar("SELECT value FROM settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        
+        Ok(value.map(|json| serde_json::from_str(&json)).transpose()?)
+    }
+    
+    /// Stores `value` under `key`, or removes the key for `None`.
+    pub(crate) async fn set_setting<T: Serialize>(&self, key: &str, value: Option<&T>) -> Result<()> {
+        match value {
+            Some(value) => {
+                sqlx::query(
+                    "INSERT INTO settings (key, value) VALUES (?, ?) \
+                     ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                )
+                .bind(key)
+                .bind(serde_json::to_string(value)?)
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM settings WHERE key = ?")
+                    .bind(key)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        
+        Ok(())
+    }
+    
+    pub(crate) async fn all_people(&self) -> Result<Vec<AuthorizedPerson>> {
+        let rows = sqlx::query(
+            "SELECT face_id, name, external_image_id, added_at, enrolled_via, on_grant_webhook, notify_email, schedule, pin_hash, match_threshold, expires_at, access_count, last_seen, role, status, enrolled_by \
+             FROM authorized_people ORDER BY added_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter().map(person_from_row).collect()
+    }
+}
+
+fn log_from_row(row: &SqliteRow) -> Result<AccessLog> {
+    let attributes: Option<String> = row.try_get("attributes")?;
+    let deny_reason: Option<String> = row.try_get("deny_reason")?;
+    
+    Ok(AccessLog {
+        timestamp: row.try_get("timestamp")?,
+        action: row.try_get("action")?,
+        person_name: row.try_get("person_name")?,
+        confidence: row.try_get("confidence")?,
+        access_granted: row.try_get("access_granted")?,
+        attributes: attributes.and_then(|json| serde_json::from_str(&json).ok()),
+        deny_reason: deny_reason.and_then(|code| enum_from_text(&code)),
+        snapshot: row.try_get("snapshot")?,
+        request_id: row.try_get("request_id")?,
+        client_ip: row.try_get("client_ip")?,
+        door_confirmed: row.try_get("door_confirmed")?,
+    })
+}
+
+fn person_from_row(row: &SqliteRow) -> Result<AuthorizedPerson> {
+    let enrolled_via: String = row.try_get("enrolled_via")?;
+    let schedule: Option<String> = row.try_get("schedule")?;
+    let role: String = row.try_get("role")?;
+    let status: String = row.try_get("status")?;
+    
+    Ok(AuthorizedPerson {
+        name: row.try_get("name")?,
+        face_id: row.try_get("face_id")?,
+        external_image_id: row.try_get("external_image_id")?,
+        added_at: row.try_get("added_at")?,
+        enrolled_via: enum_from_text(&enrolled_via).unwrap_or_default(),
+        on_grant_webhook: row.try_get("on_grant_webhook")?,
+        notify_email: row.try_get("notify_email")?,
+        schedule: schedule.and_then(|json| serde_json::from_str(&json).ok()),
+        pin_hash: row.try_get("pin_hash")?,
+        match_threshold: row.try_get("match_threshold")?,
+        expires_at: row.try_get("expires_at")?,
+        access_count: row.try_get::<i64, _>("access_count")?.max(0) as u64,
+        last_seen: row.try_get("last_seen")?,
+        role: enum_from_text(&role).unwrap_or_default(),
+        status: enum_from_text(&status).unwrap_or_default(),
+        enrolled_by: row.try_get("enrolled_by")?,
+    })
+}
+
+/// Stores a unit-variant enum as its serde name (e.g. `not_recognized`).
+fn enum_to_text<T: Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn enum_from_text<T: DeserializeOwned>(text: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(text.to_string())).ok()
+}
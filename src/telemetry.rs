@@ -0,0 +1,74 @@
+use anyhow::Result;
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::time::Duration;
+
+use crate::{AccessSource, DenyReason};
+
+/// Bucket bounds, in seconds, shared by the latency histograms. Rekognition
+/// usually answers well under a second; slow ESP32 captures sit in the tail.
+const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Installs the global Prometheus recorder. Must only be called once per
+/// process; the returned handle renders the `/metrics` body.
+pub(crate) fn install() -> Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), LATENCY_BUCKETS)?
+        .install_recorder()?;
+    
+    describe_counter!("access_granted_total", "Recognition attempts that unlocked the door");
+    describe_counter!("access_denied_total", "Recognition attempts that were denied");
+    describe_gauge!("people_enrolled", "Distinct people in the authorized registry");
+    describe_gauge!("daily_unique_visitors", "Distinct people granted entry so far today, in ACCESS_TIMEZONE");
+    describe_histogram!("rekognition_search_seconds", Unit::Seconds, "SearchFacesByImage latency");
+    describe_counter!("rekognition_throttled_total", "Rekognition calls AWS rejected as throttled");
+    describe_histogram!("esp32_capture_seconds", Unit::Seconds, "ESP32-CAM capture latency");
+    describe_counter!("door_commands_total", "Commands sent to the Pico 2 door controller");
+    describe_counter!("recognition_abandoned_total", "Recognitions dropped because the client disconnected");
+    describe_counter!("recognition_prefiltered_total", "Frames the local pre-filter kept from Rekognition");
+    
+    Ok(handle)
+}
+
+pub(crate) fn record_decision(source: AccessSource, granted: bool, reason: Option<DenyReason>) {
+    if granted {
+        counter!("access_granted_total", "source" => source.as_str()).increment(1);
+    } else {
+        let reason = reason.map_or("unknown", |reason| reason.code());
+        counter!("access_denied_total", "source" => source.as_str(), "reason" => reason).increment(1);
+    }
+}
+
+pub(crate) fn record_abandoned(source: AccessSource) {
+    counter!("recognition_abandoned_total", "source" => source.as_str()).increment(1);
+}
+
+pub(crate) fn record_prefiltered(source: AccessSource) {
+    counter!("recognition_prefiltered_total", "source" => source.as_str()).increment(1);
+}
+
+pub(crate) fn set_people_enrolled(count: usize) {
+    gauge!("people_enrolled").set(count as f64);
+}
+
+pub(crate) fn set_daily_unique_visitors(count: usize) {
+    gauge!("daily_unique_visitors").set(count as f64);
+}
+
+pub(crate) fn observe_rekognition_search(source: AccessSource, elapsed: Duration) {
+    histogram!("rekognition_search_seconds", "source" => source.as_str()).record(elapsed.as_secs_f64());
+}
+
+pub(crate) fn record_rekognition_throttle(operation: &'static str) {
+    counter!("rekognition_throttled_total", "operation" => operation).increment(1);
+}
+
+pub(crate) fn observe_esp32_capture(elapsed: Duration, ok: bool) {
+    let outcome = if ok { "success" } else { "failure" };
+    histogram!("esp32_capture_seconds", "outcome" => outcome).record(elapsed.as_secs_f64());
+}
+
+pub(crate) fn record_door_command(action: &'static str, ok: bool) {
+    let outcome = if ok { "success" } else { "failure" };
+    counter!("door_commands_total", "action" => action, "outcome" => outcome).increment(1);
+}
@@ -0,0 +1,195 @@
+use anyhow::Result;
+use axum::{
+    extract::Host,
+    http::Uri,
+    middleware::AddExtension,
+    response::Redirect,
+    Extension, Router,
+};
+use axum_server::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
+use futures::future::BoxFuture;
+use rustls::{server::WebPkiClientVerifier, RootCertStore, ServerConfig};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    sync::Arc,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tower::Layer;
+use tracing::{info, warn};
+
+/// PEM files for HTTPS, from `TLS_CERT_PATH` and `TLS_KEY_PATH`, which are
+/// set together or not at all.
+#[derive(Debug, Clone)]
+pub(crate) struct TlsSettings {
+    pub(crate) cert_path: String,
+    pub(crate) key_path: String,
+    /// `TLS_CLIENT_CA_PATH`: device routes need a client certificate
+    /// signed by this CA.
+    pub(crate) client_ca_path: Option<String>,
+}
+
+/// The HTTPS listener's settings.
+pub(crate) struct ServerTls {
+    pub(crate) config: RustlsConfig,
+    /// `TLS_CLIENT_CA_PATH` is set: device routes need a client
+    /// certificate signed by that CA.
+    pub(crate) client_auth: bool,
+}
+
+/// Loads the certificate chain and private key in `settings`. Both must be
+/// PEM files: the certificate file holds one or more
+/// `-----BEGIN CERTIFICATE-----` blocks (leaf first), the key file a single
+/// PKCS#8, PKCS#1 (RSA) or SEC1 (EC) private key. Unreadable files are an
+/// error so a misconfigured server never silently falls back to HTTP. A
+/// client CA additionally asks clients for a certificate.
+pub(crate) async fn load(settings: &TlsSettings) -> Result<ServerTls> {
+    let TlsSettings { cert_path, key_path, client_ca_path } = settings;
+    
+    match client_ca_path {
+        None => {
+            let config = RustlsConfig::from_pem_file(cert_path, key_path)
+                .await
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Failed to load TLS certificate '{}' / key '{}': {}",
+                        cert_path,
+                        key_path,
+                        e
+                    )
+                })?;
+            info!("🔐 TLS enabled with certificate {}", cert_path);
+            Ok(ServerTls { config, client_auth: false })
+        }
+        Some(ca_path) => {
+            let config = client_auth_config(cert_path, key_path, ca_path)?;
+            info!(
+                "🔐 TLS enabled with certificate {}, device certificates checked against {}",
+                cert_path, ca_path
+            );
+            Ok(ServerTls { config, client_auth: true })
+        }
+    }
+}
+
+/// Server certificate as in [`load`], plus optional client
+/// certificates signed by the CA in `ca_path`. A client without one still
+/// connects, so browsers are unaffected; one with a certificate the CA
+/// didn't sign fails the handshake.
+fn client_auth_config(cert_path: &str, key_path: &str, ca_path: &str) -> Result<RustlsConfig> {
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| anyhow::anyhow!("Failed to open '{}': {}", path, e))
+    };
+    
+    let certs = rustls_pemfile::certs(&mut open(cert_path)?).collect::<io::Result<Vec<_>>>()?;
+    let key = rustls_pemfile::private_key(&mut open(key_path)?)?
+        .ok_or_else(|| anyhow::anyhow!("No private key in '{}'", key_path))?;
+    
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut open(ca_path)?) {
+        roots.add(cert?)?;
+    }
+    if roots.is_empty() {
+        return Err(anyhow::anyhow!("No CA certificate in TLS_CLIENT_CA_PATH '{}'", ca_path));
+    }
+    
+    // Named explicitly: more than one provider is compiled in
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+        .allow_unauthenticated()
+        .build()?;
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    
+    Ok(RustlsConfig::from_config(Arc::new(config)))
+}
+
+/// Whether the connection a request came in on presented a client
+/// certificate. The handshake already rejected any not signed by
+/// `TLS_CLIENT_CA_PATH`, so one that is here is valid.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ClientCert {
+    pub(crate) verified: bool,
+}
+
+/// The HTTPS acceptor, tagging every request with its connection's
+/// [`ClientCert`].
+#[derive(Clone)]
+pub(crate) struct ClientCertAcceptor {
+    inner: RustlsAcceptor,
+}
+
+impl ClientCertAcceptor {
+    pub(crate) fn new(config: RustlsConfig) -> Self {
+        Self {
+            inner: RustlsAcceptor::new(config),
+        }
+    }
+}
+
+impl<I, S> Accept<I, S> for ClientCertAcceptor
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Send + 'static,
+{
+    type Stream = <RustlsAcceptor as Accept<I, S>>::Stream;
+    type Service = AddExtension<S, ClientCert>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+    
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let acceptor = self.inner.clone();
+        Box::pin(async move {
+            let (stream, service) = acceptor.accept(stream, service).await?;
+            let verified = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .is_some_and(|certs| !certs.is_empty());
+            Ok((stream, Extension(ClientCert { verified }).layer(service)))
+        })
+    }
+}
+
+/// Serves plain HTTP on `port`, answering every request with a permanent
+/// redirect to the same path on the HTTPS listener.
+pub(crate) fn spawn_http_redirect(port: u16, https_port: u16) {
+    let app = Router::new().fallback(move |Host(host): Host, uri: Uri| async move {
+        Redirect::permanent(&https_location(&host, https_port, &uri))
+    });
+    
+    tokio::spawn(async move {
+        let listener = match tokio::net::TcpListener::bind(("0.0.0.0", port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("⚠️ Failed to bind HTTP redirect port {}: {}", port, e);
+                return;
+            }
+        };
+        info!("↪️ Redirecting http://*:{} to HTTPS", port);
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!("⚠️ HTTP redirect server stopped: {}", e);
+        }
+    });
+}
+
+fn https_location(host: &str, https_port: u16, uri: &Uri) -> String {
+    let hostname = match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+    
+    if https_port == 443 {
+        format!("https://{}{}", hostname, path)
+    } else {
+        format!("https://{}:{}{}", hostname, https_port, path)
+    }
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+/// Short commit hash from `build.rs`, or `unknown` outside a git checkout.
+const GIT_COMMIT: &str = env!("GIT_COMMIT");
+
+/// Seconds since the epoch, from `build.rs`.
+const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+/// Which optional parts of the server are switched on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Features {
+    pub(crate) dry_run: bool,
+    pub(crate) liveness: bool,
+    pub(crate) mqtt: bool,
+    pub(crate) tls: bool,
+    pub(crate) local_prefilter: bool,
+}
+
+/// `GET /api/version`: enough to tell which build a door controller runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct VersionInfo {
+    version: String,
+    git_commit: String,
+    built_at: Option<DateTime<Utc>>,
+    features: Features,
+}
+
+impl VersionInfo {
+    pub(crate) fn new(features: Features) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: GIT_COMMIT.to_string(),
+            built_at: BUILD_TIMESTAMP
+                .parse()
+                .ok()
+                .and_then(|seconds| DateTime::from_timestamp(seconds, 0)),
+            features,
+        }
+    }
+    
+    pub(crate) fn log_banner(&self) {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        info!(
+            "🏷️ smart-door-aws v{} (commit {}, built {})",
+            self.version,
+            self.git_commit,
+            self.built_at
+                .map_or_else(|| "at an unknown time".to_string(), |built_at| built_at.to_rfc3339())
+        );
+        info!(
+            "🧩 Features: dry-run {}, liveness {}, mqtt {}, tls {}, local pre-filter {}",
+            on_off(self.features.dry_run),
+            on_off(self.features.liveness),
+            on_off(self.features.mqtt),
+            on_off(self.features.tls),
+            on_off(self.features.local_prefilter)
+        );
+    }
+}
@@ -0,0 +1,359 @@
+function authHeaders() {
+    const key = localStorage.getItem('apiKey');
+    return key ? { 'X-API-Key': key } : {};
+}
+
+function errorNote(result) {
+    if (!result.error_code) {
+        return '';
+    }
+    if (result.error_code === 'NO_FACE_IN_IMAGE') {
+        return '\n🙈 No face found in the image';
+    }
+    return `\n⚠️ Door did not open (${result.error_code}) - check the hardware and retry`;
+}
+
+async function addPerson() {
+    const name = document.getElementById('person-name').value;
+    const fileInput = document.getElementById('face-photo');
+    
+    if (!name || !fileInput.files[0]) {
+        alert('Please enter name and select a photo');
+        return;
+    }
+    
+    const formData = new FormData();
+    formData.append('name', name);
+    for (const file of fileInput.files) {
+        formData.append('photo', file);
+    }
+    
+    try {
+        const response = await fetch('/api/add-person', {
+            method: 'POST',
+            headers: authHeaders(),
+            body: formData
+        });
+        
+        if (response.status === 401) {
+            const key = prompt('🔑 This action requires the API key:');
+            if (key) {
+                localStorage.setItem('apiKey', key);
+                return addPerson();
+            }
+            return;
+        }
+        
+        const data = await response.json();
+        
+        if (data.success) {
+            alert('✅ ' + data.data.message);
+            location.reload();
+        } else {
+            alert('❌ Error: ' + data.error);
+        }
+    } catch (error) {
+        alert('❌ Network error: ' + error.message);
+    }
+}
+
+async function checkAccessESP32() {
+    try {
+        const response = await fetch('/api/check-access-esp32', {
+            method: 'POST'
+        });
+        
+        const data = await response.json();
+        
+        if (data.success) {
+            const result = data.data.access_granted ? '🟢 ACCESS GRANTED' : '🔴 ACCESS DENIED';
+            const person = data.data.person_name || 'Unknown';
+            const confidence = data.data.similarity != null ? data.data.similarity.toFixed(1) + '%' : 'N/A';
+            const reason = data.data.deny_reason ? `\nReason: ${data.data.deny_message || data.data.deny_reason}` : '';
+            const retry = data.data.retry_after_seconds ? `\nRetry in ${data.data.retry_after_seconds}s` : '';
+            const door = errorNote(data.data);
+            
+            alert(`${result}\n\nPerson: ${person}\nConfidence: ${confidence}${reason}${retry}${door}`);
+            location.reload();
+        } else {
+            alert('❌ Error: ' + data.error);
+        }
+    } catch (error) {
+        alert('❌ Network error: ' + error.message);
+    }
+}
+
+async function testAccessUpload() {
+    const fileInput = document.getElementById('test-photo');
+    
+    if (!fileInput.files[0]) {
+        alert('Please select a photo to test');
+        return;
+    }
+    
+    const formData = new FormData();
+    formData.append('photo', fileInput.files[0]);
+    
+    try {
+        const response = await fetch('/api/check-access', {
+            method: 'POST',
+            body: formData
+        });
+        
+        const data = await response.json();
+        
+        if (data.success) {
+            const result = data.data.access_granted ? '🟢 ACCESS GRANTED' : '🔴 ACCESS DENIED';
+            const person = data.data.person_name || 'Unknown';
+            const confidence = data.data.similarity != null ? data.data.similarity.toFixed(1) + '%' : 'N/A';
+            const reason = data.data.deny_reason ? `\nReason: ${data.data.deny_message || data.data.deny_reason}` : '';
+            const retry = data.data.retry_after_seconds ? `\nRetry in ${data.data.retry_after_seconds}s` : '';
+            const door = errorNote(data.data);
+            
+            alert(`${result}\n\nPerson: ${person}\nConfidence: ${confidence}${reason}${retry}${door}`);
+            location.reload();
+        } else {
+            alert('❌ Error: ' + data.error);
+        }
+    } catch (error) {
+        alert('❌ Network error: ' + error.message);
+    }
+}
+
+async function listPeople() {
+    try {
+        const response = await fetch('/api/list-people');
+        const data = await response.json();
+        
+        if (data.success && data.data.length > 0) {
+            const people = data.data
+                .map(person => person.status === 'pending'
+                    ? `${person.name} (awaiting approval)`
+                    : person.guest
+                    ? `${person.name} (guest until ${new Date(person.expires_at).toLocaleString()})`
+                    : person.role === 'admin' ? `${person.name} (admin)` : person.name)
+                .join('\n• ');
+            alert(`👥 Authorized People (${data.data.length})::\n\n• ${people}`);
+        } else {
+            alert('👥 No authorized people found\n\nAdd someone using the form above!');
+        }
+    } catch (error) {
+        alert('❌ Network error: ' + error.message);
+    }
+}
+
+async function togglePolling() {
+    const enabled = document.getElementById('polling-state').textContent !== 'ON';
+    
+    try {
+        const response = await fetch('/api/polling', {
+            method: 'POST',
+            headers: { ...authHeaders(), 'Content-Type': 'application/json' },
+            body: JSON.stringify({ enabled })
+        });
+        
+        if (response.status === 401) {
+            const key = prompt('🔑 This action requires the API key:');
+            if (key) {
+                localStorage.setItem('apiKey', key);
+                return togglePolling();
+            }
+            return;
+        }
+        
+        const data = await response.json();
+        
+        if (data.success) {
+            document.getElementById('polling-state').textContent = data.data.enabled ? 'ON' : 'OFF';
+        } else {
+            alert('❌ Error: ' + data.error);
+        }
+    } catch (error) {
+        alert('❌ Network error: ' + error.message);
+    }
+}
+
+async function setLockdown(active) {
+    if (active && !confirm('🚨 Lock every door and deny all access until cleared?')) {
+        return;
+    }
+    
+    try {
+        const response = await fetch(active ? '/api/lockdown' : '/api/lockdown/clear', {
+            method: 'POST',
+            headers: authHeaders()
+        });
+        
+        if (response.status === 401) {
+            const key = prompt('🔑 This action requires the API key:');
+            if (key) {
+                localStorage.setItem('apiKey', key);
+                return setLockdown(active);
+            }
+            return;
+        }
+        
+        const data = await response.json();
+        
+        if (data.success) {
+            location.reload();
+        } else {
+            alert('❌ Error: ' + data.error);
+        }
+    } catch (error) {
+        alert('❌ Network error: ' + error.message);
+    }
+}
+
+async function refreshDoorState() {
+    const labels = { locked: '🔒 Locked', unlocked: '🔓 Unlocked', unknown: '❔ Unknown' };
+    
+    try {
+        const response = await fetch('/api/door', { headers: authHeaders() });
+        const data = await response.json();
+        
+        if (data.success) {
+            document.getElementById('door-state').textContent = labels[data.data.state];
+        }
+    } catch (error) {
+        // Keep showing the last known state; the next event refreshes it
+    }
+}
+
+// Frames are fetched one at a time so the API key header can go along,
+// which an <img> pointing at the MJPEG stream can't send
+let previewTimer = null;
+
+async function refreshPreview() {
+    const preview = document.getElementById('camera-preview');
+    
+    try {
+        const response = await fetch('/api/camera/preview', { headers: authHeaders() });
+        if (response.ok) {
+            const previous = preview.src;
+            preview.src = URL.createObjectURL(await response.blob());
+            if (previous.startsWith('blob:')) {
+                URL.revokeObjectURL(previous);
+            }
+        }
+    } catch (error) {
+        // A dropped frame; the next one may get through
+    }
+    
+    if (previewTimer !== null) {
+        previewTimer = setTimeout(refreshPreview, Number(preview.dataset.interval));
+    }
+}
+
+function togglePreview() {
+    const button = document.getElementById('preview-toggle');
+    
+    if (previewTimer === null) {
+        previewTimer = 0;
+        button.textContent = '⏸️ Stop';
+        refreshPreview();
+    } else {
+        clearTimeout(previewTimer);
+        previewTimer = null;
+        button.textContent = '▶️ Start';
+    }
+}
+
+function renderLogEntry(log) {
+    const entry = document.createElement('div');
+    entry.className = 'log-entry ' + (log.access_granted ? 'access-granted' : 'access-denied');
+    
+    const summary = document.createElement('span');
+    const time = document.createElement('strong');
+    time.textContent = log.timestamp.slice(5, 19).replace('T', ' ');
+    summary.append(time, ' - ' + log.action);
+    
+    const details = document.createElement('span');
+    const confidence = log.confidence ? ` (${Math.round(log.confidence * 100)}%)` : '';
+    const reason = log.deny_reason ? ` [${log.deny_reason}]` : '';
+    details.textContent = confidence + reason;
+    if (log.snapshot) {
+        const link = document.createElement('a');
+        link.href = '/api/snapshots/' + encodeURIComponent(log.snapshot);
+        link.target = '_blank';
+        link.textContent = ' 📷';
+        details.append(link);
+    }
+    
+    entry.append(summary, details);
+    return entry;
+}
+
+function renderMissedNotice(missed) {
+    const notice = document.createElement('div');
+    notice.className = 'log-entry';
+    notice.textContent = `⚠️ ${missed} event(s) missed while the connection was busy - reload for the full log`;
+    return notice;
+}
+
+const readsProtected = document.body.dataset.readsProtected === 'true';
+
+// With PROTECT_READS on the page arrives without data, so the recent log
+// is fetched with the API key instead
+async function loadRecentLogs() {
+    try {
+        const response = await fetch('/api/logs?limit=10', { headers: authHeaders() });
+        
+        if (response.status === 401) {
+            const key = prompt('🔑 The access log requires the API key:');
+            if (key) {
+                localStorage.setItem('apiKey', key);
+                return loadRecentLogs();
+            }
+            return;
+        }
+        
+        const data = await response.json();
+        
+        if (data.success) {
+            document.getElementById('log').replaceChildren(...data.data.map(renderLogEntry));
+        }
+    } catch (error) {
+        // New entries still arrive over the event stream
+    }
+}
+
+function connectEvents() {
+    const protocol = location.protocol === 'https:' ? 'wss' : 'ws';
+    const socket = new WebSocket(`${protocol}://${location.host}/ws`);
+    
+    // A WebSocket can't carry the key header, so it goes in the first message
+    socket.onopen = () => {
+        if (readsProtected) {
+            socket.send(localStorage.getItem('apiKey') || '');
+        }
+    };
+    socket.onmessage = (event) => {
+        const data = JSON.parse(event.data);
+        if (data.missed !== undefined) {
+            document.getElementById('log').prepend(renderMissedNotice(data.missed));
+            return;
+        }
+        document.getElementById('log').prepend(renderLogEntry(data));
+        // Door commands and relocks are logged, so any entry may mean a change
+        refreshDoorState();
+    };
+    socket.onclose = (event) => {
+        // 1008: the key was missing or wrong
+        if (event.code === 1008) {
+            const key = prompt('🔑 The live log requires the API key:');
+            if (!key) {
+                return;
+            }
+            localStorage.setItem('apiKey', key);
+        }
+        setTimeout(connectEvents, 3000);
+    };
+}
+
+if (readsProtected) {
+    loadRecentLogs().then(connectEvents);
+} else {
+    connectEvents();
+}
+refreshDoorState();
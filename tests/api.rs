@@ -0,0 +1,1789 @@
+//! End-to-end tests: each test starts the server binary on a free port
+//! with its own database and drives it over HTTP.
+//!
+//! By default the server runs with `DRY_RUN=true`, so the in-memory face
+//! recognizer stands in for Rekognition: a search matches the first
+//! enrolled person, or nobody when the collection is empty. Set
+//! `SMART_DOOR_TEST_AWS_ENDPOINT` (e.g. `http://localhost:4566`) to run the
+//! same tests against LocalStack instead.
+
+use reqwest::{multipart, Client, StatusCode};
+use serde_json::Value;
+use std::{
+    net::TcpListener,
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    time::Duration,
+};
+
+const API_KEY: &str = "test-key";
+const FACE_IMAGE: &[u8] = include_bytes!("../assets/dry-run-capture.png");
+
+struct TestServer {
+    process: Child,
+    base_url: String,
+    client: Client,
+    dir: PathBuf,
+}
+
+impl TestServer {
+    async fn start() -> Self {
+        Self::start_with(&[]).await
+    }
+    
+    /// Starts with extra environment variables on top of the defaults.
+    /// With `TLS_CERT_PATH` among them the server is reached over HTTPS,
+    /// trusting its test certificate.
+    async fn start_with(env: &[(&str, &str)]) -> Self {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("no free port")
+            .port();
+        let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        
+        let mut command = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"));
+        // A clean environment in a scratch directory, so neither the
+        // developer's shell nor a local .env leaks into the test
+        command
+            .env_clear()
+            .current_dir(&dir)
+            .env("PORT", port.to_string())
+            .env("DATABASE_URL", format!("sqlite://{}", dir.join("test.db").display()))
+            .env("STATIC_DIR", concat!(env!("CARGO_MANIFEST_DIR"), "/static"))
+            .env("API_KEY", API_KEY)
+            .env("RATE_LIMIT_PER_MIN", "0")
+            .env("RUST_LOG", "warn")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        match std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT") {
+            Ok(endpoint) => {
+                command
+                    .env("AWS_ENDPOINT_URL", endpoint)
+                    .env("AWS_ACCESS_KEY_ID", "test")
+                    .env("AWS_SECRET_ACCESS_KEY", "test")
+                    .env("AWS_REGION", "us-east-1")
+                    .env("COLLECTION_ID", format!("test-{}", port))
+                    .env("DOOR_CONTROL_ENABLED", "false");
+            }
+            Err(_) => {
+                command.env("DRY_RUN", "true");
+            }
+        }
+        command.envs(env.iter().copied());
+        
+        let tls = env.iter().any(|(name, _)| *name == "TLS_CERT_PATH");
+        let server = TestServer {
+            process: command.spawn().expect("failed to start server"),
+            base_url: format!("{}://127.0.0.1:{}", if tls { "https" } else { "http" }, port),
+            client: Client::builder().danger_accept_invalid_certs(tls).build().unwrap(),
+            dir,
+        };
+        server.wait_until_ready().await;
+        server
+    }
+    
+    async fn wait_until_ready(&self) {
+        for _ in 0..100 {
+            if self.client.get(self.url("/health")).send().await.is_ok() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        panic!("server did not start within 10s");
+    }
+    
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+    
+    async fn add_person(&self, name: &str) -> (StatusCode, Value) {
+        let form = multipart::Form::new()
+            .text("name", name.to_string())
+            .part("photo", photo());
+        let response = self
+            .client
+            .post(self.url("/api/add-person"))
+            .header("x-api-key", API_KEY)
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        (response.status(), response.json().await.unwrap())
+    }
+    
+    async fn check_access(&self) -> (StatusCode, Value) {
+        let form = multipart::Form::new().part("photo", photo());
+        let response = self
+            .client
+            .post(self.url("/api/check-access"))
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        (response.status(), response.json().await.unwrap())
+    }
+    
+    async fn list_people(&self) -> Vec<String> {
+        let body: Value = self
+            .client
+            .get(self.url("/api/list-people"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        body["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|person| person["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+    
+    /// Full per-face records from `GET /api/people`.
+    async fn people(&self) -> Vec<Value> {
+        let body: Value = self
+            .client
+            .get(self.url("/api/people"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        body["data"].as_array().unwrap().clone()
+    }
+    
+    async fn door_status(&self) -> Value {
+        let body: Value = self
+            .client
+            .get(self.url("/api/door"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        body["data"].clone()
+    }
+    
+    async fn remove_person(&self, name: &str) -> (StatusCode, Value) {
+        let response = self
+            .client
+            .delete(self.url(&format!("/api/people/{}", name)))
+            .header("x-api-key", API_KEY)
+            .send()
+            .await
+            .unwrap();
+        (response.status(), response.json().await.unwrap())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn photo() -> multipart::Part {
+    multipart::Part::bytes(FACE_IMAGE)
+        .file_name("face.png")
+        .mime_str("image/png")
+        .unwrap()
+}
+
+fn assert_success(body: &Value) {
+    assert_eq!(body["success"], true, "unexpected response: {}", body);
+    assert!(body["error"].is_null());
+    assert!(body["error_code"].is_null());
+}
+
+fn assert_error(body: &Value, code: &str) {
+    assert_eq!(body["success"], false, "unexpected response: {}", body);
+    assert!(body["data"].is_null());
+    assert!(body["error"].is_string());
+    assert_eq!(body["error_code"], code);
+}
+
+#[tokio::test]
+async fn check_access_is_denied_with_nobody_enrolled() {
+    let server = TestServer::start().await;
+    
+    let (status, body) = server.check_access().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_success(&body);
+    assert_eq!(body["data"]["access_granted"], false);
+    assert_eq!(body["data"]["deny_reason"], "not_recognized");
+    assert_eq!(body["data"]["deny_message"], "Face not recognized");
+    assert_eq!(body["data"]["door_opened"], false);
+}
+
+#[tokio::test]
+async fn enrolled_person_is_granted_and_listed() {
+    let server = TestServer::start().await;
+    
+    let (status, body) = server.add_person("alice").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_success(&body);
+    assert!(body["data"]["face_id"].is_string());
+    assert_eq!(body["data"]["face_ids"].as_array().unwrap().len(), 1);
+    assert_eq!(body["data"]["photos_skipped"], 0);
+    
+    assert_eq!(server.list_people().await, vec!["alice"]);
+    
+    let (status, body) = server.check_access().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_success(&body);
+    assert_eq!(body["data"]["access_granted"], true);
+    assert_eq!(body["data"]["person_name"], "alice");
+    assert!(body["data"]["deny_reason"].is_null());
+    assert!(body["data"]["face"]["bounding_box"].is_object());
+    assert_eq!(body["data"]["faces_detected"], 1);
+}
+
+#[tokio::test]
+async fn confidence_is_similarity_as_a_fraction() {
+    let server = TestServer::start().await;
+    server.add_person("dave").await;
+    
+    let (_, body) = server.check_access().await;
+    let similarity = body["data"]["similarity"].as_f64().unwrap();
+    let confidence = body["data"]["confidence"].as_f64().unwrap();
+    assert!((0.0..=100.0).contains(&similarity));
+    assert!((confidence - similarity / 100.0).abs() < 1e-6, "{} vs {}", confidence, similarity);
+    
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_err() {
+        // The dry-run recognizer always reports 99%
+        assert!((similarity - 99.0).abs() < 1e-6);
+    }
+}
+
+#[tokio::test]
+async fn removed_person_is_denied() {
+    let server = TestServer::start().await;
+    server.add_person("bob").await;
+    
+    let (status, body) = server.remove_person("bob").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_success(&body);
+    assert_eq!(body["data"]["name"], "bob");
+    assert_eq!(body["data"]["faces_deleted"], 1);
+    
+    assert!(server.list_people().await.is_empty());
+    
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["access_granted"], false);
+    assert_eq!(body["data"]["deny_reason"], "not_recognized");
+}
+
+#[tokio::test]
+async fn removing_an_unknown_person_is_not_found() {
+    let server = TestServer::start().await;
+    
+    let (status, body) = server.remove_person("nobody").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_error(&body, "PERSON_NOT_FOUND");
+}
+
+#[tokio::test]
+async fn add_person_requires_an_api_key() {
+    let server = TestServer::start().await;
+    let form = multipart::Form::new()
+        .text("name", "mallory")
+        .part("photo", photo());
+    
+    let response = server
+        .client
+        .post(server.url("/api/add-person"))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_error(&response.json().await.unwrap(), "UNAUTHORIZED");
+    assert!(server.list_people().await.is_empty());
+}
+
+#[tokio::test]
+async fn multipart_without_a_photo_is_rejected() {
+    let server = TestServer::start().await;
+    
+    let form = multipart::Form::new().text("name", "carol");
+    let response = server
+        .client
+        .post(server.url("/api/add-person"))
+        .header("x-api-key", API_KEY)
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_error(&response.json().await.unwrap(), "BAD_REQUEST");
+    
+    let form = multipart::Form::new().text("note", "no photo here");
+    let response = server
+        .client
+        .post(server.url("/api/check-access"))
+        .multipart(form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    // Unexpected fields are refused before the missing photo is noticed
+    assert_error(&response.json().await.unwrap(), "INVALID_FIELD");
+}
+
+#[tokio::test]
+async fn grants_update_access_count_and_last_seen() {
+    let server = TestServer::start().await;
+    server.add_person("erin").await;
+    
+    let people = server.people().await;
+    assert_eq!(people[0]["access_count"], 0);
+    assert!(people[0]["last_seen"].is_null());
+    
+    for _ in 0..2 {
+        let (_, body) = server.check_access().await;
+        assert_eq!(body["data"]["access_granted"], true);
+    }
+    
+    let people = server.people().await;
+    assert_eq!(people[0]["name"], "erin");
+    assert_eq!(people[0]["access_count"], 2);
+    assert!(people[0]["last_seen"].is_string());
+}
+
+#[tokio::test]
+async fn unknown_route_is_a_json_not_found() {
+    let server = TestServer::start().await;
+    
+    let response = server.client.get(server.url("/api/nope")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_error(&response.json().await.unwrap(), "NOT_FOUND");
+}
+
+#[tokio::test]
+async fn wrong_method_is_a_json_method_not_allowed() {
+    let server = TestServer::start().await;
+    
+    let response = server.client.get(server.url("/api/check-access")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert!(response.headers().contains_key("allow"));
+    assert_error(&response.json().await.unwrap(), "METHOD_NOT_ALLOWED");
+}
+
+#[tokio::test]
+async fn unrecognized_image_format_is_rejected() {
+    let server = TestServer::start().await;
+    
+    let part = multipart::Part::bytes(b"definitely not an image".to_vec())
+        .file_name("face.png")
+        .mime_str("image/png")
+        .unwrap();
+    let response = server
+        .client
+        .post(server.url("/api/check-access"))
+        .multipart(multipart::Form::new().part("photo", part))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_error(&response.json().await.unwrap(), "UNSUPPORTED_IMAGE_FORMAT");
+}
+
+#[tokio::test]
+async fn malformed_form_fields_are_rejected() {
+    let server = TestServer::start().await;
+    
+    let post = |path: &'static str, form: multipart::Form| {
+        server
+            .client
+            .post(server.url(path))
+            .header("x-api-key", API_KEY)
+            .multipart(form)
+            .send()
+    };
+    
+    let text_photo = multipart::Part::bytes(FACE_IMAGE)
+        .file_name("face.txt")
+        .mime_str("text/plain")
+        .unwrap();
+    let response = post("/api/check-access", multipart::Form::new().part("photo", text_photo))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_error(&response.json().await.unwrap(), "INVALID_FIELD");
+    
+    let form = multipart::Form::new()
+        .part("photo", photo())
+        .text("extra", "surprise");
+    let response = post("/api/check-access", form).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = response.json().await.unwrap();
+    assert_error(&body, "INVALID_FIELD");
+    assert!(body["error"].as_str().unwrap().contains("extra"));
+    
+    let (status, body) = server.add_person(&"a".repeat(101)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_error(&body, "INVALID_FIELD");
+    
+    let (status, body) = server.add_person("Mary Jane").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_error(&body, "INVALID_FIELD");
+    
+    let form = multipart::Form::new()
+        .text("name", "alice")
+        .part("photo", photo())
+        .text("nmae", "typo");
+    let response = post("/api/add-person", form).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_error(&response.json().await.unwrap(), "INVALID_FIELD");
+    
+    assert!(!server.list_people().await.contains(&"alice".to_string()));
+}
+
+#[tokio::test]
+async fn responses_carry_the_request_id() {
+    let server = TestServer::start().await;
+    
+    let response = server.client.get(server.url("/api/list-people")).send().await.unwrap();
+    let header = response.headers()["x-request-id"].to_str().unwrap().to_string();
+    let body: Value = response.json().await.unwrap();
+    assert!(uuid::Uuid::parse_str(&header).is_ok(), "{}", header);
+    assert_eq!(body["request_id"], header.as_str());
+    
+    // A caller-supplied id is kept, so a camera can correlate its own logs
+    let response = server
+        .client
+        .post(server.url("/api/check-access"))
+        .header("x-request-id", "cam-42")
+        .multipart(multipart::Form::new().part("photo", photo()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "cam-42");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], "cam-42");
+}
+
+#[tokio::test]
+async fn lockdown_denies_everyone_until_cleared() {
+    let server = TestServer::start().await;
+    server.add_person("frank").await;
+    
+    let response = server
+        .client
+        .post(server.url("/api/lockdown"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["active"], true);
+    
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["access_granted"], false);
+    assert_eq!(body["data"]["deny_reason"], "lockdown");
+    
+    let response = server
+        .client
+        .post(server.url("/api/door"))
+        .header("x-api-key", API_KEY)
+        .json(&serde_json::json!({ "action": "unlock" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_error(&response.json().await.unwrap(), "LOCKDOWN_ACTIVE");
+    
+    let response = server
+        .client
+        .post(server.url("/api/lockdown/clear"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["data"]["active"], false);
+    
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["access_granted"], true);
+}
+
+#[tokio::test]
+async fn manual_unlocks_are_not_counted_as_grants() {
+    let server = TestServer::start().await;
+    
+    let response = server
+        .client
+        .post(server.url("/api/door"))
+        .header("x-api-key", API_KEY)
+        .json(&serde_json::json!({ "action": "unlock" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    let get = |path: &str| {
+        server
+            .client
+            .get(server.url(path))
+            .header("x-api-key", API_KEY)
+            .send()
+    };
+    let logs: Value = get("/api/logs").await.unwrap().json().await.unwrap();
+    let entry = logs["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["action"].as_str().unwrap().contains("Manual override"))
+        .expect("no manual override entry");
+    assert_eq!(entry["access_granted"], false);
+    
+    let stats: Value = get("/api/stats").await.unwrap().json().await.unwrap();
+    assert_success(&stats);
+    assert_eq!(stats["data"]["total_grants"], 0);
+}
+
+#[tokio::test]
+async fn access_events_stream_over_sse() {
+    let server = TestServer::start().await;
+    
+    let mut events = server.client.get(server.url("/api/events")).send().await.unwrap();
+    assert_eq!(events.status(), StatusCode::OK);
+    assert!(events.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/event-stream"));
+    
+    server.check_access().await;
+    
+    let chunk = tokio::time::timeout(Duration::from_secs(5), events.chunk())
+        .await
+        .expect("no event within 5s")
+        .unwrap()
+        .unwrap();
+    let chunk = String::from_utf8_lossy(&chunk);
+    assert!(chunk.starts_with("data:"), "{}", chunk);
+    assert!(chunk.contains("not_recognized"), "{}", chunk);
+}
+
+#[tokio::test]
+async fn collection_stats_compare_with_local_people() {
+    let server = TestServer::start().await;
+    server.add_person("grace").await;
+    
+    let body: Value = server
+        .client
+        .get(server.url("/api/collection"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["face_count"], 1);
+    assert_eq!(body["data"]["local_face_count"], 1);
+    assert_eq!(body["data"]["local_people_count"], 1);
+    assert_eq!(body["data"]["in_sync"], true);
+    assert!(body["data"]["face_model_version"].is_string());
+}
+
+#[test]
+fn invalid_quality_filter_fails_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("QUALITY_FILTER", "extreme")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("QUALITY_FILTER: 'extreme'"), "{}", stderr);
+}
+
+#[test]
+fn invalid_multi_face_policy_fails_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("MULTI_FACE_POLICY", "most")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("MULTI_FACE_POLICY: 'most'"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn selftest_reports_each_step_without_granting() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // The bundled test image only matches with the dry-run recognizer
+        return;
+    }
+    let server = TestServer::start().await;
+    server.add_person("test").await;
+    
+    let response = server
+        .client
+        .post(server.url("/api/selftest"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["passed"], true, "{}", body);
+    let steps: Vec<&str> = body["data"]["steps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|step| step["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        steps,
+        ["camera_reachable", "image_decoded", "face_detected", "search_executed", "match_found", "door_reachable"]
+    );
+    
+    // Not a real grant
+    assert_eq!(server.people().await[0]["access_count"], 0);
+}
+
+#[tokio::test]
+async fn image_without_a_face_is_not_an_access_attempt() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_err() {
+        // The dry-run recognizer sees a face in every image
+        return;
+    }
+    let server = TestServer::start().await;
+    
+    let (status, body) = server.check_access().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_success(&body);
+    assert_eq!(body["data"]["access_granted"], false);
+    assert_eq!(body["data"]["error_code"], "NO_FACE_IN_IMAGE");
+    assert!(body["data"]["deny_reason"].is_null());
+}
+
+#[tokio::test]
+async fn cors_allows_only_configured_origins() {
+    let server = TestServer::start_with(&[("CORS_ALLOWED_ORIGINS", "https://door.example.com")]).await;
+    
+    let preflight = |origin: &'static str| {
+        server
+            .client
+            .request(reqwest::Method::OPTIONS, server.url("/api/people"))
+            .header("origin", origin)
+            .header("access-control-request-method", "GET")
+            .header("access-control-request-headers", "x-api-key")
+            .send()
+    };
+    
+    let allowed = preflight("https://door.example.com").await.unwrap();
+    assert_eq!(allowed.headers()["access-control-allow-origin"], "https://door.example.com");
+    assert_eq!(allowed.headers()["access-control-allow-credentials"], "true");
+    
+    let blocked = preflight("https://evil.example.com").await.unwrap();
+    assert!(blocked.headers().get("access-control-allow-origin").is_none());
+}
+
+#[tokio::test]
+async fn door_state_follows_grants() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Door control is off against LocalStack
+        return;
+    }
+    let server = TestServer::start().await;
+    
+    let status = server.door_status().await;
+    assert_eq!(status["state"], "unknown");
+    assert!(status["since"].is_null());
+    
+    server.add_person("frank").await;
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["door_opened"], true);
+    
+    let status = server.door_status().await;
+    assert_eq!(status["state"], "unlocked");
+    assert!(status["since"].is_string());
+    assert!(status["auto_relock_at"].is_string());
+    assert_eq!(status["reported"], false);
+}
+
+#[tokio::test]
+async fn backup_restores_into_a_new_collection_from_photos() {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    
+    let server = TestServer::start().await;
+    server.add_person("grace").await;
+    let response = server
+        .client
+        .get(server.url("/api/backup"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut backup = response.json::<Value>().await.unwrap()["data"].clone();
+    assert_eq!(backup["schema_version"], 1);
+    assert_eq!(backup["people"][0]["name"], "grace");
+    assert_eq!(backup["people"][0]["faces"].as_array().unwrap().len(), 1);
+    
+    // A fresh server has none of the backed-up faces, so it needs the photos
+    let target = TestServer::start().await;
+    let restore = |backup: Value| {
+        target
+            .client
+            .post(target.url("/api/restore"))
+            .header("x-api-key", API_KEY)
+            .json(&backup)
+            .send()
+    };
+    
+    let body: Value = restore(backup.clone()).await.unwrap().json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["needs_enrollment"][0], "grace");
+    assert!(target.list_people().await.is_empty());
+    
+    backup["people"][0]["photos"] = serde_json::json!([STANDARD.encode(FACE_IMAGE)]);
+    let body: Value = restore(backup).await.unwrap().json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["reenrolled"][0], "grace");
+    assert_eq!(target.list_people().await, vec!["grace"]);
+}
+
+#[tokio::test]
+async fn photos_restored_from_a_backup_wait_for_approval() {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    
+    let source = TestServer::start().await;
+    source.add_person("ida").await;
+    let mut backup = source
+        .client
+        .get(source.url("/api/backup"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json::<Value>()
+        .await
+        .unwrap()["data"]
+        .clone();
+    backup["people"][0]["faces"][0]["status"] = Value::from("active");
+    backup["people"][0]["photos"] = serde_json::json!([STANDARD.encode(FACE_IMAGE)]);
+    
+    // The recorded status belongs to a face this collection never had
+    let target = TestServer::start_with(&[("ENROLL_REQUIRES_APPROVAL", "true")]).await;
+    let body: Value = target
+        .client
+        .post(target.url("/api/restore"))
+        .header("x-api-key", API_KEY)
+        .json(&backup)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["reenrolled"][0], "ida");
+    assert_eq!(target.people().await[0]["status"], "pending");
+}
+
+#[tokio::test]
+async fn only_the_admin_key_restores_a_backup() {
+    let server = TestServer::start_with(&[("API_KEYS", "enroller:enroll-key")]).await;
+    let backup = serde_json::json!({
+        "schema_version": 1,
+        "created_at": "2024-01-01T00:00:00Z",
+        "people": [],
+    });
+    
+    let response = server
+        .client
+        .post(server.url("/api/restore"))
+        .header("x-api-key", "enroll-key")
+        .json(&backup)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_error(&response.json().await.unwrap(), "FORBIDDEN");
+}
+
+#[tokio::test]
+async fn deny_messages_can_be_overridden() {
+    let server = TestServer::start_with(&[("DENY_MESSAGES", r#"{"not_recognized": "Please ring the bell"}"#)]).await;
+    
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["deny_reason"], "not_recognized");
+    assert_eq!(body["data"]["deny_message"], "Please ring the bell");
+    
+    let logs: Value = server
+        .client
+        .get(server.url("/api/logs"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let entry = &logs["data"].as_array().unwrap()[0];
+    assert_eq!(entry["deny_reason"], "not_recognized");
+    assert!(entry["action"].as_str().unwrap().contains("Please ring the bell"));
+}
+
+#[tokio::test]
+async fn ingest_refuses_stale_frames_beyond_the_clock_skew_tolerance() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Needs the dry-run recognizer, which matches every face
+        return;
+    }
+    let server = TestServer::start_with(&[
+        ("DEVICE_TOKEN", "camera-token"),
+        ("CLOCK_SKEW_TOLERANCE_SECONDS", "5"),
+    ])
+    .await;
+    let ingest = |offset: i64| {
+        server
+            .client
+            .post(server.url("/api/ingest"))
+            .header("x-device-token", "camera-token")
+            .header("x-captured-at", (chrono::Utc::now().timestamp() + offset).to_string())
+            .header("content-type", "image/png")
+            .body(FACE_IMAGE)
+            .send()
+    };
+    
+    // A camera clock a little ahead or behind is within the tolerance
+    for offset in [3, -13] {
+        let response = ingest(offset).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_success(&response.json().await.unwrap());
+    }
+    
+    for offset in [-60, 60] {
+        let response = ingest(offset).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_error(&response.json().await.unwrap(), "STALE_FRAME");
+    }
+}
+
+#[tokio::test]
+async fn oversized_upload_is_a_json_payload_too_large() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    
+    let server = TestServer::start_with(&[("MAX_CHECK_UPLOAD_MB", "1")]).await;
+    
+    // Only the headers are sent: the server answers from Content-Length and
+    // may hang up before a real client finished writing a 2MB body
+    let mut stream = tokio::net::TcpStream::connect(server.base_url.trim_start_matches("http://"))
+        .await
+        .unwrap();
+    let request = format!(
+        "POST /api/check-access HTTP/1.1\r\nHost: localhost\r\n\
+         Content-Type: multipart/form-data; boundary=frame\r\nContent-Length: {}\r\n\r\n",
+        2 * 1024 * 1024
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    
+    let mut response = Vec::new();
+    let mut buffer = [0u8; 4096];
+    while !response.ends_with(b"}") {
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buffer))
+            .await
+            .expect("no response within 5s")
+            .unwrap();
+        assert!(read > 0, "connection closed early");
+        response.extend_from_slice(&buffer[..read]);
+    }
+    let response = String::from_utf8(response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 413"), "{}", head);
+    assert_error(&serde_json::from_str(body).unwrap(), "PAYLOAD_TOO_LARGE");
+}
+
+#[tokio::test]
+async fn version_reports_build_and_features() {
+    let server = TestServer::start().await;
+    let body: Value = server
+        .client
+        .get(server.url("/api/version"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_success(&body);
+    
+    let version = &body["data"];
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert!(!version["git_commit"].as_str().unwrap().is_empty());
+    assert!(version["built_at"].is_string());
+    let dry_run = std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_err();
+    assert_eq!(version["features"]["dry_run"], dry_run);
+    assert_eq!(version["features"]["tls"], false);
+}
+
+#[tokio::test]
+async fn person_notify_targets_are_validated() {
+    let server = TestServer::start().await;
+    server.add_person("heidi").await;
+    let put = |name: &str, targets: Value| {
+        server
+            .client
+            .put(server.url(&format!("/api/people/{}/notify", name)))
+            .header("x-api-key", API_KEY)
+            .json(&targets)
+            .send()
+    };
+    
+    let response = put("heidi", serde_json::json!({ "webhook_url": "http://127.0.0.1:9/arrived" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["webhook_url"], "http://127.0.0.1:9/arrived");
+    assert!(body["data"]["email"].is_null());
+    
+    // The webhook can't be reached; the grant must not notice
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["access_granted"], true);
+    
+    let response = put("heidi", serde_json::json!({ "email": "mom@example.com" })).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_error(&response.json().await.unwrap(), "BAD_REQUEST");
+    
+    let response = put("heidi", serde_json::json!({ "webhook_url": "not a url" })).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    
+    let response = put("nobody", serde_json::json!({})).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_error(&response.json().await.unwrap(), "PERSON_NOT_FOUND");
+}
+
+#[tokio::test]
+async fn debug_search_lists_candidates_without_logging() {
+    let server = TestServer::start().await;
+    server.add_person("ivan").await;
+    let logs = |server: &TestServer| {
+        server
+            .client
+            .get(server.url("/api/logs"))
+            .send()
+    };
+    let logs_before: Value = logs(&server).await.unwrap().json().await.unwrap();
+    
+    let search = |key: Option<&str>| {
+        let mut request = server
+            .client
+            .post(server.url("/api/debug/search?max_faces=5"))
+            .multipart(multipart::Form::new().part("photo", photo()));
+        if let Some(key) = key {
+            request = request.header("x-api-key", key);
+        }
+        request.send()
+    };
+    
+    let response = search(None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    
+    let response = search(Some(API_KEY)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["debug"], true);
+    let candidates = body["data"]["candidates"].as_array().unwrap();
+    assert_eq!(candidates[0]["name"], "ivan");
+    assert_eq!(candidates[0]["in_registry"], true);
+    assert_eq!(candidates[0]["meets_threshold"], true);
+    
+    let logs_after: Value = logs(&server).await.unwrap().json().await.unwrap();
+    assert_eq!(logs_before["data"], logs_after["data"]);
+}
+
+#[tokio::test]
+async fn protected_reads_leave_only_the_dashboard_shell_public() {
+    let server = TestServer::start_with(&[("PROTECT_READS", "true")]).await;
+    server.check_access().await;
+    
+    let response = server.client.get(server.url("/")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"data-reads-protected="true""#), "unexpected dashboard: {}", html);
+    assert!(!html.contains("Log Entries"), "dashboard leaked stats: {}", html);
+    assert!(!html.contains("Access DENIED"), "dashboard leaked the log: {}", html);
+    
+    let response = server.client.get(server.url("/api/logs")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn dashboard_shows_log_size_and_retention() {
+    let server = TestServer::start_with(&[("LOG_RETENTION_MAX_ENTRIES", "500")]).await;
+    server.check_access().await;
+    
+    let html = server
+        .client
+        .get(server.url("/"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html.contains("Log Entries (max 500)"), "unexpected dashboard: {}", html);
+}
+
+#[tokio::test]
+async fn devices_can_be_tested_on_their_own() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Needs the dry-run door and camera
+        return;
+    }
+    let server = TestServer::start().await;
+    
+    let response = server
+        .client
+        .post(server.url("/api/test/door?action=unlock"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    
+    let body: Value = server
+        .client
+        .post(server.url("/api/test/door?action=unlock"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["ok"], true);
+    assert_eq!(body["data"]["action"], "unlock");
+    assert!(body["data"]["latency_ms"].is_u64());
+    
+    let body: Value = server
+        .client
+        .get(server.url("/api/test/camera"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["ok"], true);
+    assert_eq!(body["data"]["bytes"], FACE_IMAGE.len());
+    assert!(body["data"]["width"].as_u64().unwrap() > 0);
+    
+    let response = server
+        .client
+        .get(server.url("/api/test/camera?image=true"))
+        .header("x-api-key", API_KEY)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), FACE_IMAGE);
+}
+
+#[tokio::test]
+async fn client_ip_comes_from_proxy_headers_only_when_trusted() {
+    async fn logged_client_ip(server: &TestServer) -> Value {
+        server
+            .client
+            .post(server.url("/api/check-access"))
+            .header("x-forwarded-for", "203.0.113.7, 10.0.0.2")
+            .multipart(multipart::Form::new().part("photo", photo()))
+            .send()
+            .await
+            .unwrap();
+        let logs: Value = server
+            .client
+            .get(server.url("/api/logs"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        logs["data"][0]["client_ip"].clone()
+    }
+    
+    let direct = TestServer::start().await;
+    assert_eq!(logged_client_ip(&direct).await, "127.0.0.1");
+    
+    let proxied = TestServer::start_with(&[("TRUST_PROXY", "true")]).await;
+    assert_eq!(logged_client_ip(&proxied).await, "10.0.0.2");
+}
+
+#[test]
+fn invalid_aws_outage_policy_fails_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("AWS_OUTAGE_POLICY", "allow")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("AWS_OUTAGE_POLICY: 'allow'"), "{}", stderr);
+}
+
+#[test]
+fn missing_prefilter_model_fails_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("LOCAL_PREFILTER", "true")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("LOCAL_PREFILTER_MODEL: 'models/seeta_fd_frontal_v1.0.bin'"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn listener_rate_limit_mqtt_and_key_typos_fail_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("PORT", "abc")
+        .env("RATE_LIMIT_PER_MIN", "3O")
+        .env("MQTT_ENABLED", "yes")
+        .env("API_KEYS", "alice:secret-key,frontdesk")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("PORT: 'abc'"), "{}", stderr);
+    assert!(stderr.contains("RATE_LIMIT_PER_MIN: '3O'"), "{}", stderr);
+    assert!(stderr.contains("MQTT_ENABLED: 'yes'"), "{}", stderr);
+    assert!(stderr.contains("API_KEYS: entry 2 is not name:key"), "{}", stderr);
+    assert!(!stderr.contains("secret-key"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn ingest_needs_a_client_certificate_from_the_device_ca() {
+    // Made by tests/fixtures/tls/generate.sh
+    let fixture = |file: &str| format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), file);
+    let (cert, key, ca) = (fixture("server.pem"), fixture("server-key.pem"), fixture("devices-ca.pem"));
+    let server = TestServer::start_with(&[
+        ("TLS_CERT_PATH", cert.as_str()),
+        ("TLS_KEY_PATH", key.as_str()),
+        ("TLS_CLIENT_CA_PATH", ca.as_str()),
+        ("DEVICE_TOKEN", "camera-token"),
+    ])
+    .await;
+    let client_for = |name: &str| {
+        let cert = std::fs::read(fixture(&format!("{}.pem", name))).unwrap();
+        let key = std::fs::read(fixture(&format!("{}-key.pem", name))).unwrap();
+        Client::builder()
+            .danger_accept_invalid_certs(true)
+            .identity(reqwest::Identity::from_pkcs8_pem(&cert, &key).unwrap())
+            .build()
+            .unwrap()
+    };
+    let ingest = |client: &Client| {
+        client
+            .post(server.url("/api/ingest"))
+            .header("x-device-token", "camera-token")
+            .header("content-type", "image/png")
+            .body(FACE_IMAGE)
+            .send()
+    };
+    
+    // No certificate: the handshake succeeds, the route refuses
+    let response = ingest(&server.client).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_error(&response.json().await.unwrap(), "FORBIDDEN");
+    
+    // Signed by another CA: the handshake itself fails
+    assert!(ingest(&client_for("stranger")).await.is_err());
+    
+    let response = ingest(&client_for("camera")).await.unwrap();
+    assert_ne!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn client_ca_without_server_certificate_fails_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("DATABASE_URL", format!("sqlite://{}", dir.join("test.db").display()))
+        .env("TLS_CLIENT_CA_PATH", "devices-ca.pem")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("TLS_CLIENT_CA_PATH: needs TLS_CERT_PATH and TLS_KEY_PATH"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn malformed_collection_tags_fail_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("COLLECTION_TAGS", "env=prod,owner,aws:team=home")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("COLLECTION_TAGS: 'owner'"), "{}", stderr);
+    assert!(stderr.contains("COLLECTION_TAGS: 'aws:team'"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn camera_preview_serves_frames_only_when_enabled() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Needs the dry-run camera
+        return;
+    }
+    
+    let preview = |server: &TestServer, path: &str| {
+        server
+            .client
+            .get(server.url(path))
+            .header("x-api-key", API_KEY)
+            .send()
+    };
+    
+    let server = TestServer::start().await;
+    let response = preview(&server, "/api/camera/preview").await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_error(&response.json().await.unwrap(), "PREVIEW_DISABLED");
+    
+    let server = TestServer::start_with(&[("PREVIEW_ENABLED", "true"), ("PREVIEW_MAX_FPS", "10")]).await;
+    let response = server.client.get(server.url("/api/camera/preview")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    
+    let response = preview(&server, "/api/camera/preview").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert!(!response.bytes().await.unwrap().is_empty());
+    
+    let mut response = preview(&server, "/api/camera/preview?stream=true").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "multipart/x-mixed-replace; boundary=frame"
+    );
+    let mut received = Vec::new();
+    while !received.windows(4).any(|window| window == b"\r\n\r\n") {
+        received.extend_from_slice(&response.chunk().await.unwrap().expect("stream ended early"));
+    }
+    assert!(received.starts_with(b"--frame\r\nContent-Type: image/png\r\n"));
+}
+
+#[tokio::test]
+async fn add_person_replays_a_repeated_idempotency_key() {
+    let server = TestServer::start().await;
+    
+    let enroll = |name: &'static str, key: &'static str| {
+        let form = multipart::Form::new().text("name", name).part("photo", photo());
+        server
+            .client
+            .post(server.url("/api/add-person"))
+            .header("x-api-key", API_KEY)
+            .header("idempotency-key", key)
+            .multipart(form)
+            .send()
+    };
+    
+    let first = enroll("ivan", "enroll-ivan-1").await.unwrap();
+    assert_eq!(first.status(), StatusCode::OK);
+    assert_eq!(first.headers()["idempotent-replayed"], "false");
+    let first: Value = first.json().await.unwrap();
+    assert_success(&first);
+    
+    let retry = enroll("ivan", "enroll-ivan-1").await.unwrap();
+    assert_eq!(retry.status(), StatusCode::OK);
+    assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    let retry: Value = retry.json().await.unwrap();
+    assert_success(&retry);
+    assert_eq!(retry["data"]["face_ids"], first["data"]["face_ids"]);
+    
+    let collection: Value = server
+        .client
+        .get(server.url("/api/collection"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(collection["data"]["local_face_count"], 1, "{}", collection);
+    
+    let reused = enroll("judy", "enroll-ivan-1").await.unwrap();
+    assert_eq!(reused.status(), StatusCode::BAD_REQUEST);
+    assert_error(&reused.json().await.unwrap(), "BAD_REQUEST");
+}
+
+#[tokio::test]
+async fn close_matches_for_two_people_are_ambiguous() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Needs the dry-run recognizer, which matches every face equally
+        return;
+    }
+    let server = TestServer::start_with(&[("MATCH_MARGIN", "5")]).await;
+    
+    server.add_person("kim").await;
+    let (_, body) = server.check_access().await;
+    assert_success(&body);
+    assert_eq!(body["data"]["access_granted"], true);
+    
+    server.add_person("lee").await;
+    let (_, body) = server.check_access().await;
+    assert_success(&body);
+    assert_eq!(body["data"]["access_granted"], false);
+    assert_eq!(body["data"]["deny_reason"], "ambiguous_match");
+}
+
+#[test]
+fn empty_event_buffer_fails_startup() {
+    let dir = std::env::temp_dir().join(format!("smart-door-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    
+    let output = Command::new(env!("CARGO_BIN_EXE_smart-door-aws"))
+        .env_clear()
+        .current_dir(&dir)
+        .env("DRY_RUN", "true")
+        .env("EVENT_BUFFER", "0")
+        .output()
+        .expect("failed to run server");
+    let _ = std::fs::remove_dir_all(&dir);
+    
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("EVENT_BUFFER: 0 is below"), "{}", stderr);
+}
+
+#[tokio::test]
+async fn door_confirmation_is_reported_on_grants() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Door control is off against LocalStack
+        return;
+    }
+    let server = TestServer::start_with(&[("DOOR_CONFIRM", "true"), ("DOOR_CONFIRM_TIMEOUT_MS", "1000")]).await;
+    
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["access_granted"], false);
+    assert!(body["data"]["door_confirmed"].is_null());
+    
+    server.add_person("quinn").await;
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["door_opened"], true);
+    assert_eq!(body["data"]["door_confirmed"], true);
+    
+    let logs: Value = server
+        .client
+        .get(server.url("/api/logs"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let grant = logs["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|entry| entry["person_name"] == "quinn")
+        .expect("grant was logged");
+    assert_eq!(grant["door_confirmed"], true);
+}
+
+#[tokio::test]
+async fn only_admins_set_roles_and_pins() {
+    let server = TestServer::start_with(&[("API_KEYS", "enroller:enroll-key")]).await;
+    server.add_person("rosa").await;
+    let put = |path: &str, key: &'static str, body: Value| {
+        server
+            .client
+            .put(server.url(path))
+            .header("x-api-key", key)
+            .json(&body)
+            .send()
+    };
+    
+    let response = put("/api/people/rosa/role", "enroll-key", serde_json::json!({ "role": "admin" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_error(&response.json().await.unwrap(), "FORBIDDEN");
+    let response = put("/api/people/rosa/pin", "enroll-key", serde_json::json!({ "pin": "135790" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_error(&response.json().await.unwrap(), "FORBIDDEN");
+    assert_eq!(server.people().await[0]["role"], "user");
+    
+    let response = put("/api/people/rosa/role", API_KEY, serde_json::json!({ "role": "admin" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = put("/api/people/rosa/pin", API_KEY, serde_json::json!({ "pin": "135790" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn admins_can_sign_in_by_face() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Needs the dry-run camera and recognizer, which matches every face
+        return;
+    }
+    let server = TestServer::start().await;
+    
+    let verify = |form: multipart::Form| {
+        let request = server.client.post(server.url("/api/admin/verify")).multipart(form);
+        async move {
+            let response = request.send().await.unwrap();
+            (response.status(), response.json::<Value>().await.unwrap())
+        }
+    };
+    let with_pin = |pin: &str| multipart::Form::new().text("pin", pin.to_string());
+    
+    server.add_person("rosa").await;
+    let response = server
+        .client
+        .put(server.url("/api/people/rosa/pin"))
+        .header("x-api-key", API_KEY)
+        .json(&serde_json::json!({ "pin": "135790" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    for form in [with_pin("135790"), with_pin("135790").part("photo", photo())] {
+        let (status, body) = verify(form).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_error(&body, "NOT_ADMIN");
+    }
+    
+    let body: Value = server
+        .client
+        .put(server.url("/api/people/rosa/role"))
+        .header("x-api-key", API_KEY)
+        .json(&serde_json::json!({ "role": "admin" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_success(&body);
+    assert_eq!(server.people().await[0]["role"], "admin");
+    
+    let (status, body) = verify(with_pin("000000")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_error(&body, "NOT_ADMIN");
+    
+    let (status, body) = verify(with_pin("135790").part("photo", photo())).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["name"], "rosa");
+    
+    let (status, body) = verify(with_pin("135790")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_success(&body);
+    assert_eq!(body["data"]["name"], "rosa");
+    let token = body["data"]["token"].as_str().unwrap().to_string();
+    
+    let remove = |token: String| {
+        server
+            .client
+            .delete(server.url("/api/people/nobody"))
+            .header("x-admin-session", token)
+            .send()
+    };
+    assert_eq!(remove("not-a-session".to_string()).await.unwrap().status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(remove(token.clone()).await.unwrap().status(), StatusCode::NOT_FOUND);
+    
+    let response = server
+        .client
+        .get(server.url("/api/backup"))
+        .header("x-admin-session", token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn admin_sign_in_answers_the_liveness_challenge() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Needs the dry-run recognizer, whose frames never show a blink or turn
+        return;
+    }
+    let server = TestServer::start_with(&[("LIVENESS_CHALLENGE", "true")]).await;
+    let verify = |form: multipart::Form| {
+        let request = server.client.post(server.url("/api/admin/verify")).multipart(form);
+        async move {
+            let response = request.send().await.unwrap();
+            (response.status(), response.json::<Value>().await.unwrap())
+        }
+    };
+    let answer = |session_id: &str| {
+        multipart::Form::new()
+            .text("pin", "135790")
+            .text("session_id", session_id.to_string())
+            .part("frame1", photo())
+            .part("frame2", photo())
+    };
+    
+    let (status, body) = verify(multipart::Form::new().text("pin", "135790").part("photo", photo())).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_error(&body, "BAD_REQUEST");
+    
+    let (status, body) = verify(answer("no-such-session")).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_error(&body, "LIVENESS_SESSION_EXPIRED");
+    
+    let body: Value = server
+        .client
+        .post(server.url("/api/liveness/start"))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let session_id = body["data"]["session_id"].as_str().unwrap().to_string();
+    // Two identical frames show neither a blink nor a turn
+    let (status, body) = verify(answer(&session_id)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_error(&body, "NOT_ADMIN");
+}
+
+#[tokio::test]
+async fn enrollments_wait_for_approval() {
+    if std::env::var("SMART_DOOR_TEST_AWS_ENDPOINT").is_ok() {
+        // Needs the dry-run recognizer, which matches every face
+        return;
+    }
+    let server = TestServer::start_with(&[
+        ("ENROLL_REQUIRES_APPROVAL", "true"),
+        ("API_KEYS", "enroller:enroll-key"),
+    ])
+    .await;
+    
+    let decide = |name: &str, decision: &str, key: &'static str| {
+        server
+            .client
+            .post(server.url(&format!("/api/people/{}/{}", name, decision)))
+            .header("x-api-key", key)
+            .send()
+    };
+    
+    let form = multipart::Form::new().text("name", "sam").part("photo", photo());
+    let body: Value = server
+        .client
+        .post(server.url("/api/add-person"))
+        .header("x-api-key", "enroll-key")
+        .multipart(form)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["pending_approval"], true);
+    assert_eq!(server.people().await[0]["status"], "pending");
+    
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["access_granted"], false);
+    assert_eq!(body["data"]["deny_reason"], "pending_approval");
+    
+    let response = decide("sam", "approve", "enroll-key").await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_error(&response.json().await.unwrap(), "FORBIDDEN");
+    
+    // The admin key approves its own enrollments, or nothing could ever be
+    // approved with only API_KEY set
+    server.add_person("tess").await;
+    let response = decide("tess", "approve", API_KEY).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = decide("tess", "reject", API_KEY).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    
+    let response = decide("sam", "approve", API_KEY).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let (_, body) = server.check_access().await;
+    assert_eq!(body["data"]["access_granted"], true);
+    
+    let response = decide("sam", "reject", API_KEY).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    
+    let response = decide("nobody", "approve", API_KEY).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn snapshots_support_range_requests() {
+    let server = TestServer::start().await;
+    std::fs::create_dir_all(server.dir.join("snapshots")).unwrap();
+    std::fs::write(server.dir.join("snapshots/frame.png"), FACE_IMAGE).unwrap();
+    let get = |id: &str, range: Option<&str>| {
+        let mut request = server
+            .client
+            .get(server.url(&format!("/api/snapshots/{}", id)))
+            .header("x-api-key", API_KEY);
+        if let Some(range) = range {
+            request = request.header("range", range.to_string());
+        }
+        request.send()
+    };
+    
+    let response = get("frame.png", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    assert_eq!(response.bytes().await.unwrap().as_ref(), FACE_IMAGE);
+    
+    let response = get("frame.png", Some("bytes=0-3")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        response.headers()["content-range"],
+        format!("bytes 0-3/{}", FACE_IMAGE.len()).as_str()
+    );
+    assert_eq!(response.bytes().await.unwrap().as_ref(), &FACE_IMAGE[..4]);
+    
+    let response = get("frame.png", Some(&format!("bytes={}-", FACE_IMAGE.len() + 10))).await.unwrap();
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        response.headers()["content-range"],
+        format!("bytes */{}", FACE_IMAGE.len()).as_str()
+    );
+    assert_error(&response.json().await.unwrap(), "RANGE_NOT_SATISFIABLE");
+    
+    let response = get("missing.png", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_error(&response.json().await.unwrap(), "SNAPSHOT_NOT_FOUND");
+    
+    let response = get("..secret", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_error(&response.json().await.unwrap(), "BAD_REQUEST");
+}
+
+#[tokio::test]
+async fn snapshots_can_be_rechecked_without_logging() {
+    let server = TestServer::start().await;
+    std::fs::create_dir_all(server.dir.join("snapshots")).unwrap();
+    std::fs::write(server.dir.join("snapshots/frame.png"), FACE_IMAGE).unwrap();
+    let recheck = |id: &str| {
+        server
+            .client
+            .post(server.url(&format!("/api/snapshots/{}/recheck", id)))
+            .header("x-api-key", API_KEY)
+            .send()
+    };
+    
+    let body: Value = recheck("frame.png").await.unwrap().json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["access_granted"], false);
+    assert_eq!(body["data"]["deny_reason"], "not_recognized");
+    
+    server.add_person("ivan").await;
+    let logs = || server.client.get(server.url("/api/logs")).send();
+    let logs_before: Value = logs().await.unwrap().json().await.unwrap();
+    let body: Value = recheck("frame.png").await.unwrap().json().await.unwrap();
+    assert_success(&body);
+    assert_eq!(body["data"]["access_granted"], true);
+    assert_eq!(body["data"]["person_name"], "ivan");
+    assert_eq!(body["data"]["candidates"][0]["name"], "ivan");
+    let logs_after: Value = logs().await.unwrap().json().await.unwrap();
+    assert_eq!(logs_before["data"], logs_after["data"]);
+    
+    let response = recheck("missing.png").await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_error(&response.json().await.unwrap(), "SNAPSHOT_NOT_FOUND");
+}
+
+#[tokio::test]
+async fn metrics_port_moves_metrics_and_health_off_the_public_port() {
+    let metrics_port = TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("no free port")
+        .port();
+    let server = TestServer::start_with(&[("METRICS_PORT", &metrics_port.to_string())]).await;
+    
+    for path in ["/metrics", "/health"] {
+        let response = server.client.get(server.url(path)).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{} still public", path);
+    }
+    
+    let metrics_url = |path: &str| format!("http://127.0.0.1:{}{}", metrics_port, path);
+    let response = server.client.get(metrics_url("/metrics")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.text().await.unwrap().contains("daily_unique_visitors"));
+    let response = server.client.get(metrics_url("/health")).send().await.unwrap();
+    assert_ne!(response.status(), StatusCode::NOT_FOUND);
+    let response = server.client.get(metrics_url("/api/logs")).send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
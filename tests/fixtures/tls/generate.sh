@@ -0,0 +1,35 @@
+#!/bin/sh
+# Regenerates the certificates used by the client certificate tests in
+# tests/api.rs. They are test-only and valid for 100 years.
+set -e
+cd "$(dirname "$0")"
+DAYS=36500
+
+key() {
+    openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out "$1"
+}
+
+ca() {
+    key "$1-key.pem"
+    openssl req -x509 -new -key "$1-key.pem" -sha256 -days $DAYS -subj "/CN=$2" \
+        -addext "basicConstraints=critical,CA:TRUE" \
+        -addext "keyUsage=critical,keyCertSign,cRLSign" \
+        -out "$1.pem"
+}
+
+leaf() {
+    key "$1-key.pem"
+    openssl req -new -key "$1-key.pem" -subj "/CN=$2" -out "$1.csr"
+    printf '%s\n' "basicConstraints=critical,CA:FALSE" "keyUsage=critical,digitalSignature" \
+        "extendedKeyUsage=$4" "subjectAltName=DNS:$2" > "$1.ext"
+    openssl x509 -req -in "$1.csr" -CA "$3.pem" -CAkey "$3-key.pem" -CAcreateserial \
+        -sha256 -days $DAYS -extfile "$1.ext" -out "$1.pem"
+    rm "$1.csr" "$1.ext"
+}
+
+ca devices-ca "Smart Door test devices CA"
+ca other-ca "Unrelated test CA"
+leaf server localhost devices-ca serverAuth
+leaf camera camera.local devices-ca clientAuth
+leaf stranger stranger.local other-ca clientAuth
+rm -f ./*.srl other-ca-key.pem devices-ca-key.pem